@@ -1,17 +1,34 @@
 use darling::ast::Data;
-use darling::{FromDeriveInput, FromVariant};
+use darling::util::Flag;
+use darling::{Error, FromDeriveInput, FromVariant};
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{Generics, Ident, LitStr};
+use syn::{
+	parse_quote,
+	Attribute,
+	Expr,
+	ExprLit,
+	FnArg,
+	Generics,
+	Ident,
+	ItemTrait,
+	Lit,
+	LitStr,
+	Meta,
+	ReturnType,
+	TraitItem,
+};
 
 #[derive(Debug, FromVariant)]
-#[darling(attributes(action))]
+#[darling(attributes(action), forward_attrs(doc))]
 struct ActionField {
 	ident: Ident,
+	attrs: Vec<Attribute>,
 
-	id: LitStr,
-	title: LitStr,
-	icon: LitStr,
+	id: Option<LitStr>,
+	title: Option<LitStr>,
+	icon: Option<LitStr>,
+	default: Flag,
 }
 
 #[derive(Debug, FromDeriveInput)]
@@ -20,31 +37,170 @@ struct Action {
 	ident: Ident,
 	data: Data<ActionField, ()>,
 	generics: Generics,
+
+	from_str: Flag,
+	display: Flag,
+	case_insensitive: Flag,
+}
+
+/// A variant with all of its fields resolved.
+struct ResolvedField {
+	ident: Ident,
+	id: LitStr,
+	title: LitStr,
+	icon: LitStr,
+	default: bool,
+}
+
+impl ActionField {
+	fn resolve(self, errors: &mut darling::error::Accumulator) -> Option<ResolvedField> {
+		let Self {
+			ident,
+			attrs,
+			id,
+			title,
+			icon,
+			default,
+		} = self;
+		// Fall back to the first line of the doc comment for the title, so that
+		// documented variants don't have to repeat themselves
+		let title = title.or_else(|| doc_summary(&attrs));
+
+		let mut missing = |field: &str, value: Option<LitStr>, hint: &str| {
+			if value.is_none() {
+				errors.push(
+					Error::custom(format!(
+						"action variant `{ident}` is missing `{field}`; add `#[action({field} = \
+						 \"...\")]`{hint}"
+					))
+					.with_span(&ident),
+				);
+			}
+			value
+		};
+		let id = missing("id", id, "");
+		let title = missing("title", title, " or a doc comment");
+		let icon = missing("icon", icon, "");
+
+		Some(ResolvedField {
+			id: id?,
+			title: title?,
+			icon: icon?,
+			ident,
+			default: default.is_present(),
+		})
+	}
+}
+
+/// Returns the first non-empty line of a doc comment, if any.
+fn doc_summary(attrs: &[Attribute]) -> Option<LitStr> {
+	attrs.iter().find_map(|attr| {
+		let Meta::NameValue(meta) = &attr.meta else {
+			return None;
+		};
+		let Expr::Lit(ExprLit {
+			lit: Lit::Str(s), ..
+		}) = &meta.value
+		else {
+			return None;
+		};
+		let line = s.value();
+		let line = line.trim();
+		(!line.is_empty()).then(|| LitStr::new(line, s.span()))
+	})
 }
 
+/// Normalizes an ID for `#[action(case_insensitive)]`, the same way the
+/// generated `from_id` does at runtime.
+fn normalize(id: &str) -> String {
+	id.trim().to_lowercase()
+}
+
+/// Derives `krunner::Action` for an enum with unit variants.
+///
+/// See the documentation of `krunner::Action` for details.
 #[proc_macro_derive(Action, attributes(action))]
 pub fn derive_action(input: TokenStream) -> TokenStream {
-	let (ident, data, generics) = match Action::from_derive_input(&syn::parse_macro_input!(input)) {
-		Ok(Action {
-			ident,
-			data,
-			generics,
-		}) => (ident, data, generics),
+	let Action {
+		ident,
+		data,
+		generics,
+		from_str,
+		display,
+		case_insensitive,
+	} = match Action::from_derive_input(&syn::parse_macro_input!(input)) {
+		Ok(action) => action,
 		Err(e) => return e.write_errors().into(),
 	};
-	let variants = data.take_enum().unwrap();
+
+	let mut errors = Error::accumulator();
+	let variants: Vec<_> = data
+		.take_enum()
+		.unwrap()
+		.into_iter()
+		.filter_map(|v| v.resolve(&mut errors))
+		.collect();
+	let mut defaults = variants.iter().filter(|v| v.default);
+	let default_action = defaults.next().map(|v| &v.ident);
+	for extra in defaults {
+		errors.push(
+			Error::custom("only one action can be marked as `#[action(default)]`")
+				.with_span(&extra.ident),
+		);
+	}
+	let case_insensitive = case_insensitive.is_present();
+	if case_insensitive {
+		// IDs that only differ in case or surrounding whitespace couldn't be
+		// told apart anymore
+		let mut seen = std::collections::HashMap::new();
+		for v in &variants {
+			let normalized = normalize(&v.id.value());
+			if let Some(other) = seen.insert(normalized, &v.ident) {
+				errors.push(
+					Error::custom(format!(
+						"action variant `{}` has the same ID as `{other}` when ignoring case",
+						v.ident
+					))
+					.with_span(&v.id),
+				);
+			}
+		}
+	}
+	if let Err(e) = errors.finish() {
+		return e.write_errors().into();
+	}
+	let default_action = default_action.map(|ident| {
+		quote! {
+			fn default_action() -> ::std::option::Option<Self> {
+				::std::option::Option::Some(Self::#ident)
+			}
+		}
+	});
 
 	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
 	let variant_ids = variants.iter().map(|v| &v.ident);
-	let from_ids = variants.iter().map(|ActionField { id, ident, .. }| {
+	let from_ids = variants.iter().map(|ResolvedField { id, ident, .. }| {
+		let id = if case_insensitive {
+			LitStr::new(&normalize(&id.value()), id.span())
+		} else {
+			id.clone()
+		};
 		quote! { #id => ::std::option::Option::Some(Self::#ident), }
 	});
-	let to_ids = variants.iter().map(|ActionField { id, ident, .. }| {
-		quote! { Self::#ident => #id, }
-	});
+	let matched_id = if case_insensitive {
+		quote! { &*::std::primitive::str::to_lowercase(::std::primitive::str::trim(s)) }
+	} else {
+		quote! { s }
+	};
+	let to_ids: Vec<_> = variants
+		.iter()
+		.map(|ResolvedField { id, ident, .. }| {
+			quote! { Self::#ident => #id, }
+		})
+		.collect();
 	let infos = variants.iter().map(
-		|ActionField {
+		|ResolvedField {
 		     ident, title, icon, ..
 		 }| {
 			quote! {
@@ -56,6 +212,33 @@ pub fn derive_action(input: TokenStream) -> TokenStream {
 		},
 	);
 
+	let from_str = from_str.is_present().then(|| {
+		quote! {
+			#[automatically_derived]
+			impl #impl_generics ::std::str::FromStr for #ident #ty_generics #where_clause {
+				type Err = ::krunner::InvalidActionId;
+
+				fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+					<Self as ::krunner::Action>::from_id(s).ok_or_else(|| {
+						::krunner::InvalidActionId(::std::string::String::from(s))
+					})
+				}
+			}
+		}
+	});
+	let display = display.is_present().then(|| {
+		quote! {
+			#[automatically_derived]
+			impl #impl_generics ::std::fmt::Display for #ident #ty_generics #where_clause {
+				fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+					f.write_str(match self {
+						#(#to_ids)*
+					})
+				}
+			}
+		}
+	});
+
 	quote! {
 		#[automatically_derived]
 		impl #impl_generics ::krunner::Action for #ident #ty_generics #where_clause {
@@ -63,7 +246,7 @@ pub fn derive_action(input: TokenStream) -> TokenStream {
 				&[#(Self::#variant_ids),*]
 			}
 			fn from_id(s: &str) -> ::std::option::Option<Self> {
-				match s {
+				match #matched_id {
 					#(#from_ids)*
 					_ => ::std::option::Option::None,
 				}
@@ -78,7 +261,61 @@ pub fn derive_action(input: TokenStream) -> TokenStream {
 					#(#infos)*
 				}
 			}
+			#default_action
 		}
+		#from_str
+		#display
 	}
 	.into()
 }
+
+/// Turns the `async fn`s of a trait into functions returning `Send` futures,
+/// without boxing them.
+///
+/// Used for `krunner::AsyncRunner` with the `native-async` feature. Default
+/// method bodies become `async move` blocks, bounded on `Self: Send` (or
+/// `Sync`, for `&self`) so that they can capture `self`, like `async_trait`
+/// does.
+#[doc(hidden)]
+#[proc_macro_attribute]
+pub fn send_futures(_attr: TokenStream, item: TokenStream) -> TokenStream {
+	let mut item = syn::parse_macro_input!(item as ItemTrait);
+
+	for trait_item in &mut item.items {
+		let TraitItem::Fn(f) = trait_item else {
+			continue;
+		};
+		if f.sig.asyncness.take().is_none() {
+			continue;
+		}
+		let output = match &f.sig.output {
+			ReturnType::Default => quote! { () },
+			ReturnType::Type(_, ty) => quote! { #ty },
+		};
+		f.sig.output = parse_quote! {
+			-> impl ::std::future::Future<Output = #output> + ::std::marker::Send
+		};
+
+		let Some(body) = &mut f.default else {
+			continue;
+		};
+		let bound = match f.sig.inputs.first() {
+			Some(FnArg::Receiver(r)) if r.mutability.is_some() => Some(quote! { Send }),
+			Some(FnArg::Receiver(_)) => Some(quote! { Sync }),
+			_ => None,
+		};
+		if let Some(bound) = bound {
+			f.sig
+				.generics
+				.make_where_clause()
+				.predicates
+				.push(parse_quote! { Self: ::std::marker::#bound });
+		}
+		let stmts = &body.stmts;
+		*body = parse_quote! {{
+			async move { #(#stmts)* }
+		}};
+	}
+
+	quote! { #item }.into()
+}
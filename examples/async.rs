@@ -0,0 +1,71 @@
+// An asynchronous runner that looks up the addresses of host names, like
+// `host kde.org`.
+//
+// Requires the `tokio` feature.
+
+use std::time::Duration;
+
+use krunner::{AsyncRunner, AsyncRunnerExt, Config, Match, MatchFilter, RunnerContext};
+
+struct Host;
+
+#[cfg_attr(not(feature = "native-async"), async_trait::async_trait)]
+impl AsyncRunner for Host {
+	type Action = std::convert::Infallible;
+	type Err = std::io::Error;
+
+	async fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		let host = query.trim();
+		if host.is_empty() || host.contains(char::is_whitespace) {
+			return Ok(vec![]);
+		}
+		// Names that don't resolve are simply not matched
+		let Ok(addrs) = tokio::net::lookup_host((host, 0)).await else {
+			return Ok(vec![]);
+		};
+		Ok(addrs
+			.map(|addr| {
+				let ip = addr.ip().to_string();
+				Match::builder(&ip)
+					.title(&ip)
+					.subtitle(Some(host.to_owned()))
+					.icon("network-server")
+					.replace_query(Some(ip))
+					.build()
+			})
+			.collect())
+	}
+
+	async fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		// KRunner puts the address into the search field by itself
+		Ok(())
+	}
+
+	async fn config(&mut self) -> Result<Option<Config<Self::Action>>, Self::Err> {
+		let mut config = Config::default();
+		config.match_filter = Some(MatchFilter::Keywords(vec!["host".to_owned()]));
+		config.strip_trigger_words = true;
+		// Only look up what the user ends up typing
+		config.debounce = Some(Duration::from_millis(300));
+		Ok(Some(config))
+	}
+
+	fn match_timeout(&self) -> Option<Duration> {
+		Some(Duration::from_secs(2))
+	}
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+	Host.start("your.service.name", "/Host").await?;
+	Ok(())
+}
@@ -0,0 +1,53 @@
+// A runner that answers simple sums, like `1 + 2 - 0.5`.
+
+use krunner::{Match, RunnerContext, RunnerExt};
+
+struct Calculator;
+
+impl krunner::Runner for Calculator {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		let Some(answer) = eval(&query) else {
+			return Ok(vec![]);
+		};
+		Ok(vec![Match::answer(answer.to_string())
+			.subtitle(format!("= {query}"))
+			.icon("accessories-calculator")])
+	}
+
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		// KRunner puts the answer into the search field by itself
+		Ok(())
+	}
+}
+
+/// Evaluates additions and subtractions of numbers.
+fn eval(query: &str) -> Option<f64> {
+	// A lone number is not worth answering
+	if !query.contains(['+', '-']) {
+		return None;
+	}
+	let query = query.replace('-', "+-");
+	let mut terms = query.split('+').map(str::trim).filter(|t| !t.is_empty());
+	let first = terms.next()?.parse::<f64>().ok()?;
+	terms.try_fold(first, |sum, term| {
+		let term = term.replace(' ', "");
+		Some(sum + term.parse::<f64>().ok()?)
+	})
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	Calculator.start("your.service.name", "/Calculator")?;
+	Ok(())
+}
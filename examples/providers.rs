@@ -0,0 +1,95 @@
+use std::error::Error;
+
+use krunner::{ActionInfo, DynAction, Match, MatchProvider, ProviderHost, RunnerContext};
+
+/// Echoes the query back.
+struct Echo;
+
+impl MatchProvider for Echo {
+	fn name(&self) -> &str {
+		"echo"
+	}
+
+	fn matches(&self, query: &str, _ctx: &RunnerContext) -> Vec<Match<DynAction>> {
+		vec![Match {
+			id: query.to_owned(),
+			title: query.to_owned(),
+			icon: "mail-reply-sender".into(),
+			..Match::default()
+		}]
+	}
+
+	fn run(
+		&self,
+		match_id: &str,
+		_action: Option<&DynAction>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Box<dyn Error + Send + Sync>> {
+		println!("echo: {match_id}");
+		Ok(())
+	}
+}
+
+/// Reverses the query, and can print it in uppercase.
+struct Reverse;
+
+impl Reverse {
+	fn shout() -> DynAction {
+		DynAction {
+			id: "shout".to_owned(),
+			info: ActionInfo {
+				title: "Shout".to_owned(),
+				icon: "audio-volume-high".to_owned(),
+			},
+		}
+	}
+}
+
+impl MatchProvider for Reverse {
+	fn name(&self) -> &str {
+		"reverse"
+	}
+
+	fn actions(&self) -> Vec<DynAction> {
+		vec![Self::shout()]
+	}
+
+	fn matches(&self, query: &str, _ctx: &RunnerContext) -> Vec<Match<DynAction>> {
+		let reversed: String = query.chars().rev().collect();
+		vec![Match {
+			id: reversed.clone(),
+			title: reversed,
+			icon: "object-flip-horizontal".into(),
+			actions: vec![Self::shout()],
+			..Match::default()
+		}]
+	}
+
+	fn run(
+		&self,
+		match_id: &str,
+		action: Option<&DynAction>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Box<dyn Error + Send + Sync>> {
+		match action {
+			Some(a) if a.id == "shout" => println!("reverse: {}", match_id.to_uppercase()),
+			_ => println!("reverse: {match_id}"),
+		}
+		Ok(())
+	}
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut host = ProviderHost::new();
+	// Providers may just as well be chosen at runtime, e.g. from a config file
+	for name in std::env::args().skip(1) {
+		match name.as_str() {
+			"echo" => host.add(Box::new(Echo)),
+			"reverse" => host.add(Box::new(Reverse)),
+			_ => eprintln!("unknown provider: {name}"),
+		}
+	}
+
+	host.start("your.service.name", "/YourPath")?;
+	Ok(())
+}
@@ -1,6 +1,6 @@
 // Requires the `derive` feature.
 
-use krunner::{Match, RunnerExt};
+use krunner::{Match, RunnerContext, RunnerExt};
 
 #[derive(krunner::Action)]
 enum Action {
@@ -14,24 +14,32 @@ impl krunner::Runner for Runner {
 	type Action = Action;
 	type Err = String;
 
-	fn matches(&mut self, query: String) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
 		let mut matches = vec![];
 
 		if query == "hi" {
-			matches.push(Match {
-				id: "hi".to_owned(),
-				title: "Hello there!".to_owned(),
-				icon: "user-available".to_owned().into(),
-				subtitle: Some("This is a sample KRunner match!".to_owned()),
-
-				..Match::default()
-			})
+			matches.push(
+				Match::builder("hi")
+					.title("Hello there!")
+					.icon("user-available")
+					.subtitle("This is a sample KRunner match!".to_owned())
+					.build(),
+			);
 		}
 
 		Ok(matches)
 	}
 
-	fn run(&mut self, match_id: String, action: Option<Self::Action>) -> Result<(), Self::Err> {
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
 		Ok(())
 	}
 }
@@ -0,0 +1,67 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use dbus::blocking::Connection;
+
+/// A connection to the session bus, used solely to query the activity
+/// manager. It is kept separate from the runner's own connection, as the
+/// latter may be busy dispatching the very call we are handling.
+static SESSION: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// The current activity as of the last time it was queried, so that the
+/// activity manager isn't asked on every keystroke.
+static CURRENT: Mutex<Option<(Instant, Option<String>)>> = Mutex::new(None);
+
+/// How long the current activity is cached for. Switching activities is rare,
+/// and a query typed right after a switch is only a second out of date.
+const CURRENT_TTL: Duration = Duration::from_secs(1);
+
+/// Returns the ID of the current activity, as reported by the KDE activity
+/// manager (`org.kde.ActivityManager`), or `None` if it is unavailable.
+pub fn current_activity() -> Option<String> {
+	let mut session = SESSION.lock().unwrap_or_else(PoisonError::into_inner);
+	if session.is_none() {
+		*session = Some(Connection::new_session().ok()?);
+	}
+	let conn = session.as_ref()?;
+
+	let proxy = conn.with_proxy(
+		"org.kde.ActivityManager",
+		"/ActivityManager/Activities",
+		Duration::from_millis(500),
+	);
+	let result: Result<(String,), _> =
+		proxy.method_call("org.kde.ActivityManager.Activities", "CurrentActivity", ());
+
+	match result {
+		Ok((id,)) if !id.is_empty() => Some(id),
+		Ok(_) => None,
+		Err(_) => {
+			// The connection may have been lost, so start over next time
+			*session = None;
+			None
+		}
+	}
+}
+
+/// Like [`current_activity`], but only asks the activity manager again once
+/// the last answer is older than [`CURRENT_TTL`].
+fn cached_current_activity() -> Option<String> {
+	let mut current = CURRENT.lock().unwrap_or_else(PoisonError::into_inner);
+	match &*current {
+		Some((at, id)) if at.elapsed() < CURRENT_TTL => id.clone(),
+		_ => {
+			let id = current_activity();
+			*current = Some((Instant::now(), id.clone()));
+			id
+		}
+	}
+}
+
+/// Checks whether the current activity is one of `allowed`.
+///
+/// If the activity manager is absent or doesn't know the current activity,
+/// every activity is considered allowed.
+pub(crate) fn in_allowed_activity(allowed: &[String]) -> bool {
+	cached_current_activity().is_none_or(|id| allowed.contains(&id))
+}
@@ -0,0 +1,228 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+
+use crate::{
+	Action,
+	AsyncRunner,
+	Config,
+	ConfigPage,
+	Match,
+	Runner,
+	RunnerContext,
+	TeardownPolicy,
+};
+
+/// Adapts a blocking [`Runner`] into an [`AsyncRunner`].
+///
+/// Each method of the runner that may block is run on Tokio's blocking thread
+/// pool with [`spawn_blocking`](tokio::task::spawn_blocking), so that it
+/// doesn't hold up other tasks. This allows existing synchronous runners to be
+/// served from an asynchronous application without rewriting them.
+///
+/// The remaining, non-`async` methods are meant to be cheap, and are called
+/// directly. If a call whose future was dropped (e.g. because it timed out)
+/// is still running on the blocking thread pool, they answer as the runner
+/// did after its last call rather than wait for it.
+///
+/// # Example
+/// ```ignore
+/// use krunner::{AsyncAdapter, AsyncRunnerExt};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// 	AsyncAdapter::new(CalculatorRunner)
+/// 		.start("some.runner.path", "/SomeRunner")
+/// 		.await?;
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AsyncAdapter<R> {
+	runner: Arc<Mutex<R>>,
+	snapshot: Arc<Mutex<Snapshot>>,
+}
+
+/// The answers of the non-`async` methods of a runner, as of its last call.
+#[derive(Debug)]
+struct Snapshot {
+	config_is_static: bool,
+	config_page: Option<ConfigPage>,
+	/// The IDs of the available actions, as actions needn't be [`Clone`].
+	available_actions: Option<Vec<String>>,
+	allowed_activities: Option<Vec<String>>,
+	debug_relevance: bool,
+	teardown_policy: TeardownPolicy,
+}
+
+impl Snapshot {
+	fn of<R: Runner>(runner: &R) -> Self {
+		Self {
+			config_is_static: runner.config_is_static(),
+			config_page: runner.config_page(),
+			available_actions: runner
+				.available_actions()
+				.map(|actions| actions.iter().map(Action::to_id).collect()),
+			allowed_activities: runner.allowed_activities(),
+			debug_relevance: runner.debug_relevance(),
+			teardown_policy: runner.teardown_policy(),
+		}
+	}
+}
+
+impl<R: Runner> AsyncAdapter<R> {
+	/// Wraps the given runner.
+	pub fn new(runner: R) -> Self {
+		Self {
+			snapshot: Arc::new(Mutex::new(Snapshot::of(&runner))),
+			runner: Arc::new(Mutex::new(runner)),
+		}
+	}
+
+	/// Unwraps the runner, if no method of it is still running.
+	#[must_use]
+	pub fn into_inner(self) -> Option<R> {
+		Arc::into_inner(self.runner).map(|m| m.into_inner().unwrap_or_else(PoisonError::into_inner))
+	}
+
+	/// Runs `f` with the runner on the current thread, or returns `None` if
+	/// the runner is busy.
+	///
+	/// The runner can only be busy with a call whose future was dropped, but
+	/// is still running on the blocking thread pool. Waiting for it would block
+	/// the async runtime, so the [snapshot](Self::snapshot) is used instead.
+	fn try_with<T>(&self, f: impl FnOnce(&R) -> T) -> Option<T> {
+		match self.runner.try_lock() {
+			Ok(runner) => Some(f(&runner)),
+			Err(TryLockError::Poisoned(e)) => Some(f(&e.into_inner())),
+			Err(TryLockError::WouldBlock) => None,
+		}
+	}
+
+	/// Returns what the runner answered after its last call.
+	fn snapshot(&self) -> MutexGuard<'_, Snapshot> {
+		self.snapshot.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Runs `f` with the runner on the blocking thread pool.
+	async fn blocking<T: Send + 'static>(&self, f: impl FnOnce(&mut R) -> T + Send + 'static) -> T
+	where
+		R: Send + 'static,
+	{
+		let runner = Arc::clone(&self.runner);
+		let snapshot = Arc::clone(&self.snapshot);
+		let task = tokio::task::spawn_blocking(move || {
+			let mut runner = runner.lock().unwrap_or_else(PoisonError::into_inner);
+			// Panics are caught here, so that their location can be passed on
+			let result = panic::catch_unwind(AssertUnwindSafe(|| f(&mut runner)))
+				.map_err(|payload| (payload, crate::error::take_panic_location()));
+			if let Ok(new) = panic::catch_unwind(AssertUnwindSafe(|| Snapshot::of(&*runner))) {
+				*snapshot.lock().unwrap_or_else(PoisonError::into_inner) = new;
+			}
+			result
+		});
+		match task.await {
+			Ok(Ok(v)) => v,
+			Ok(Err((payload, location))) => crate::error::resume_unwind_at(payload, location),
+			// Blocking tasks can't be cancelled, so this can only be a panic
+			Err(e) => panic::resume_unwind(e.into_panic()),
+		}
+	}
+}
+
+#[cfg_attr(
+	all(not(docs_rs), not(feature = "native-async")),
+	async_trait::async_trait
+)]
+impl<R> AsyncRunner for AsyncAdapter<R>
+where
+	R: Runner + Send + 'static,
+	R::Action: Send + 'static,
+	R::Err: Send + 'static,
+{
+	type Action = R::Action;
+	type Err = R::Err;
+
+	const INTERFACE: &'static str = R::INTERFACE;
+
+	async fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		let ctx = ctx.clone();
+		self.blocking(move |r| r.matches(query, &ctx)).await
+	}
+
+	async fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		let ctx = ctx.clone();
+		self.blocking(move |r| r.run(match_id, action, &ctx)).await
+	}
+
+	async fn run_many(
+		&mut self,
+		runs: Vec<(String, Option<Self::Action>)>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		let ctx = ctx.clone();
+		self.blocking(move |r| r.run_many(runs, &ctx)).await
+	}
+
+	fn default_action_for(&self, match_id: &str) -> Option<Self::Action> {
+		// This depends on the match, so there is no snapshot of it
+		self.try_with(|r| r.default_action_for(match_id))
+			.unwrap_or_else(Self::Action::default_action)
+	}
+
+	async fn config(&mut self) -> Result<Option<Config<Self::Action>>, Self::Err> {
+		self.blocking(|r| r.config().map(Some)).await
+	}
+
+	fn config_is_static(&self) -> bool {
+		self.try_with(Runner::config_is_static)
+			.unwrap_or_else(|| self.snapshot().config_is_static)
+	}
+
+	fn config_page(&self) -> Option<ConfigPage> {
+		self.try_with(Runner::config_page)
+			.unwrap_or_else(|| self.snapshot().config_page.clone())
+	}
+
+	fn available_actions(&self) -> Option<Vec<Self::Action>> {
+		self.try_with(Runner::available_actions).unwrap_or_else(|| {
+			let ids = self.snapshot().available_actions.clone()?;
+			Some(
+				ids.iter()
+					.filter_map(|id| Self::Action::from_id(id))
+					.collect(),
+			)
+		})
+	}
+
+	fn allowed_activities(&self) -> Option<Vec<String>> {
+		self.try_with(Runner::allowed_activities)
+			.unwrap_or_else(|| self.snapshot().allowed_activities.clone())
+	}
+
+	fn debug_relevance(&self) -> bool {
+		self.try_with(Runner::debug_relevance)
+			.unwrap_or_else(|| self.snapshot().debug_relevance)
+	}
+
+	async fn startup(&mut self) -> Result<(), Self::Err> {
+		self.blocking(Runner::startup).await
+	}
+
+	fn teardown_policy(&self) -> TeardownPolicy {
+		self.try_with(Runner::teardown_policy)
+			.unwrap_or_else(|| self.snapshot().teardown_policy)
+	}
+
+	async fn teardown(&mut self) -> Result<(), Self::Err> {
+		self.blocking(Runner::teardown).await
+	}
+}
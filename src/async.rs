@@ -1,24 +1,58 @@
-use std::fmt::Display;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::{pin, Pin};
 use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
 
 use dbus::channel::MatchingReceiver;
 use dbus::message::MatchRule;
+use dbus::nonblock::SyncConnection;
 use dbus::MethodErr;
 use dbus_crossroads::{Context, Crossroads, IfaceToken};
-use tokio::sync::Mutex;
+use dbus_tokio::connection::IOResource;
+use tokio::sync::{mpsc, oneshot, Mutex};
 
-use crate::{Action, Config, Match};
+use crate::state::SharedState;
+use crate::{
+	Action,
+	Config,
+	ConfigPage,
+	Match,
+	NameFlags,
+	RunnerContext,
+	RunnerError,
+	TeardownPolicy,
+};
 
-#[cfg_attr(not(docs_rs), async_trait::async_trait)]
+#[cfg_attr(
+	all(not(docs_rs), not(feature = "native-async")),
+	async_trait::async_trait
+)]
+#[cfg_attr(
+	all(not(docs_rs), feature = "native-async"),
+	krunner_derive::send_futures
+)]
 /// An asynchronous runner.
 #[doc = concat!("\n\n", include_str!("./docs/runner/runner.md"), "\n\n")]
 /// Check out [`Runner`](crate::Runner) for a fully blocking, synchronous
 /// equivalent.
+///
+/// By default, this trait is defined with [`async_trait`], so implementations
+/// must be marked with `#[async_trait::async_trait]` as well, and every call
+/// boxes its future. With the `native-async` feature, it uses native `async
+/// fn`s in traits instead: implementations are then written with plain
+/// `async fn`s, whose futures must be `Send`.
+/// As this changes how the trait is implemented, the feature is best enabled
+/// by the final binary rather than by libraries.
 pub trait AsyncRunner {
 	#[doc = include_str!("./docs/runner/action.md")]
 	type Action: Action;
 	#[doc = include_str!("./docs/runner/err.md")]
-	type Err: Display;
+	type Err: RunnerError;
+	#[doc = include_str!("./docs/runner/interface.md")]
+	const INTERFACE: &'static str = crate::KRUNNER_INTERFACE;
 
 	#[doc = concat!(include_str!("./docs/runner/matches.md"), "\n\n")]
 	/// # Example
@@ -34,7 +68,8 @@ pub trait AsyncRunner {
 	///
 	///     async fn matches(
 	///         &mut self,
-	///         query: String
+	///         query: String,
+	///         _ctx: &RunnerContext,
 	///     ) -> Result<Vec<Match<Self::Action>>, Self::Err> {
 	///         let matches = if self.known_words.contains(&query) {
 	///             vec![Match {
@@ -54,7 +89,68 @@ pub trait AsyncRunner {
 	///     // ...
 	/// }
 	/// ```
-	async fn matches(&mut self, query: String) -> Result<Vec<Match<Self::Action>>, Self::Err>;
+	async fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err>;
+
+	/// Like [`matches`](Self::matches), but sends matches through a channel as
+	/// they're found, instead of returning them all at once.
+	///
+	/// KRunner receives all matches for a query in a single reply, which is
+	/// sent once this method returns and every clone of `matches` has been
+	/// dropped, so matches may also be sent from tasks spawned by the runner.
+	/// Streaming pays off together with a [match
+	/// timeout](Self::match_timeout): when the timeout elapses, the matches
+	/// sent so far are still shown, so a runner backed by a slow source (e.g.
+	/// a web search) can show its early results rather than none at all.
+	///
+	/// When this returns an error, any matches already sent are discarded.
+	///
+	/// This is what the runner is actually queried with. By default, it sends
+	/// the result of [`matches`](Self::matches), which a streaming runner may
+	/// in turn implement by collecting its own stream, for callers that
+	/// expect all matches at once.
+	///
+	/// # Example
+	///
+	/// ```ignore
+	/// #[async_trait::async_trait]
+	/// impl krunner::AsyncRunner for Runner {
+	///     // ...
+	///
+	///     async fn matches_streaming(
+	///         &mut self,
+	///         query: String,
+	///         _ctx: &RunnerContext,
+	///         matches: mpsc::UnboundedSender<Match<Self::Action>>,
+	///     ) -> Result<(), Self::Err> {
+	///         let mut results = self.search.query(&query).await?;
+	///         while let Some(result) = results.next().await {
+	///             let _ = matches.send(Match::builder(&result.url).title(result.title).build());
+	///         }
+	///         Ok(())
+	///     }
+	///
+	///     // ...
+	/// }
+	/// ```
+	async fn matches_streaming(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+		matches: mpsc::UnboundedSender<Match<Self::Action>>,
+	) -> Result<(), Self::Err>
+	where
+		Self::Action: Send,
+	{
+		for m in self.matches(query, ctx).await? {
+			// Nobody is listening anymore if the query timed out
+			let _ = matches.send(m);
+		}
+		Ok(())
+	}
 
 	#[doc = concat!(include_str!("./docs/runner/run.md"), "\n\n")]
 	/// # Example
@@ -72,6 +168,7 @@ pub trait AsyncRunner {
 	///         &mut self,
 	///         match_id: String,
 	///         action: Option<Self::Action>,
+	///         _ctx: &RunnerContext,
 	///     ) -> Result<(), Self::Err> {
 	///         match action {
 	///             Some(Action::LaunchDictionary) => {
@@ -94,13 +191,105 @@ pub trait AsyncRunner {
 		&mut self,
 		match_id: String,
 		action: Option<Self::Action>,
+		ctx: &RunnerContext,
 	) -> Result<(), Self::Err>;
 
-	#[doc = include_str!("./docs/runner/config.md")]
+	#[doc = include_str!("./docs/runner/run_many.md")]
+	async fn run_many(
+		&mut self,
+		runs: Vec<(String, Option<Self::Action>)>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err>
+	where
+		Self::Action: Send,
+	{
+		for (match_id, action) in runs {
+			self.run(match_id, action, ctx).await?;
+		}
+		Ok(())
+	}
+
+	#[doc = include_str!("./docs/runner/default_action_for.md")]
+	fn default_action_for(&self, match_id: &str) -> Option<Self::Action> {
+		let _ = match_id;
+		Self::Action::default_action()
+	}
+
+	#[doc = concat!(include_str!("./docs/runner/config.md"), "\n\n")]
+	/// Returning `Ok(None)` is the same as returning the
+	/// [default config](Config::default).
 	async fn config(&mut self) -> Result<Option<Config<Self::Action>>, Self::Err> {
 		Ok(None)
 	}
 
+	#[doc = include_str!("./docs/runner/config_is_static.md")]
+	fn config_is_static(&self) -> bool {
+		false
+	}
+
+	#[doc = include_str!("./docs/runner/config_page.md")]
+	fn config_page(&self) -> Option<ConfigPage> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/available_actions.md")]
+	fn available_actions(&self) -> Option<Vec<Self::Action>> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/allowed_activities.md")]
+	fn allowed_activities(&self) -> Option<Vec<String>> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/debug_relevance.md")]
+	fn debug_relevance(&self) -> bool {
+		crate::debug_relevance_from_env()
+	}
+
+	/// The longest time [`matches`](Self::matches) may take for a single
+	/// query.
+	///
+	/// When the timeout elapses, the future returned by `matches` is dropped,
+	/// which cancels it at its next `.await`, and KRunner is sent no matches
+	/// for the query, except for those already sent by
+	/// [`matches_streaming`](Self::matches_streaming). This keeps a slow or
+	/// hanging data source from holding up every following query, since the
+	/// runner is locked for as long as `matches` runs.
+	///
+	/// Timeouts require the time driver of the Tokio runtime to be enabled,
+	/// which is the case for runtimes created with `#[tokio::main]`.
+	///
+	/// Defaults to `None`, meaning no timeout.
+	fn match_timeout(&self) -> Option<Duration> {
+		None
+	}
+
+	/// The longest time [`teardown`](Self::teardown) may take.
+	///
+	/// When the timeout elapses, the future returned by `teardown` is dropped
+	/// and a warning is logged. This keeps a runner that is stuck flushing to
+	/// slow I/O from hanging its host when KRunner ends a session, or when
+	/// the process shuts down.
+	///
+	/// As with [`match_timeout`](Self::match_timeout), this requires the time
+	/// driver of the Tokio runtime to be enabled.
+	///
+	/// Defaults to `None`, meaning no timeout.
+	fn teardown_timeout(&self) -> Option<Duration> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/startup.md")]
+	async fn startup(&mut self) -> Result<(), Self::Err> {
+		Ok(())
+	}
+
+	#[doc = include_str!("./docs/runner/teardown_policy.md")]
+	fn teardown_policy(&self) -> TeardownPolicy {
+		TeardownPolicy::default()
+	}
+
 	#[doc = include_str!("./docs/runner/teardown.md")]
 	async fn teardown(&mut self) -> Result<(), Self::Err> {
 		Ok(())
@@ -114,8 +303,17 @@ pub trait AsyncRunnerExt: AsyncRunner + Sized + Send + 'static {
 	///
 	/// This is a convenience function that starts a new D-Bus connection,
 	/// requests the given service name, [registers the KRunner
-	/// interface](Self::register), and starts an asynchronous task that
-	/// is indefinitely listening on the session bus.
+	/// interface](Self::register), calls [`startup`](AsyncRunner::startup),
+	/// and starts an asynchronous task that is indefinitely listening on the
+	/// session bus.
+	///
+	/// With the `signal` feature enabled, receiving SIGTERM or SIGINT instead
+	/// stops listening, calls [`teardown`](AsyncRunner::teardown) and returns.
+	///
+	/// # Errors
+	/// Returns an `org.freedesktop.DBus.Error.InvalidArgs` error before
+	/// connecting if the service name or the object path is invalid (e.g. if
+	/// they were passed the wrong way around).
 	///
 	/// # Example
 	/// ```ignore
@@ -137,138 +335,678 @@ pub trait AsyncRunnerExt: AsyncRunner + Sized + Send + 'static {
 	where
 		Self::Action: Send;
 
+	/// Starts running this runner asynchronously, requesting the service name
+	/// with the given flags.
+	///
+	/// This is the same as [`start`](Self::start), except that it allows e.g.
+	/// a newly started process to take over the name from an old one.
+	async fn start_with_flags(
+		self,
+		service: &'static str,
+		path: &'static str,
+		flags: NameFlags,
+	) -> Result<(), dbus::Error>
+	where
+		Self::Action: Send;
+
+	/// Starts running this runner asynchronously, using an existing D-Bus
+	/// connection.
+	///
+	/// This is the same as [`start`](Self::start), except that the connection
+	/// can be shared with other parts of the application, or be to a
+	/// different bus. The caller is responsible for driving the connection,
+	/// e.g. by spawning the resource future returned by
+	/// [`dbus_tokio::connection::new_session_sync`].
+	async fn start_on(
+		self,
+		connection: Arc<SyncConnection>,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<(), dbus::Error>
+	where
+		Self::Action: Send;
+
+	/// Starts handling calls to this runner on an existing D-Bus connection,
+	/// without waiting for them.
+	///
+	/// Unlike [`start_on`](Self::start_on), this returns right away with a
+	/// handle to the runner, which can be used to stop handling calls later
+	/// on. As with `start_on`, the caller is responsible for driving the
+	/// connection.
+	///
+	/// # Example
+	/// ```ignore
+	/// use krunner::AsyncRunnerExt;
+	///
+	/// #[tokio::main]
+	/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// 	let (res, c) = dbus_tokio::connection::new_session_sync()?;
+	/// 	tokio::spawn(res);
+	///
+	/// 	let runner = Runner.attach(c, "some.runner.path", "/SomeRunner").await?;
+	/// 	do_other_work().await;
+	/// 	runner.detach().await?;
+	/// 	Ok(())
+	/// }
+	/// ```
+	async fn attach(
+		self,
+		connection: Arc<SyncConnection>,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<AttachedRunner<Self>, dbus::Error>
+	where
+		Self::Action: Send;
+
+	#[doc = include_str!("./docs/runnerext/config_dir.md")]
+	fn config_dir(service: &str) -> io::Result<PathBuf>;
+
 	#[doc = include_str!("./docs/runnerext/register.md")]
 	fn register(cr: &mut Crossroads) -> IfaceToken<Arc<Mutex<Self>>>
 	where
 		Self::Action: Send;
 }
+
+/// An [`AsyncRunner`] attached to a D-Bus connection driven by the caller,
+/// returned by [`AsyncRunnerExt::attach`].
+///
+/// Dropping this without calling [`detach`](Self::detach) keeps the runner
+/// attached for as long as the connection lives.
+pub struct AttachedRunner<R> {
+	c: Arc<SyncConnection>,
+	receive: dbus::channel::Token,
+	runner: Arc<Mutex<R>>,
+}
 #[cfg_attr(not(docs_rs), async_trait::async_trait)]
 impl<R: AsyncRunner + Sized + Send + 'static> AsyncRunnerExt for R {
 	async fn start(self, service: &'static str, path: &'static str) -> Result<(), dbus::Error>
 	where
 		Self::Action: Send,
 	{
-		let (res, c) = dbus_tokio::connection::new_session_sync()?;
-
-		let _handle = tokio::spawn(async {
-			let err = res.await;
-			panic!("Lost connection to D-Bus: {err}");
-		});
+		self.start_with_flags(service, path, NameFlags::default())
+			.await
+	}
 
-		c.request_name(service, false, true, false).await?;
+	async fn start_with_flags(
+		self,
+		service: &'static str,
+		path: &'static str,
+		flags: NameFlags,
+	) -> Result<(), dbus::Error>
+	where
+		Self::Action: Send,
+	{
+		crate::builder::validate_service(service)?;
+		crate::builder::validate_path(path)?;
+		let connection = dbus_tokio::connection::new_session_sync()?;
+		serve_new(self, connection, service, path, flags, &Spawner::default()).await
+	}
 
-		let mut cr = Crossroads::new();
-		cr.set_async_support(Some((
-			c.clone(),
-			Box::new(|x| {
-				tokio::spawn(x);
-			}),
-		)));
+	async fn start_on(
+		self,
+		c: Arc<SyncConnection>,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<(), dbus::Error>
+	where
+		Self::Action: Send,
+	{
+		serve(
+			self,
+			c,
+			service,
+			path,
+			NameFlags::default(),
+			&Spawner::default(),
+		)
+		.await
+	}
 
-		let token = Self::register(&mut cr);
-		cr.insert(path, &[token], Arc::new(Mutex::new(self)));
+	async fn attach(
+		self,
+		c: Arc<SyncConnection>,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<AttachedRunner<Self>, dbus::Error>
+	where
+		Self::Action: Send,
+	{
+		attach(
+			self,
+			c,
+			service,
+			path,
+			NameFlags::default(),
+			&Spawner::default(),
+		)
+		.await
+	}
 
-		// equiv to `serve`
-		c.start_receive(
-			MatchRule::new_method_call(),
-			Box::new(move |msg, conn| {
-				cr.handle_message(msg, conn).unwrap();
-				true
-			}),
-		);
-		std::future::pending::<()>().await;
-		unreachable!()
+	fn config_dir(service: &str) -> io::Result<PathBuf> {
+		crate::settings::runner_config_dir(service)
 	}
 
 	fn register(cr: &mut Crossroads) -> IfaceToken<Arc<Mutex<Self>>>
 	where
 		Self::Action: Send,
 	{
-		cr.register("org.kde.krunner1", |b| {
-			b.method(
-				"Actions",
-				(),
-				("matches",),
-				|_, _: &mut Arc<Mutex<Self>>, _: ()| {
-					let actions: Vec<_> =
-						R::Action::all().iter().map(crate::action_as_arg).collect();
-
-					Ok((actions,))
-				},
-			);
-			b.method_with_cr_async(
-				"Run",
-				("matchId", "actionId"),
-				(),
-				|mut ctx, cr, (match_id, action_id): (String, String)| {
+		let state = SharedState::default();
+
+		cr.register(Self::INTERFACE, |b| {
+			b.method_with_cr_async("Actions", (), ("matches",), {
+				let state = state.clone();
+				move |mut ctx, cr, _: ()| {
 					let runner = get_runner::<Self>(cr, &ctx);
+					let runner_ctx = RunnerContext::new(&ctx);
+					let state = state.clone();
 
 					async move {
-						ctx.reply('r: {
-							let mut lock = runner.lock().await;
-
-							let action = if let Some(action) = R::Action::from_id(&action_id) {
-								Some(action)
-							} else if action_id.is_empty() {
-								None
-							} else {
-								break 'r Err(MethodErr::invalid_arg("unknown action"));
-							};
-							lock.run(match_id, action)
-								.await
-								.map_err(|e| MethodErr::failed(&e))
-						})
+						let available =
+							catch_panic(async { Ok(runner.lock().await.available_actions()) });
+						ctx.reply(available.await.map(|available| {
+							let path = runner_ctx.path();
+							(state.actions(path, available.as_deref(), &runner_ctx),)
+						}))
 					}
-				},
-			);
-			b.method_with_cr_async(
-				"Match",
-				("query",),
-				("matches",),
-				|mut ctx, cr, (query,): (String,)| {
+				}
+			});
+			b.method_with_cr_async("Run", ("matchId", "actionId"), (), {
+				let state = state.clone();
+				move |mut ctx, cr, (match_id, action_id): (String, String)| {
+					event!(debug, path = %ctx.path(), match_id, action_id, "Run");
 					let runner = get_runner::<Self>(cr, &ctx);
+					let runner_ctx = RunnerContext::new(&ctx).with_cache(state.cache(ctx.path()));
 
 					async move {
-						ctx.reply({
+						let result = catch_panic(async {
 							let mut lock = runner.lock().await;
-
-							lock.matches(query)
-								.await
-								.map(|v| (v,))
-								.map_err(|e| MethodErr::failed(&e))
-						})
+							handle_run(&mut *lock, match_id, &action_id, &runner_ctx).await
+						});
+						ctx.reply(result.await)
 					}
-				},
-			);
-			b.method_with_cr_async("Config", (), ("config",), |mut ctx, cr, _: ()| {
-				let runner = get_runner::<Self>(cr, &ctx);
+				}
+			});
+			b.method_with_cr_async("Match", ("query",), ("matches",), {
+				let state = state.clone();
+				move |mut ctx, cr, (query,): (String,)| {
+					event!(debug, path = %ctx.path(), query, "Match");
+					let runner = get_runner::<Self>(cr, &ctx);
+					let runner_ctx = RunnerContext::new(&ctx).with_cache(state.cache(ctx.path()));
+					let id = state.begin_query(ctx.path());
+					let state = state.clone();
 
-				async move {
-					ctx.reply({
-						let mut lock = runner.lock().await;
+					async move {
+						let matches =
+							catch_panic(handle_query(&runner, query, runner_ctx, &state, id));
+						ctx.reply(matches.await.map(|v| (v,)))
+					}
+				}
+			});
+			b.method_with_cr_async("Config", (), ("config",), {
+				let state = state.clone();
+				move |mut ctx, cr, _: ()| {
+					event!(debug, path = %ctx.path(), "Config");
+					let runner = get_runner::<Self>(cr, &ctx);
+					let state = state.clone();
 
-						match lock.config().await {
-							Ok(Some(v)) => Ok((v,)),
-							Ok(None) => Err(MethodErr::no_method("config")),
-							Err(e) => Err(MethodErr::failed(&e)),
-						}
-					})
+					async move {
+						let path = ctx.path().clone();
+						let config = catch_panic(async {
+							let mut lock = runner.lock().await;
+							handle_config(&mut *lock, &path, &state).await
+						});
+						ctx.reply(config.await.map(|c| (c,)))
+					}
 				}
 			});
-			b.method_with_cr_async("Teardown", (), (), |mut ctx, cr, _: ()| {
+			b.method_with_cr_async("Teardown", (), (), move |mut ctx, cr, _: ()| {
+				#[cfg(feature = "icon-lookup")]
+				crate::icons::clear_lookups();
 				let runner = get_runner::<Self>(cr, &ctx);
+				let state = state.clone();
 				async move {
-					ctx.reply({
+					let path = ctx.path().clone();
+					let result = catch_panic(async {
 						let mut lock = runner.lock().await;
-
-						lock.teardown().await.map_err(|e| MethodErr::failed(&e))
-					})
+						match lock.teardown_policy() {
+							TeardownPolicy::KeepCache => Ok(()),
+							TeardownPolicy::EvictCache => {
+								state.evict(&path);
+								Ok(())
+							}
+							TeardownPolicy::Custom => {
+								state.cache(&path).clear();
+								handle_teardown(&mut *lock).await
+							}
+						}
+					});
+					ctx.reply(result.await)
 				}
 			});
 		})
 	}
 }
 
+/// A future spawned by a [`Spawner`].
+pub type SpawnedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs the tasks that serve an [`AsyncRunner`], i.e. the D-Bus connection and
+/// every method call, in the background.
+///
+/// By default, tasks are spawned with [`tokio::spawn`], which works with both
+/// the multi-thread and the current-thread runtime, as long as the runner is
+/// started from within the runtime. A custom spawner allows running them
+/// elsewhere, e.g. on a dedicated runtime in an application that doesn't
+/// otherwise use Tokio. Use it with [`RunnerBuilder::spawner`].
+///
+/// # Example
+/// ```
+/// use krunner::Spawner;
+///
+/// let rt = tokio::runtime::Builder::new_current_thread()
+/// 	.build()
+/// 	.unwrap();
+/// let handle = rt.handle().clone();
+/// let spawner = Spawner::new(move |task| {
+/// 	handle.spawn(task);
+/// });
+/// ```
+///
+/// [`RunnerBuilder::spawner`]: crate::RunnerBuilder::spawner
+#[derive(Clone)]
+pub struct Spawner(Arc<dyn Fn(SpawnedFuture) + Send + Sync>);
+
+impl Spawner {
+	/// Creates a spawner that spawns tasks with the given function.
+	pub fn new(spawn: impl Fn(SpawnedFuture) + Send + Sync + 'static) -> Self {
+		Self(Arc::new(spawn))
+	}
+
+	fn spawn(&self, task: SpawnedFuture) {
+		(self.0)(task);
+	}
+}
+
+impl Default for Spawner {
+	fn default() -> Self {
+		Self::new(|task| {
+			tokio::spawn(task);
+		})
+	}
+}
+
+impl std::fmt::Debug for Spawner {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Spawner").finish_non_exhaustive()
+	}
+}
+
+impl<R: AsyncRunner + Send> AttachedRunner<R> {
+	/// The attached runner.
+	///
+	/// Calls to the runner are handled while holding the lock, so it should
+	/// only be held briefly.
+	#[must_use]
+	pub fn runner(&self) -> &Arc<Mutex<R>> {
+		&self.runner
+	}
+
+	/// Stops handling calls to the runner, and calls
+	/// [`teardown`](AsyncRunner::teardown) on it.
+	///
+	/// The service name is kept, as the connection may still be using it.
+	///
+	/// # Errors
+	/// Returns the error from [`teardown`](AsyncRunner::teardown), if any.
+	pub async fn detach(self) -> Result<(), dbus::Error> {
+		// The connection may outlive us, so stop handling calls with it
+		self.c.stop_receive(self.receive);
+
+		handle_teardown(&mut *self.runner.lock().await)
+			.await
+			.map_err(|e| crate::dbus_err(&e))
+	}
+}
+
+impl<R> std::fmt::Debug for AttachedRunner<R> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("AttachedRunner").finish_non_exhaustive()
+	}
+}
+
+/// Serves `runner` on a new connection until shut down, driving the
+/// connection with `spawner`.
+pub(crate) async fn serve_new<R: AsyncRunnerExt>(
+	runner: R,
+	(res, c): (IOResource<SyncConnection>, Arc<SyncConnection>),
+	service: &'static str,
+	path: &'static str,
+	flags: NameFlags,
+	spawner: &Spawner,
+) -> Result<(), dbus::Error>
+where
+	R::Action: Send,
+{
+	// Custom spawners can't abort tasks, so tell the task to stop instead
+	let (stop, stopped) = oneshot::channel::<()>();
+	spawner.spawn(Box::pin(async move {
+		let mut res = pin!(res);
+		let mut stopped = pin!(stopped);
+		let err = std::future::poll_fn(|cx| {
+			if stopped.as_mut().poll(cx).is_ready() {
+				return Poll::Ready(None);
+			}
+			res.as_mut().poll(cx).map(Some)
+		})
+		.await;
+		if let Some(err) = err {
+			panic!("Lost connection to D-Bus: {err}");
+		}
+	}));
+
+	let result = serve(runner, c, service, path, flags, spawner).await;
+	let _ = stop.send(());
+	result
+}
+
+/// Serves `runner` on the given connection until shut down.
+async fn serve<R: AsyncRunnerExt>(
+	runner: R,
+	c: Arc<SyncConnection>,
+	service: &'static str,
+	path: &'static str,
+	flags: NameFlags,
+	spawner: &Spawner,
+) -> Result<(), dbus::Error>
+where
+	R::Action: Send,
+{
+	let attached = attach(runner, c, service, path, flags, spawner).await?;
+	let mut attached = DetachOnDrop(Some(attached), spawner.clone());
+
+	#[cfg(not(feature = "signal"))]
+	{
+		let _ = &mut attached;
+		std::future::pending::<()>().await;
+		unreachable!()
+	}
+
+	#[cfg(feature = "signal")]
+	{
+		shutdown_signal()
+			.await
+			.map_err(|e| dbus::Error::new_failed(&format!("cannot handle signals: {e}")))?;
+
+		// We're shutting down either way, so there's nobody left to report
+		// the error to
+		if let Some(attached) = attached.0.take() {
+			let _ = attached.detach().await;
+		}
+		Ok(())
+	}
+}
+
+/// Detaches a runner when dropped, so that it's torn down even if serving it
+/// fails or is cancelled.
+///
+/// Teardown is asynchronous, so it's spawned as a new task, if the runtime is
+/// still around to run it.
+struct DetachOnDrop<R>(Option<AttachedRunner<R>>, Spawner)
+where
+	R: AsyncRunner + Send + 'static,
+	R::Action: Send;
+
+impl<R> Drop for DetachOnDrop<R>
+where
+	R: AsyncRunner + Send + 'static,
+	R::Action: Send,
+{
+	fn drop(&mut self) {
+		let Some(attached) = self.0.take() else {
+			return;
+		};
+		if tokio::runtime::Handle::try_current().is_ok() {
+			self.1.spawn(Box::pin(async move {
+				let _ = attached.detach().await;
+			}));
+		}
+	}
+}
+
+/// Requests `service` and starts handling calls to `runner` on the given
+/// connection.
+async fn attach<R: AsyncRunnerExt>(
+	mut runner: R,
+	c: Arc<SyncConnection>,
+	service: &'static str,
+	path: &'static str,
+	flags: NameFlags,
+	spawner: &Spawner,
+) -> Result<AttachedRunner<R>, dbus::Error>
+where
+	R::Action: Send,
+{
+	crate::builder::validate_service(service)?;
+	crate::builder::validate_path(path)?;
+	flags.request_async(&c, service).await?;
+	runner
+		.startup()
+		.await
+		.map_err(|e| crate::dbus_err(&crate::method_err(&e)))?;
+
+	let mut cr = Crossroads::new();
+	let spawner = spawner.clone();
+	cr.set_async_support(Some((c.clone(), Box::new(move |x| spawner.spawn(x)))));
+
+	let runner = Arc::new(Mutex::new(runner));
+	let token = R::register(&mut cr);
+	cr.insert(path, &[token], Arc::clone(&runner));
+
+	// equiv to `serve`
+	let receive = c.start_receive(
+		MatchRule::new_method_call(),
+		Box::new(move |msg, conn| {
+			crate::host::handle_message(&mut cr, msg, conn);
+			true
+		}),
+	);
+	Ok(AttachedRunner { c, receive, runner })
+}
+
+pub(crate) async fn handle_match<R: AsyncRunner + Send>(
+	runner: &mut R,
+	query: String,
+	ctx: RunnerContext,
+	state: &SharedState,
+) -> Result<Vec<Match<R::Action>>, MethodErr>
+where
+	R::Action: Send,
+{
+	if let Some(allowed) = runner.allowed_activities() {
+		let allowed =
+			tokio::task::spawn_blocking(move || crate::activities::in_allowed_activity(&allowed))
+				.await
+				.unwrap_or(true);
+		if !allowed {
+			return Ok(vec![]);
+		}
+	}
+
+	let path = &ctx.path().to_owned();
+	if !state.config_read(path) {
+		match runner.config().await {
+			Ok(config) => state.set_config(path, &config.unwrap_or_default()),
+			#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+			Err(e) => {
+				event!(warn, path = %path, error = %e, "cannot read config");
+			}
+		}
+	}
+	if state.disabled(path) {
+		return Ok(vec![]);
+	}
+	let ctx = ctx
+		.with_raw_query(query.clone())
+		.with_trigger_words(state.trigger_words(path));
+	let query = state.preprocess_query(path, query);
+
+	ctx.cache().clear();
+	let timeout = runner.match_timeout();
+	let (tx, mut rx) = mpsc::unbounded_channel();
+	let mut matches = Vec::new();
+	let stream = async {
+		runner.matches_streaming(query, &ctx, tx).await?;
+		// Tasks spawned by the runner may still be sending matches
+		while let Some(m) = rx.recv().await {
+			matches.push(m);
+		}
+		Ok::<_, R::Err>(())
+	};
+	let streamed = match timeout {
+		Some(timeout) => tokio::time::timeout(timeout, stream)
+			.await
+			.unwrap_or_else(|_| {
+				event!(warn, path = %path, ?timeout, "matches timed out");
+				Ok(())
+			}),
+		None => stream.await,
+	};
+	streamed.map_err(|e| crate::method_err(&e))?;
+	// Keep what was sent before timing out
+	while let Ok(m) = rx.try_recv() {
+		matches.push(m);
+	}
+	state.postprocess_matches(path, &mut matches);
+	event!(debug, path = %path, count = matches.len(), "returning matches");
+	if cfg!(debug_assertions) && runner.debug_relevance() {
+		crate::annotate_relevance(&mut matches);
+	}
+	state.check_reply_size(path, &matches)?;
+	Ok(matches)
+}
+
+/// Handles the query numbered `id`, unless a newer query supersedes it while
+/// waiting out the [debounce window](Config::debounce) or waiting for the
+/// runner, in which case there are no matches.
+pub(crate) async fn handle_query<R: AsyncRunner + Send>(
+	runner: &Mutex<R>,
+	query: String,
+	ctx: RunnerContext,
+	state: &SharedState,
+	id: u64,
+) -> Result<Vec<Match<R::Action>>, MethodErr>
+where
+	R::Action: Send,
+{
+	let path = ctx.path().to_owned();
+	if let Some(window) = state.debounce(&path) {
+		tokio::time::sleep(window).await;
+	}
+	if state.superseded(&path, id) {
+		return Ok(vec![]);
+	}
+	let mut lock = runner.lock().await;
+	// Newer queries may have arrived while waiting for the lock
+	if state.superseded(&path, id) {
+		return Ok(vec![]);
+	}
+	handle_match(&mut *lock, query, ctx, state).await
+}
+
+pub(crate) async fn handle_config<R: AsyncRunner + Send>(
+	runner: &mut R,
+	path: &str,
+	state: &SharedState,
+) -> Result<Config<R::Action>, MethodErr> {
+	if runner.config_is_static() {
+		if let Some(config) = state.config(path) {
+			return Ok(config);
+		}
+	}
+	match runner.config().await {
+		Ok(Some(config)) => {
+			state.set_config(path, &config);
+			Ok(config)
+		}
+		// Replying with an error would make KRunner treat the runner as broken,
+		// so send the default config instead
+		Ok(None) => {
+			let config = Config::default();
+			state.set_config(path, &config);
+			Ok(config)
+		}
+		Err(e) => Err(crate::method_err(&e)),
+	}
+}
+
+pub(crate) async fn handle_teardown<R: AsyncRunner + Send>(
+	runner: &mut R,
+) -> Result<(), MethodErr> {
+	let timeout = runner.teardown_timeout();
+	let teardown = runner.teardown();
+	let result = match timeout {
+		Some(timeout) => {
+			let Ok(result) = tokio::time::timeout(timeout, teardown).await else {
+				event!(warn, ?timeout, "teardown timed out");
+				return Ok(());
+			};
+			result
+		}
+		None => teardown.await,
+	};
+	result.map_err(|e| crate::method_err(&e))
+}
+
+pub(crate) async fn handle_run<R: AsyncRunner + Send>(
+	runner: &mut R,
+	match_id: String,
+	action_id: &str,
+	ctx: &RunnerContext,
+) -> Result<(), MethodErr> {
+	let action = if let Some(action) = R::Action::from_id(action_id) {
+		Some(action)
+	} else if action_id.is_empty() {
+		runner.default_action_for(&match_id)
+	} else {
+		return Err(MethodErr::invalid_arg("unknown action"));
+	};
+	runner
+		.run(match_id, action, ctx)
+		.await
+		.map_err(|e| crate::method_err(&e))
+}
+
+/// Runs `fut`, replying with an error instead of unwinding if it panics.
+///
+/// Tokio would otherwise swallow the panic along with the reply, leaving
+/// KRunner waiting for a reply until it times out.
+pub(crate) async fn catch_panic<T>(
+	fut: impl Future<Output = Result<T, MethodErr>>,
+) -> Result<T, MethodErr> {
+	let mut fut = pin!(fut);
+	std::future::poll_fn(move |cx| {
+		match crate::catch_unwind(|| fut.as_mut().poll(cx)) {
+			Ok(poll) => poll,
+			Err(e) => Poll::Ready(Err(e)),
+		}
+	})
+	.await
+}
+
 fn get_runner<R: AsyncRunnerExt>(cr: &mut Crossroads, ctx: &Context) -> Arc<Mutex<R>> {
 	Arc::clone(cr.data_mut(ctx.path()).unwrap())
 }
+
+/// Waits until either SIGTERM or SIGINT is received.
+#[cfg(feature = "signal")]
+pub(crate) async fn shutdown_signal() -> std::io::Result<()> {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut term = signal(SignalKind::terminate())?;
+	let mut int = signal(SignalKind::interrupt())?;
+	tokio::select! {
+		_ = term.recv() => {}
+		_ = int.recv() => {}
+	}
+	Ok(())
+}
@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::{env, io};
+
+use crate::{ErrorKind, RunnerError};
+
+/// Finds the executable with the given name in `$PATH`, the same way a shell
+/// would.
+///
+/// Runners that shell out to other programs can check for them up front with
+/// this, and propagate a [`MissingBinary`] error through their
+/// [`Err`](crate::Runner::Err) type, instead of panicking or failing with an
+/// obscure "No such file or directory" once the program is spawned. Names
+/// containing a `/` are taken as paths and only checked for being
+/// executable.
+///
+/// # Errors
+/// Returns an error if no executable file with the given name can be found.
+///
+/// # Example
+/// ```no_run
+/// use std::process::Command;
+///
+/// fn nix_index() -> Result<String, Box<dyn std::error::Error>> {
+/// 	let nix = krunner::require_binary("nix")?;
+/// 	let output = Command::new(nix)
+/// 		.args(["search", "nixpkgs", "--json", "^"])
+/// 		.output()?;
+/// 	Ok(String::from_utf8(output.stdout)?)
+/// }
+/// ```
+pub fn require_binary(name: &str) -> Result<PathBuf, MissingBinary> {
+	let missing = || MissingBinary(name.to_owned());
+	if name.is_empty() {
+		return Err(missing());
+	}
+	if name.contains('/') {
+		let path = Path::new(name);
+		return is_executable(path)
+			.then(|| path.to_owned())
+			.ok_or_else(missing);
+	}
+	let path = env::var_os("PATH").ok_or_else(missing)?;
+	env::split_paths(&path)
+		.map(|dir| dir.join(name))
+		.find(|path| is_executable(path))
+		.ok_or_else(missing)
+}
+
+fn is_executable(path: &Path) -> bool {
+	path.metadata()
+		.is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// An error returned when a program a runner depends on can't be found.
+///
+/// Returned by [`require_binary`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MissingBinary(pub String);
+
+impl Display for MissingBinary {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "cannot find `{}`; is it installed and in $PATH?", self.0)
+	}
+}
+impl Error for MissingBinary {}
+impl RunnerError for MissingBinary {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::NotSupported
+	}
+}
+
+impl From<MissingBinary> for io::Error {
+	fn from(e: MissingBinary) -> Self {
+		io::Error::new(io::ErrorKind::NotFound, e)
+	}
+}
@@ -0,0 +1,303 @@
+use dbus::blocking::Connection;
+#[cfg(feature = "tokio")]
+use dbus::nonblock::SyncConnection;
+
+#[cfg(feature = "tokio")]
+use crate::{AsyncRunnerExt, Spawner};
+use crate::{RunnerExt, RunnerHost};
+
+/// The message bus a runner is served on.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Bus {
+	/// The session bus of the current user, which is where KRunner looks for
+	/// runners.
+	#[default]
+	Session,
+	/// The system-wide bus.
+	System,
+}
+
+/// How the service name of a runner is requested from the bus.
+///
+/// The default matches the behavior of [`RunnerExt::start`]: the name is
+/// taken over from its current owner if it allows that, and the runner waits
+/// in line for the name otherwise. Runners that want to be hot-reloadable,
+/// where a newly started process takes over from the old one, should set
+/// [`allow_replacement`](Self::allow_replacement).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct NameFlags {
+	/// Whether another process may take over the name later by requesting it
+	/// with [`replace_existing`](Self::replace_existing).
+	pub allow_replacement: bool,
+	/// Whether to take over the name from its current owner, if the owner
+	/// allows it.
+	pub replace_existing: bool,
+	/// Whether to wait in line for the name if it can't be taken over, rather
+	/// than failing.
+	pub queue: bool,
+}
+
+impl Default for NameFlags {
+	fn default() -> Self {
+		Self {
+			allow_replacement: false,
+			replace_existing: true,
+			queue: true,
+		}
+	}
+}
+
+impl NameFlags {
+	/// Requests `service` on the given connection with these flags.
+	pub(crate) fn request(self, c: &Connection, service: &str) -> Result<(), dbus::Error> {
+		let reply = c.request_name(
+			service,
+			self.allow_replacement,
+			self.replace_existing,
+			!self.queue,
+		)?;
+		self.check(service, reply as u32)
+	}
+
+	/// Requests `service` on the given asynchronous connection with these
+	/// flags.
+	#[cfg(feature = "tokio")]
+	pub(crate) async fn request_async(
+		self,
+		c: &SyncConnection,
+		service: &str,
+	) -> Result<(), dbus::Error> {
+		let reply = c
+			.request_name(
+				service,
+				self.allow_replacement,
+				self.replace_existing,
+				!self.queue,
+			)
+			.await?;
+		self.check(service, reply as u32)
+	}
+
+	/// Turns the reply to a name request into an error if the name wasn't
+	/// acquired or queued for as requested.
+	fn check(self, service: &str, reply: u32) -> Result<(), dbus::Error> {
+		// DBUS_REQUEST_NAME_REPLY_*
+		const PRIMARY_OWNER: u32 = 1;
+		const IN_QUEUE: u32 = 2;
+		const ALREADY_OWNER: u32 = 4;
+
+		match reply {
+			PRIMARY_OWNER | ALREADY_OWNER => Ok(()),
+			IN_QUEUE if self.queue => Ok(()),
+			_ => Err(dbus::Error::new_custom(
+				"org.freedesktop.DBus.Error.AddressInUse",
+				&format!(
+					"service name {service:?} is already owned by another process, which doesn't \
+					 allow it to be replaced"
+				),
+			)),
+		}
+	}
+}
+
+/// A builder for starting a [runner](crate::Runner) with named, validated
+/// settings.
+///
+/// Like with [`RunnerExt::start`], the service name and object path are
+/// validated before connecting to the bus, so that mistakes (such as passing
+/// them the wrong way around) result in a clear error.
+///
+/// With the `tokio` feature enabled, `AsyncRunner`s can be started with
+/// `run_async` as well.
+///
+/// # Example
+/// ```ignore
+/// use krunner::{Bus, RunnerBuilder};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// 	RunnerBuilder::new(Runner)
+/// 		.service("some.runner.path")
+/// 		.path("/SomeRunner")
+/// 		.bus(Bus::Session)
+/// 		.run()?;
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RunnerBuilder<R> {
+	runner: R,
+	service: Option<&'static str>,
+	path: Option<&'static str>,
+	bus: Bus,
+	name_flags: NameFlags,
+	#[cfg(feature = "tokio")]
+	spawner: Spawner,
+}
+
+impl<R> RunnerBuilder<R> {
+	/// Creates a builder for the given runner.
+	pub fn new(runner: R) -> Self {
+		Self {
+			runner,
+			service: None,
+			path: None,
+			bus: Bus::default(),
+			name_flags: NameFlags::default(),
+			#[cfg(feature = "tokio")]
+			spawner: Spawner::default(),
+		}
+	}
+
+	/// Sets the well-known service name to request (e.g. `some.runner.path`).
+	#[must_use]
+	pub fn service(mut self, service: &'static str) -> Self {
+		self.service = Some(service);
+		self
+	}
+
+	/// Sets the object path to serve the runner on (e.g. `/SomeRunner`).
+	#[must_use]
+	pub fn path(mut self, path: &'static str) -> Self {
+		self.path = Some(path);
+		self
+	}
+
+	/// Sets the bus to serve the runner on. Defaults to [`Bus::Session`].
+	#[must_use]
+	pub fn bus(mut self, bus: Bus) -> Self {
+		self.bus = bus;
+		self
+	}
+
+	/// Sets how the service name is requested. Defaults to
+	/// [`NameFlags::default`].
+	#[must_use]
+	pub fn name_flags(mut self, name_flags: NameFlags) -> Self {
+		self.name_flags = name_flags;
+		self
+	}
+
+	/// Checks that the service name and the object path are present and
+	/// valid.
+	fn validate(&self) -> Result<(&'static str, &'static str), dbus::Error> {
+		let service = self
+			.service
+			.ok_or_else(|| invalid_args("no service name was given"))?;
+		validate_service(service)?;
+		let path = self
+			.path
+			.ok_or_else(|| invalid_args("no object path was given"))?;
+		validate_path(path)?;
+		Ok((service, path))
+	}
+}
+
+impl<R: RunnerExt> RunnerBuilder<R> {
+	/// Validates the settings, then starts running the runner on the main
+	/// thread indefinitely, like [`RunnerExt::start`].
+	///
+	/// # Errors
+	/// Returns an `org.freedesktop.DBus.Error.InvalidArgs` error if the service
+	/// name or the object path is missing or invalid, and any error from
+	/// connecting to the bus or serving the runner otherwise.
+	pub fn run(self) -> Result<(), dbus::Error> {
+		let (service, path) = self.validate()?;
+		let c = match self.bus {
+			Bus::Session => Connection::new_session()?,
+			Bus::System => Connection::new_system()?,
+		};
+		RunnerHost::new(service)
+			.with_name_flags(self.name_flags)
+			.with_runner(path, self.runner)
+			.start_on(&c)
+	}
+}
+
+#[cfg(feature = "tokio")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "tokio")))]
+impl<R: AsyncRunnerExt> RunnerBuilder<R> {
+	/// Sets how the tasks serving the runner are spawned. Defaults to
+	/// [`tokio::spawn`].
+	///
+	/// This only affects [`run_async`](Self::run_async).
+	#[must_use]
+	pub fn spawner(mut self, spawner: Spawner) -> Self {
+		self.spawner = spawner;
+		self
+	}
+
+	/// Validates the settings, then starts running the runner asynchronously,
+	/// like [`AsyncRunnerExt::start`].
+	///
+	/// # Errors
+	/// Returns an `org.freedesktop.DBus.Error.InvalidArgs` error if the service
+	/// name or the object path is missing or invalid, and any error from
+	/// connecting to the bus or serving the runner otherwise.
+	pub async fn run_async(self) -> Result<(), dbus::Error>
+	where
+		R::Action: Send,
+	{
+		let (service, path) = self.validate()?;
+		let connection = match self.bus {
+			Bus::Session => dbus_tokio::connection::new_session_sync()?,
+			Bus::System => dbus_tokio::connection::new_system_sync()?,
+		};
+		crate::_async::serve_new(
+			self.runner,
+			connection,
+			service,
+			path,
+			self.name_flags,
+			&self.spawner,
+		)
+		.await
+	}
+}
+
+/// Checks a well-known bus name against the rules of the D-Bus
+/// specification.
+pub(crate) fn validate_service(service: &str) -> Result<(), dbus::Error> {
+	let invalid = |reason: &str| {
+		Err(invalid_args(&format!(
+			"invalid service name {service:?}: {reason}"
+		)))
+	};
+
+	if service.starts_with(':') {
+		return invalid("unique names can't be requested");
+	}
+	if service.len() > 255 {
+		return invalid("longer than 255 characters");
+	}
+	let elements: Vec<_> = service.split('.').collect();
+	if elements.len() < 2 {
+		return invalid("must contain at least two elements separated by '.'");
+	}
+	for element in elements {
+		if element.is_empty() {
+			return invalid("elements can't be empty");
+		}
+		if element.starts_with(|c: char| c.is_ascii_digit()) {
+			return invalid("elements can't start with a digit");
+		}
+		if let Some(c) = element
+			.chars()
+			.find(|&c| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+		{
+			return invalid(&format!("contains invalid character {c:?}"));
+		}
+	}
+	Ok(())
+}
+
+/// Checks an object path against the rules of the D-Bus specification.
+pub(crate) fn validate_path(path: &str) -> Result<(), dbus::Error> {
+	dbus::Path::new(path)
+		.map(drop)
+		.map_err(|e| invalid_args(&format!("invalid object path {path:?}: {e}")))
+}
+
+fn invalid_args(message: &str) -> dbus::Error {
+	dbus::Error::new_custom("org.freedesktop.DBus.Error.InvalidArgs", message)
+}
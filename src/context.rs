@@ -0,0 +1,263 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Information about the D-Bus call a runner is currently handling.
+///
+/// This is passed to [`matches`](crate::Runner::matches) and
+/// [`run`](crate::Runner::run), and can be used to tell apart different
+/// callers, or to correlate a `run` call with the `matches` call before it.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RunnerContext {
+	sender: Option<String>,
+	serial: Option<u32>,
+	path: String,
+	locale: Option<String>,
+	raw_query: Option<String>,
+	trigger_words: Vec<String>,
+	cache: MatchCache,
+}
+
+/// Values cached by a runner for its matches, keyed by match ID.
+///
+/// Runners can store whatever they computed for a match in
+/// [`matches`](crate::Runner::matches), and get it back in
+/// [`run`](crate::Runner::run) instead of parsing it back out of the match ID.
+/// The cache is cleared before every query, and when KRunner tears the runner
+/// down (unless its [teardown policy](crate::Runner::teardown_policy) is
+/// [`KeepCache`](crate::TeardownPolicy::KeepCache)), so it only ever holds
+/// values for the matches of the latest query.
+///
+/// Each runner object has its own cache. Clones of a cache share the same
+/// values.
+///
+/// # Example
+/// ```
+/// use krunner::RunnerContext;
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// struct Package {
+/// 	name: String,
+/// 	version: String,
+/// }
+///
+/// let ctx = RunnerContext::default();
+///
+/// // In `matches`
+/// ctx.cache().insert("hello", Package {
+/// 	name: "hello".to_owned(),
+/// 	version: "2.12".to_owned(),
+/// });
+///
+/// // In `run`
+/// let package: Package = ctx.cache().get("hello").unwrap();
+/// assert_eq!(package.version, "2.12");
+/// ```
+#[derive(Clone, Default)]
+pub struct MatchCache(Arc<Mutex<HashMap<String, Box<dyn Any + Send>>>>);
+
+impl MatchCache {
+	fn lock(&self) -> MutexGuard<'_, HashMap<String, Box<dyn Any + Send>>> {
+		self.0.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Caches a value for the match with the given ID, replacing any value
+	/// cached for it before.
+	pub fn insert<T: Any + Send>(&self, match_id: impl Into<String>, value: T) {
+		self.lock().insert(match_id.into(), Box::new(value));
+	}
+
+	/// Returns a copy of the value cached for the match with the given ID, if
+	/// there is one of type `T`.
+	#[must_use]
+	pub fn get<T: Any + Clone>(&self, match_id: &str) -> Option<T> {
+		self.lock().get(match_id)?.downcast_ref().cloned()
+	}
+
+	/// Removes and returns the value cached for the match with the given ID,
+	/// if there is one of type `T`.
+	#[must_use]
+	pub fn take<T: Any>(&self, match_id: &str) -> Option<T> {
+		let mut cache = self.lock();
+		if !cache.get(match_id)?.is::<T>() {
+			return None;
+		}
+		cache.remove(match_id)?.downcast().ok().map(|v| *v)
+	}
+
+	/// Removes all cached values.
+	pub fn clear(&self) {
+		self.lock().clear();
+	}
+}
+
+impl fmt::Debug for MatchCache {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("MatchCache")
+			.field("len", &self.lock().len())
+			.finish()
+	}
+}
+
+/// Caches are equal if they share the same values.
+impl PartialEq for MatchCache {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+}
+impl Eq for MatchCache {}
+
+impl RunnerContext {
+	pub(crate) fn new(ctx: &dbus_crossroads::Context) -> Self {
+		let msg = ctx.message();
+		Self::from_parts(
+			msg.sender().map(|s| s.to_string()),
+			msg.get_serial(),
+			ctx.path().to_string(),
+		)
+	}
+
+	pub(crate) fn from_parts(sender: Option<String>, serial: Option<u32>, path: String) -> Self {
+		Self {
+			sender,
+			serial,
+			path,
+			locale: session_locale(),
+			raw_query: None,
+			trigger_words: vec![],
+			cache: MatchCache::default(),
+		}
+	}
+
+	pub(crate) fn with_raw_query(mut self, query: String) -> Self {
+		self.raw_query = Some(query);
+		self
+	}
+
+	pub(crate) fn with_cache(mut self, cache: MatchCache) -> Self {
+		self.cache = cache;
+		self
+	}
+
+	/// Sets the [locale](Self::locale) of this context.
+	///
+	/// This is mostly useful for testing localized runners with a
+	/// [`TestHarness`](crate::testing::TestHarness).
+	///
+	/// # Example
+	/// ```
+	/// use krunner::RunnerContext;
+	///
+	/// let ctx = RunnerContext::default().with_locale("de_DE");
+	/// assert_eq!(ctx.locale(), Some("de_DE"));
+	/// ```
+	#[must_use]
+	pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+		self.locale = Some(locale.into());
+		self
+	}
+
+	/// Sets the [trigger words](Self::without_trigger_word) of this context.
+	///
+	/// These are normally taken from the [`MatchFilter::Keywords`] of the
+	/// runner's config, so this is mostly useful for testing.
+	///
+	/// [`MatchFilter::Keywords`]: crate::MatchFilter::Keywords
+	#[must_use]
+	pub fn with_trigger_words<S: Into<String>>(
+		mut self,
+		words: impl IntoIterator<Item = S>,
+	) -> Self {
+		self.trigger_words = words.into_iter().map(Into::into).collect();
+		self
+	}
+
+	/// The unique bus name of the caller (e.g. `:1.42`), if known.
+	#[must_use]
+	pub fn sender(&self) -> Option<&str> {
+		self.sender.as_deref()
+	}
+
+	/// The serial number of the message being handled, if known.
+	///
+	/// Serial numbers are assigned by the caller, and are only unique per
+	/// caller.
+	#[must_use]
+	pub fn serial(&self) -> Option<u32> {
+		self.serial
+	}
+
+	/// The object path the call was made on (e.g. `/SomeRunner`).
+	#[must_use]
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// The [cache](MatchCache) of the runner handling the call.
+	#[must_use]
+	pub fn cache(&self) -> &MatchCache {
+		&self.cache
+	}
+
+	/// The query as KRunner sent it, if this is a call to
+	/// [`matches`](crate::Runner::matches).
+	///
+	/// This always includes the trigger word, even if the query passed to
+	/// `matches` has it [stripped](crate::Config::strip_trigger_words).
+	#[must_use]
+	pub fn raw_query(&self) -> Option<&str> {
+		self.raw_query.as_deref()
+	}
+
+	/// Removes the trigger word the query starts with, along with any
+	/// whitespace following it, if there is one.
+	///
+	/// The trigger words are the keywords of the runner's
+	/// [`MatchFilter::Keywords`], and are compared case-insensitively, like
+	/// KRunner does. A trigger word only counts as a whole word, i.e. when it
+	/// is followed by whitespace or the end of the query. The config is read
+	/// before the first query, so this works from then on.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::RunnerContext;
+	///
+	/// let ctx = RunnerContext::default().with_trigger_words(["calc", "="]);
+	/// assert_eq!(ctx.without_trigger_word("Calc 1 + 1"), "1 + 1");
+	/// assert_eq!(ctx.without_trigger_word("= 2*3"), "2*3");
+	/// assert_eq!(ctx.without_trigger_word("calculus"), "calculus");
+	/// assert_eq!(ctx.without_trigger_word("1 + 1"), "1 + 1");
+	/// ```
+	///
+	/// [`MatchFilter::Keywords`]: crate::MatchFilter::Keywords
+	#[must_use]
+	pub fn without_trigger_word<'q>(&self, query: &'q str) -> &'q str {
+		crate::state::strip_trigger_word(query, &self.trigger_words)
+	}
+
+	/// The locale the results should be presented in (e.g. `de_DE`), if known.
+	///
+	/// D-Bus calls don't carry a locale of their own, so this is the locale of
+	/// the session as set by the `LC_ALL`, `LC_MESSAGES` and `LANG` environment
+	/// variables, with any codeset (e.g. `.UTF-8`) removed.
+	#[must_use]
+	pub fn locale(&self) -> Option<&str> {
+		self.locale.as_deref()
+	}
+}
+
+fn session_locale() -> Option<String> {
+	["LC_ALL", "LC_MESSAGES", "LANG"]
+		.into_iter()
+		.filter_map(|var| std::env::var(var).ok())
+		.find(|value| !value.is_empty())
+		.map(|value| match value.split_once('.') {
+			// Keep any modifier, e.g. `sr_RS.UTF-8@latin` -> `sr_RS@latin`
+			Some((name, codeset)) => match codeset.find('@') {
+				Some(at) => format!("{name}{}", &codeset[at..]),
+				None => name.to_owned(),
+			},
+			None => value,
+		})
+}
@@ -0,0 +1,182 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use dbus::MethodErr;
+
+/// Errors that can be returned by [runners](crate::Runner).
+///
+/// Besides a message, every error has a [kind](ErrorKind), which decides the
+/// D-Bus error name that KRunner receives. Types that don't override
+/// [`kind`](Self::kind) are reported as [`ErrorKind::Failed`].
+///
+/// # Example
+/// ```
+/// use krunner::{ErrorKind, RunnerError};
+///
+/// #[derive(Debug)]
+/// enum Error {
+/// 	Offline,
+/// 	BadQuery(String),
+/// }
+///
+/// impl std::fmt::Display for Error {
+/// 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// 		match self {
+/// 			Self::Offline => f.write_str("no network connection"),
+/// 			Self::BadQuery(q) => write!(f, "cannot understand {q:?}"),
+/// 		}
+/// 	}
+/// }
+///
+/// impl RunnerError for Error {
+/// 	fn kind(&self) -> ErrorKind {
+/// 		match self {
+/// 			Self::Offline => ErrorKind::Failed,
+/// 			Self::BadQuery(_) => ErrorKind::InvalidArgs,
+/// 		}
+/// 	}
+/// }
+/// ```
+pub trait RunnerError: Display {
+	/// The kind of this error.
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::Failed
+	}
+}
+
+/// The kind of a [`RunnerError`], which maps to a D-Bus error name.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+	/// A generic failure (`org.freedesktop.DBus.Error.Failed`).
+	Failed,
+	/// The query, match or action was invalid
+	/// (`org.freedesktop.DBus.Error.InvalidArgs`).
+	InvalidArgs,
+	/// The request isn't supported by the runner
+	/// (`org.freedesktop.DBus.Error.NotSupported`).
+	NotSupported,
+	/// The runner wasn't allowed to do what was requested
+	/// (`org.freedesktop.DBus.Error.AccessDenied`).
+	AccessDenied,
+	/// An operation took too long (`org.freedesktop.DBus.Error.Timeout`).
+	Timeout,
+	/// An error with a custom D-Bus error name (e.g.
+	/// `com.example.MyRunner.Error.Offline`).
+	Custom(Cow<'static, str>),
+}
+
+impl ErrorKind {
+	/// The D-Bus error name for this kind of error.
+	#[must_use]
+	pub fn error_name(&self) -> &str {
+		match self {
+			Self::Failed => "org.freedesktop.DBus.Error.Failed",
+			Self::InvalidArgs => "org.freedesktop.DBus.Error.InvalidArgs",
+			Self::NotSupported => "org.freedesktop.DBus.Error.NotSupported",
+			Self::AccessDenied => "org.freedesktop.DBus.Error.AccessDenied",
+			Self::Timeout => "org.freedesktop.DBus.Error.Timeout",
+			Self::Custom(name) => name,
+		}
+	}
+}
+
+pub(crate) fn method_err<E: RunnerError + ?Sized>(e: &E) -> MethodErr {
+	let kind = e.kind();
+	event!(warn, error = %e, kind = kind.error_name(), "runner returned an error");
+	// Fall back to a generic failure rather than panicking on invalid names
+	match dbus::strings::ErrorName::new(kind.error_name()) {
+		Ok(name) => MethodErr::from((name.into_static(), e.to_string())),
+		Err(_) => MethodErr::failed(e),
+	}
+}
+
+/// Turns an error reply into an error for whoever started the runner.
+pub(crate) fn dbus_err(e: &MethodErr) -> dbus::Error {
+	dbus::Error::new_custom(e.errorname().clone(), e.description())
+}
+
+thread_local! {
+	/// Where the last panic on this thread happened, as recorded by the hook
+	/// installed by [`install_panic_hook`].
+	static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook that records where panics happen, so that the
+/// error replies and logs for runners that panicked can include it.
+///
+/// Runners that panic are always replied to with an error rather than taking
+/// down the whole service, but the location of a panic is only known to the
+/// panic hook. As the hook is shared by the whole process, this is opt-in, and
+/// best called early in `main`. The hook that was installed before is still
+/// called for every panic, and calling this more than once has no effect.
+pub fn install_panic_hook() {
+	static HOOK: Once = Once::new();
+	HOOK.call_once(|| {
+		let prev = panic::take_hook();
+		panic::set_hook(Box::new(move |info| {
+			let location = info.location().map(ToString::to_string);
+			PANIC_LOCATION.with(|l| *l.borrow_mut() = location);
+			prev(info);
+		}));
+	});
+}
+
+/// Runs `f`, turning a panic into an error reply instead of unwinding.
+pub(crate) fn catch_unwind<T>(f: impl FnOnce() -> T) -> Result<T, MethodErr> {
+	take_panic_location();
+	panic::catch_unwind(AssertUnwindSafe(f))
+		.map_err(|payload| panic_err(&*payload, take_panic_location().as_deref()))
+}
+
+/// Takes the location of the last panic on this thread, if it was recorded.
+pub(crate) fn take_panic_location() -> Option<String> {
+	PANIC_LOCATION.with(|l| l.borrow_mut().take())
+}
+
+/// Resumes a panic caught on another thread, keeping its location.
+#[cfg(feature = "tokio")]
+pub(crate) fn resume_unwind_at(payload: Box<dyn Any + Send>, location: Option<String>) -> ! {
+	PANIC_LOCATION.with(|l| *l.borrow_mut() = location);
+	panic::resume_unwind(payload)
+}
+
+/// Turns a panic caught while handling a method call into an error reply.
+fn panic_err(payload: &(dyn Any + Send), location: Option<&str>) -> MethodErr {
+	let msg = payload
+		.downcast_ref::<&str>()
+		.copied()
+		.or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+		.unwrap_or("unknown panic");
+	event!(error, panic = msg, location, "runner panicked");
+	match location {
+		Some(location) => MethodErr::failed(&format!("runner panicked at {location}: {msg}")),
+		None => MethodErr::failed(&format!("runner panicked: {msg}")),
+	}
+}
+
+impl RunnerError for String {}
+impl RunnerError for &str {}
+impl RunnerError for Cow<'_, str> {}
+impl RunnerError for fmt::Error {}
+impl RunnerError for Box<dyn Error> {}
+impl RunnerError for Box<dyn Error + Send + Sync> {}
+impl RunnerError for Infallible {}
+impl RunnerError for io::Error {
+	fn kind(&self) -> ErrorKind {
+		match io::Error::kind(self) {
+			io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => ErrorKind::InvalidArgs,
+			io::ErrorKind::Unsupported => ErrorKind::NotSupported,
+			io::ErrorKind::PermissionDenied => ErrorKind::AccessDenied,
+			io::ErrorKind::TimedOut => ErrorKind::Timeout,
+			_ => ErrorKind::Failed,
+		}
+	}
+}
@@ -0,0 +1,520 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use dbus::blocking::Connection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::message::MatchRule;
+use dbus::strings::BusName;
+use dbus::{Message, MethodErr};
+use dbus_crossroads::{Crossroads, IfaceToken};
+
+use crate::{NameFlags, RunnerExt};
+
+/// Hosts several runners on a single D-Bus connection and service name.
+///
+/// Each runner is served on its own object path, which is what KRunner uses
+/// to tell them apart. This is useful when shipping a suite of related runners
+/// (e.g. a unit converter and a calculator) that would rather share one
+/// process.
+///
+/// # Example
+/// ```ignore
+/// use krunner::RunnerHost;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// 	RunnerHost::new("some.runner.suite")
+/// 		.with_runner("/Units", UnitRunner)
+/// 		.with_runner("/Calculator", CalcRunner)
+/// 		.start()?;
+/// 	Ok(())
+/// }
+/// ```
+pub struct RunnerHost {
+	service: &'static str,
+	name_flags: NameFlags,
+	cr: Crossroads,
+	tokens: HashMap<TypeId, Box<dyn Any>>,
+	startups: Vec<Startup>,
+	teardowns: Vec<Teardown>,
+	/// The first invalid object path a runner was added with, reported when
+	/// the host is started.
+	invalid_path: Option<&'static str>,
+}
+
+type Startup = Box<dyn FnOnce(&mut Crossroads) -> Result<(), dbus::Error> + Send>;
+type Teardown = Box<dyn FnOnce(&mut Crossroads) + Send>;
+
+/// Runners attached to a D-Bus connection driven by the caller, returned by
+/// [`RunnerHost::attach`].
+///
+/// Dropping this without calling [`detach`](Self::detach) keeps the runners
+/// attached for as long as the connection lives.
+pub struct AttachedHost {
+	receive: dbus::channel::Token,
+	cr: Arc<Mutex<Crossroads>>,
+	teardowns: Vec<Teardown>,
+}
+
+/// A handle to runners served on a background thread, returned by
+/// [`RunnerHost::spawn`].
+///
+/// Dropping the handle leaves the runners running until the process exits;
+/// call [`stop`](Self::stop) to shut them down.
+#[derive(Debug)]
+pub struct RunnerHandle {
+	stop: Arc<AtomicBool>,
+	thread: JoinHandle<Result<(), dbus::Error>>,
+}
+
+impl RunnerHost {
+	/// Creates a host that will request the given service name.
+	#[must_use]
+	pub fn new(service: &'static str) -> Self {
+		Self {
+			service,
+			name_flags: NameFlags::default(),
+			cr: Crossroads::new(),
+			tokens: HashMap::new(),
+			startups: vec![],
+			teardowns: vec![],
+			invalid_path: None,
+		}
+	}
+
+	/// Sets how the service name is requested. Defaults to
+	/// [`NameFlags::default`].
+	#[must_use]
+	pub fn with_name_flags(mut self, name_flags: NameFlags) -> Self {
+		self.name_flags = name_flags;
+		self
+	}
+
+	/// Adds a runner, served on the given object path.
+	///
+	/// If the path isn't a valid D-Bus object path (e.g. because it doesn't
+	/// start with `/`), the runner isn't added, and starting the host fails
+	/// with an `org.freedesktop.DBus.Error.InvalidArgs` error instead.
+	#[must_use]
+	pub fn with_runner<R: RunnerExt>(self, path: &'static str, runner: R) -> Self {
+		self.with_runner_interfaces(path, runner, [])
+	}
+
+	/// Adds a runner, served on the given object path alongside additional
+	/// interfaces.
+	///
+	/// The interfaces have to be registered beforehand with the
+	/// [`Crossroads`] instance returned by [`crossroads`](Self::crossroads).
+	/// This is useful for exposing e.g. a debug or control interface on the
+	/// same object as the `org.kde.krunner1` interface.
+	///
+	/// # Example
+	/// ```no_run
+	/// use krunner::{Match, Runner, RunnerContext, RunnerHost};
+	///
+	/// #[derive(Default)]
+	/// struct Counter {
+	/// 	queries: u32,
+	/// }
+	///
+	/// impl Runner for Counter {
+	/// 	type Action = std::convert::Infallible;
+	/// 	type Err = String;
+	///
+	/// 	fn matches(
+	/// 		&mut self,
+	/// 		_: String,
+	/// 		_: &RunnerContext,
+	/// 	) -> Result<Vec<Match<Self::Action>>, String> {
+	/// 		self.queries += 1;
+	/// 		Ok(vec![])
+	/// 	}
+	///
+	/// 	fn run(
+	/// 		&mut self,
+	/// 		_: String,
+	/// 		_: Option<Self::Action>,
+	/// 		_: &RunnerContext,
+	/// 	) -> Result<(), String> {
+	/// 		Ok(())
+	/// 	}
+	/// }
+	///
+	/// let mut host = RunnerHost::new("some.runner.path");
+	/// let stats = host.crossroads().register("some.runner.Stats", |b| {
+	/// 	b.property("Queries")
+	/// 		.get(|_, runner: &mut Counter| Ok(runner.queries));
+	/// });
+	/// host.with_runner_interfaces("/Counter", Counter::default(), [stats])
+	/// 	.start()?;
+	/// # Ok::<(), dbus::Error>(())
+	/// ```
+	#[must_use]
+	pub fn with_runner_interfaces<R: RunnerExt>(
+		mut self,
+		path: &'static str,
+		runner: R,
+		interfaces: impl IntoIterator<Item = IfaceToken<R>>,
+	) -> Self {
+		if crate::builder::validate_path(path).is_err() {
+			self.invalid_path.get_or_insert(path);
+			return self;
+		}
+
+		// Runners of the same type share the same interface registration
+		let ty = TypeId::of::<R>();
+		let token = if let Some(token) = self
+			.tokens
+			.get(&ty)
+			.and_then(|t| t.downcast_ref::<IfaceToken<R>>())
+		{
+			*token
+		} else {
+			let token = R::register(&mut self.cr);
+			self.tokens.insert(ty, Box::new(token));
+			token
+		};
+
+		let tokens: Vec<_> = std::iter::once(token).chain(interfaces).collect();
+		self.cr.insert(path, &tokens, runner);
+
+		self.startups
+			.push(Box::new(move |cr| match cr.data_mut::<R>(&path.into()) {
+				Some(runner) => runner
+					.startup()
+					.map_err(|e| crate::dbus_err(&crate::method_err(&e))),
+				None => Ok(()),
+			}));
+		self.teardowns.push(Box::new(move |cr| {
+			if let Some(runner) = cr.data_mut::<R>(&path.into()) {
+				// We're shutting down either way, so there's nobody left to
+				// report the error to
+				let _ = runner.teardown();
+			}
+		}));
+		self
+	}
+
+	/// Returns the [`Crossroads`] instance runners are registered with, e.g.
+	/// to register [additional interfaces](Self::with_runner_interfaces).
+	pub fn crossroads(&mut self) -> &mut Crossroads {
+		&mut self.cr
+	}
+
+	/// Starts serving all added runners on the main thread indefinitely.
+	///
+	/// This starts a new D-Bus connection, requests the service name, calls
+	/// [`startup`](crate::Runner::startup) on every runner, and starts
+	/// indefinitely listening on the session bus.
+	///
+	/// With the `signal` feature enabled, receiving SIGTERM or SIGINT instead
+	/// calls [`teardown`](crate::Runner::teardown) on every runner and returns.
+	/// A second signal terminates the process immediately.
+	///
+	/// # Errors
+	/// Returns an `org.freedesktop.DBus.Error.InvalidArgs` error before
+	/// connecting if the service name or the object path of any runner is
+	/// invalid, and any error from connecting to the bus or serving the runners
+	/// otherwise.
+	pub fn start(self) -> Result<(), dbus::Error> {
+		self.validate()?;
+		self.start_on(&Connection::new_session()?)
+	}
+
+	/// Starts serving all added runners on the given connection.
+	///
+	/// This is the same as [`start`](Self::start), except that it uses an
+	/// existing connection, e.g. one shared with other parts of the
+	/// application, or one to a different bus.
+	pub fn start_on(mut self, c: &Connection) -> Result<(), dbus::Error> {
+		self.validate()?;
+		self.name_flags.request(c, self.service)?;
+		startup(&mut self.cr, self.startups)?;
+
+		#[cfg(not(feature = "signal"))]
+		return serve_until(self.cr, self.teardowns, c, &AtomicBool::new(false));
+
+		#[cfg(feature = "signal")]
+		{
+			let term = shutdown_flag()
+				.map_err(|e| dbus::Error::new_failed(&format!("cannot handle signals: {e}")))?;
+			serve_until(self.cr, self.teardowns, c, &term)
+		}
+	}
+
+	/// Requests the service name and starts handling calls to all added
+	/// runners on the given connection, without waiting for them.
+	///
+	/// Unlike [`start_on`](Self::start_on), this returns right away, and the
+	/// caller is responsible for driving the connection, e.g. with
+	/// [`Connection::process`]. This allows the connection to be used for
+	/// other purposes in the same event loop.
+	///
+	/// # Example
+	/// ```ignore
+	/// use std::time::Duration;
+	///
+	/// use dbus::blocking::Connection;
+	/// use krunner::RunnerHost;
+	///
+	/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// 	let c = Connection::new_session()?;
+	/// 	let runners = RunnerHost::new("some.runner.path")
+	/// 		.with_runner("/SomeRunner", SomeRunner)
+	/// 		.attach(&c)?;
+	///
+	/// 	while !should_quit() {
+	/// 		c.process(Duration::from_millis(200))?;
+	/// 		do_other_work(&c);
+	/// 	}
+	/// 	runners.detach(&c);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn attach(mut self, c: &Connection) -> Result<AttachedHost, dbus::Error> {
+		self.validate()?;
+		self.name_flags.request(c, self.service)?;
+		startup(&mut self.cr, self.startups)?;
+		Ok(AttachedHost::new(self.cr, self.teardowns, c))
+	}
+
+	/// Starts serving all added runners on a new thread, and returns a handle
+	/// to stop them with.
+	///
+	/// This starts a new D-Bus connection on the session bus, requests the
+	/// service name and calls [`startup`](crate::Runner::startup) on every
+	/// runner before returning, so that errors doing so are reported right
+	/// away. Unlike [`start`](Self::start), signals are left alone even
+	/// with the `signal` feature enabled, as they belong to the application
+	/// embedding the runners.
+	///
+	/// # Example
+	/// ```ignore
+	/// use krunner::RunnerHost;
+	///
+	/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+	/// 	let handle = RunnerHost::new("some.runner.path")
+	/// 		.with_runner("/SomeRunner", SomeRunner)
+	/// 		.spawn()?;
+	///
+	/// 	run_the_rest_of_the_app();
+	///
+	/// 	handle.stop()?;
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn spawn(self) -> Result<RunnerHandle, dbus::Error> {
+		self.validate()?;
+
+		// The interface tokens are only needed to add runners, and can't be
+		// sent to another thread
+		let Self {
+			service,
+			name_flags,
+			mut cr,
+			startups,
+			teardowns,
+			..
+		} = self;
+		let stop = Arc::new(AtomicBool::new(false));
+		let (started, on_start) = mpsc::channel();
+
+		let flag = Arc::clone(&stop);
+		let thread = std::thread::Builder::new()
+			.name(format!("krunner {service}"))
+			.spawn(move || {
+				let c = Connection::new_session()
+					.and_then(|c| name_flags.request(&c, service).map(|()| c))
+					.and_then(|c| startup(&mut cr, startups).map(|()| c));
+				match c {
+					Ok(c) => {
+						let _ = started.send(Ok(()));
+						serve_until(cr, teardowns, &c, &flag)
+					}
+					Err(e) => {
+						let _ = started.send(Err(e));
+						Ok(())
+					}
+				}
+			})
+			.map_err(|e| dbus::Error::new_failed(&format!("cannot spawn thread: {e}")))?;
+
+		match on_start.recv() {
+			Ok(Ok(())) => Ok(RunnerHandle { stop, thread }),
+			Ok(Err(e)) => Err(e),
+			// The thread can only hang up early by panicking
+			Err(_) => Err(RunnerHandle { stop, thread }.join().unwrap_err()),
+		}
+	}
+
+	/// Checks that the service name and the object paths of all runners are
+	/// valid.
+	fn validate(&self) -> Result<(), dbus::Error> {
+		crate::builder::validate_service(self.service)?;
+		self.invalid_path
+			.map_or(Ok(()), crate::builder::validate_path)
+	}
+}
+
+/// Calls [`startup`](crate::Runner::startup) on every runner, stopping at the
+/// first error.
+fn startup(cr: &mut Crossroads, startups: Vec<Startup>) -> Result<(), dbus::Error> {
+	startups.into_iter().try_for_each(|startup| startup(cr))
+}
+
+/// Serves all runners on the given connection until `stop` is raised, then
+/// tears them down.
+///
+/// The runners are also torn down if serving them fails or panics.
+pub(crate) fn serve_until(
+	cr: Crossroads,
+	teardowns: Vec<Teardown>,
+	c: &Connection,
+	stop: &AtomicBool,
+) -> Result<(), dbus::Error> {
+	let _attached = DetachOnDrop(Some(AttachedHost::new(cr, teardowns, c)), c);
+	while !stop.load(Ordering::Relaxed) {
+		c.process(Duration::from_millis(200))?;
+	}
+	Ok(())
+}
+
+/// Detaches runners when dropped, so that they're torn down on every way out
+/// of [`serve_until`].
+struct DetachOnDrop<'c>(Option<AttachedHost>, &'c Connection);
+
+impl Drop for DetachOnDrop<'_> {
+	fn drop(&mut self) {
+		let Some(attached) = self.0.take() else {
+			return;
+		};
+		if std::thread::panicking() {
+			// Panicking again would abort the process
+			let _ = panic::catch_unwind(AssertUnwindSafe(|| attached.detach(self.1)));
+		} else {
+			attached.detach(self.1);
+		}
+	}
+}
+
+impl AttachedHost {
+	fn new(cr: Crossroads, teardowns: Vec<Teardown>, c: &Connection) -> Self {
+		let cr = Arc::new(Mutex::new(cr));
+		let receiver = Arc::clone(&cr);
+		let receive = c.start_receive(
+			MatchRule::new_method_call(),
+			Box::new(move |msg, conn| {
+				handle_message(&mut lock(&receiver), msg, conn);
+				true
+			}),
+		);
+		Self {
+			receive,
+			cr,
+			teardowns,
+		}
+	}
+
+	/// Stops handling calls to the runners on the given connection, which
+	/// has to be the one they were attached to, and calls
+	/// [`teardown`](crate::Runner::teardown) on every runner.
+	///
+	/// The service name is kept, as the connection may still be using it.
+	pub fn detach(self, c: &Connection) {
+		// The connection may outlive us, so stop handling calls with it
+		c.stop_receive(self.receive);
+
+		let mut cr = lock(&self.cr);
+		for teardown in self.teardowns {
+			teardown(&mut cr);
+		}
+	}
+}
+
+impl fmt::Debug for AttachedHost {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("AttachedHost").finish_non_exhaustive()
+	}
+}
+
+impl RunnerHandle {
+	/// Stops serving the runners, and waits for their
+	/// [`teardown`](crate::Runner::teardown) to finish.
+	///
+	/// KRunner may have to wait for up to 200 milliseconds for the runners to
+	/// notice.
+	///
+	/// # Errors
+	/// Returns the error that stopped the runners early, if any.
+	///
+	/// # Panics
+	/// Panics if a runner panicked.
+	pub fn stop(self) -> Result<(), dbus::Error> {
+		self.stop.store(true, Ordering::Relaxed);
+		self.join()
+	}
+
+	/// Whether the runners have stopped, either because of an error or a
+	/// panic.
+	#[must_use]
+	pub fn is_finished(&self) -> bool {
+		self.thread.is_finished()
+	}
+
+	fn join(self) -> Result<(), dbus::Error> {
+		self.thread
+			.join()
+			.unwrap_or_else(|e| std::panic::resume_unwind(e))
+	}
+}
+
+/// Handles a method call with `cr`, replying with an error instead of
+/// unwinding if a runner panics.
+///
+/// Without this, a bug in a single runner would take down the whole service.
+pub(crate) fn handle_message(cr: &mut Crossroads, msg: Message, conn: &impl Sender) {
+	// `msg` is handed over, so only keep what an error reply needs
+	let serial = msg.get_serial().filter(|_| !msg.get_no_reply());
+	let sender = msg.sender().map(BusName::into_static);
+	// This only fails for messages other than method calls, which are
+	// filtered out by the match rules of all callers
+	if let Err(err) = crate::catch_unwind(|| cr.handle_message(msg, conn)) {
+		if let Some(reply) = serial.and_then(|serial| error_reply(serial, sender, &err)) {
+			let _ = conn.send(reply);
+		}
+	}
+}
+
+/// Builds an error reply to the call with the given serial from `sender`.
+fn error_reply(serial: u32, sender: Option<BusName<'static>>, err: &MethodErr) -> Option<Message> {
+	// Error replies only refer to the serial and sender of the call, so an
+	// empty message with both stands in for it
+	let mut call = Message::new_signal("/", crate::KRUNNER_INTERFACE, "Panicked").ok()?;
+	call.set_serial(serial);
+	call.set_sender(sender);
+	Some(err.to_message(&call))
+}
+
+/// Registers a flag that is raised on SIGTERM or SIGINT.
+#[cfg(feature = "signal")]
+fn shutdown_flag() -> std::io::Result<Arc<AtomicBool>> {
+	use signal_hook::consts::{SIGINT, SIGTERM};
+	use signal_hook::flag;
+
+	let term = Arc::new(AtomicBool::new(false));
+	for sig in [SIGTERM, SIGINT] {
+		// Registered first, so that it only fires on the second signal
+		flag::register_conditional_shutdown(sig, 1, Arc::clone(&term))?;
+		flag::register(sig, Arc::clone(&term))?;
+	}
+	Ok(term)
+}
+
+fn lock(cr: &Mutex<Crossroads>) -> std::sync::MutexGuard<'_, Crossroads> {
+	cr.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::{env, fs};
+
+/// The file extensions icon themes use, in order of preference.
+const EXTENSIONS: [&str; 4] = ["svg", "svgz", "png", "xpm"];
+
+/// Finds the file of the icon with the given name in the current icon theme,
+/// following the [freedesktop.org icon theme
+/// specification](https://specifications.freedesktop.org/icon-theme-spec/latest/).
+///
+/// The current theme is read from KDE's `kdeglobals`, and defaults to Breeze.
+/// Themes it inherits from, `hicolor` and `/usr/share/pixmaps` are searched
+/// as well. Icon sizes are not taken into account, since KRunner scales
+/// icons as needed.
+///
+/// The directories of the theme are only read once, when this is first
+/// called, and the result of each lookup is remembered until KRunner next
+/// tears the runner down, so icons installed in the meantime may not be
+/// found.
+///
+/// # Example
+/// ```no_run
+/// match krunner::lookup_icon("firefox") {
+/// 	Some(path) => println!("found Firefox at {}", path.display()),
+/// 	None => println!("Firefox has no icon"),
+/// }
+/// ```
+#[must_use]
+pub fn lookup_icon(name: &str) -> Option<PathBuf> {
+	static DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+	// Names with path separators would escape the icon directories
+	if name.is_empty() || name.contains('/') {
+		return None;
+	}
+	if let Some(found) = lookups().get(name) {
+		return found.clone();
+	}
+	let found = DIRS.get_or_init(icon_dirs).iter().find_map(|dir| {
+		EXTENSIONS
+			.iter()
+			.map(|ext| dir.join(format!("{name}.{ext}")))
+			.find(|path| path.is_file())
+	});
+	lookups().insert(name.to_owned(), found.clone());
+	found
+}
+
+/// Forgets the results of earlier lookups, so that icons are looked up on
+/// the filesystem again.
+pub(crate) fn clear_lookups() {
+	lookups().clear();
+}
+
+/// The results of earlier lookups, keyed by icon name.
+fn lookups() -> MutexGuard<'static, HashMap<String, Option<PathBuf>>> {
+	static LOOKUPS: OnceLock<Mutex<HashMap<String, Option<PathBuf>>>> = OnceLock::new();
+	LOOKUPS
+		.get_or_init(Mutex::default)
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Picks the first of the given icon names that exists, or the first one if
+/// none of them do.
+pub(crate) fn pick_icon(names: &[String]) -> &str {
+	names
+		.iter()
+		.find(|name| lookup_icon(name).is_some())
+		.or(names.first())
+		.map_or("", String::as_str)
+}
+
+/// Every directory that may contain icons of the current theme, in the order
+/// they should be searched.
+fn icon_dirs() -> Vec<PathBuf> {
+	let bases = base_dirs();
+
+	let mut themes = vec![current_theme()];
+	let mut dirs = vec![];
+	let mut i = 0;
+	while let Some(theme) = themes.get(i).cloned() {
+		i += 1;
+		for base in &bases {
+			let root = base.join(&theme);
+			let Some(index) = read_index(&root.join("index.theme")) else {
+				continue;
+			};
+			if let Some(subdirs) = index.get("Directories") {
+				dirs.extend(list(subdirs).map(|dir| root.join(dir)));
+			}
+			for parent in index.get("Inherits").map(|s| list(s)).into_iter().flatten() {
+				if !themes.iter().any(|t| t == parent) {
+					themes.push(parent.to_owned());
+				}
+			}
+		}
+		if i == themes.len() && !themes.iter().any(|t| t == "hicolor") {
+			themes.push("hicolor".to_owned());
+		}
+	}
+	dirs.push("/usr/share/pixmaps".into());
+	dirs
+}
+
+/// The directories icon themes are installed in.
+fn base_dirs() -> Vec<PathBuf> {
+	let home = env::var_os("HOME").map(PathBuf::from);
+	let data_home = env::var_os("XDG_DATA_HOME")
+		.map(PathBuf::from)
+		.or_else(|| home.as_ref().map(|home| home.join(".local/share")));
+	let data_dirs = env::var("XDG_DATA_DIRS")
+		.ok()
+		.filter(|dirs| !dirs.is_empty())
+		.unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+
+	let mut bases = vec![];
+	bases.extend(home.map(|home| home.join(".icons")));
+	bases.extend(data_home.map(|dir| dir.join("icons")));
+	bases.extend(data_dirs.split(':').map(|dir| Path::new(dir).join("icons")));
+	bases
+}
+
+/// The name of the icon theme chosen in KDE's settings.
+fn current_theme() -> String {
+	let config_home = env::var_os("XDG_CONFIG_HOME")
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+	config_home
+		.and_then(|dir| fs::read_to_string(dir.join("kdeglobals")).ok())
+		.and_then(|globals| {
+			let mut in_icons = false;
+			globals.lines().find_map(|line| {
+				let line = line.trim();
+				if line.starts_with('[') {
+					in_icons = line == "[Icons]";
+					return None;
+				}
+				let (key, value) = line.split_once('=')?;
+				(in_icons && key.trim() == "Theme").then(|| value.trim().to_owned())
+			})
+		})
+		.unwrap_or_else(|| "breeze".to_owned())
+}
+
+/// Reads the keys of the `[Icon Theme]` group of an `index.theme` file.
+fn read_index(path: &Path) -> Option<HashMap<String, String>> {
+	let index = fs::read_to_string(path).ok()?;
+	let mut in_theme = false;
+	let mut keys = HashMap::new();
+	for line in index.lines().map(str::trim) {
+		if line.starts_with('[') {
+			in_theme = line == "[Icon Theme]";
+		} else if let Some((key, value)) = line.split_once('=').filter(|_| in_theme) {
+			keys.insert(key.trim().to_owned(), value.trim().to_owned());
+		}
+	}
+	Some(keys)
+}
+
+fn list(s: &str) -> impl Iterator<Item = &str> {
+	s.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
@@ -0,0 +1,90 @@
+use std::io::Cursor;
+use std::num::TryFromIntError;
+
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{DynamicImage, ImageError, ImageResult, RgbaImage};
+
+use crate::{ImageData, ImageFormat};
+
+/// Converts a decoded image into icon data.
+///
+/// Images with an alpha channel become [`Argb32`](ImageFormat::Argb32)
+/// images, and the rest become [`Rgb32`](ImageFormat::Rgb32) images, since
+/// KRunner reads image data without an alpha channel as 3 bytes per pixel.
+/// Either way, channels are 8 bits wide, and in the order KRunner expects.
+///
+/// Fails if the image is too large for its dimensions to be sent to KRunner.
+///
+/// # Example
+/// ```
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// use krunner::{ImageData, ImageFormat};
+///
+/// let image = RgbaImage::from_pixel(2, 2, Rgba([0xff, 0x00, 0x00, 0x80]));
+/// let data = ImageData::try_from(DynamicImage::ImageRgba8(image))?;
+/// assert_eq!(data.format, ImageFormat::Argb32);
+/// assert_eq!(data.row_stride, 8);
+/// # Ok::<(), std::num::TryFromIntError>(())
+/// ```
+impl TryFrom<DynamicImage> for ImageData {
+	type Error = TryFromIntError;
+
+	fn try_from(image: DynamicImage) -> Result<Self, Self::Error> {
+		let width = i32::try_from(image.width())?;
+		let height = i32::try_from(image.height())?;
+		let has_alpha = image.color().has_alpha();
+
+		let (format, channels, data) = if has_alpha {
+			(ImageFormat::Argb32, 4, image.into_rgba8().into_raw())
+		} else {
+			(ImageFormat::Rgb32, 3, image.into_rgb8().into_raw())
+		};
+
+		Ok(Self {
+			width,
+			height,
+			row_stride: width.checked_mul(channels).ok_or_else(overflow)?,
+			has_alpha,
+			format,
+			data,
+		})
+	}
+}
+
+impl ImageData {
+	/// Encodes this image as PNG, e.g. to check what an icon looks like, or to
+	/// cache it on disk.
+	///
+	/// Fails if the dimensions of the image are negative, or if encoding fails.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::ImageData;
+	///
+	/// let png = ImageData::from_argb32_pixels(1, 1, &[0xffff_0000]).to_png_bytes()?;
+	/// assert!(png.starts_with(b"\x89PNG"));
+	/// # Ok::<(), image::ImageError>(())
+	/// ```
+	#[cfg_attr(docs_rs, doc(cfg(feature = "image")))]
+	pub fn to_png_bytes(&self) -> ImageResult<Vec<u8>> {
+		let argb = self.to_argb32();
+		let image = u32::try_from(argb.width)
+			.ok()
+			.zip(u32::try_from(argb.height).ok())
+			.and_then(|(width, height)| RgbaImage::from_raw(width, height, argb.data))
+			.ok_or_else(|| {
+				ImageError::Parameter(ParameterError::from_kind(
+					ParameterErrorKind::DimensionMismatch,
+				))
+			})?;
+
+		let mut png = Cursor::new(vec![]);
+		image.write_to(&mut png, image::ImageFormat::Png)?;
+		Ok(png.into_inner())
+	}
+}
+
+/// Creates a [`TryFromIntError`], which can't be constructed directly.
+fn overflow() -> TryFromIntError {
+	i32::try_from(u32::MAX).unwrap_err()
+}
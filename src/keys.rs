@@ -0,0 +1,34 @@
+//! The keys of the properties that KRunner reads from replies.
+//!
+//! KRunner silently ignores properties it doesn't know, so a misspelled key
+//! doesn't fail loudly. Every marshaller uses these instead of literals.
+
+/// Keys of the properties of a match.
+pub(crate) mod matches {
+	/// The URLs of the match.
+	pub(crate) const URLS: &str = "urls";
+	/// The category of the match.
+	pub(crate) const CATEGORY: &str = "category";
+	/// The subtitle of the match.
+	pub(crate) const SUBTEXT: &str = "subtext";
+	/// Whether the match is displayed as multiline text.
+	pub(crate) const MULTILINE: &str = "multiline";
+	/// The text that replaces the query when the match is run.
+	pub(crate) const QUERY: &str = "query";
+	/// The IDs of the actions of the match.
+	pub(crate) const ACTIONS: &str = "actions";
+	/// The custom image data of the icon of the match.
+	pub(crate) const ICON_DATA: &str = "icon-data";
+}
+
+/// Keys of the properties of a runner config.
+pub(crate) mod config {
+	/// The trigger words of a [keyword filter](crate::MatchFilter::Keywords).
+	pub(crate) const TRIGGER_WORDS: &str = "TriggerWords";
+	/// The pattern of a [regex filter](crate::MatchFilter::Regex).
+	pub(crate) const MATCH_REGEX: &str = "MatchRegex";
+	/// The minimum length of a query.
+	pub(crate) const MIN_LETTER_COUNT: &str = "MinLetterCount";
+	/// The actions of the runner.
+	pub(crate) const ACTIONS: &str = "Actions";
+}
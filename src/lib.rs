@@ -11,20 +11,81 @@
 	clippy::ignored_unit_patterns
 )]
 
+/// Emits a [`tracing`] event at the given level if the `tracing` feature is
+/// enabled, and does nothing otherwise.
+macro_rules! event {
+	($level:ident, $($arg:tt)*) => {
+		#[cfg(feature = "tracing")]
+		::tracing::$level!(target: "krunner", $($arg)*);
+	};
+}
+
 #[path = "async.rs"]
 #[cfg(feature = "tokio")]
 #[cfg_attr(docs_rs, doc(cfg(feature = "tokio")))]
 mod _async;
+mod activities;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "tokio")))]
+mod adapter;
+mod binary;
+mod builder;
+mod context;
+mod error;
+mod host;
+#[cfg(feature = "icon-lookup")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "icon-lookup")))]
+mod icons;
+#[cfg(feature = "image")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "image")))]
+mod image;
+mod keys;
+mod match_builder;
+mod matching;
+mod metadata;
+#[cfg(feature = "notifications")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "notifications")))]
+mod notify;
+mod provider;
+mod settings;
+mod shared;
+mod state;
 mod sync;
+pub mod testing;
+mod usage;
+#[cfg(feature = "wasm")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "wasm")))]
+mod wasm;
+#[cfg(feature = "zbus")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "zbus")))]
+mod zbus_host;
 
 use std::any::Any;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 #[cfg(feature = "tokio")]
 pub use _async::*;
+pub use activities::current_activity;
+#[cfg(feature = "tokio")]
+pub use adapter::*;
+pub use binary::*;
+pub use builder::*;
+pub use context::*;
 use dbus::arg::{Append, Arg, ArgType, Dict, IterAppend, PropMap, RefArg, Variant};
 use dbus::Signature;
+pub use error::*;
+pub use host::*;
+#[cfg(feature = "icon-lookup")]
+pub use icons::*;
 #[cfg(feature = "derive")]
 #[cfg_attr(docs_rs, doc(cfg(feature = "derive")))]
 /// Derive macro allowing users to easily generate [`Action`s](Action) for their
@@ -35,9 +96,27 @@ use dbus::Signature;
 /// for the unique identifier of the action, `title` for the human-friendly name
 /// of the action, and `icon` for the name of the action's icon.
 ///
+/// If `title` is omitted, the first line of the variant's doc comment is used
+/// instead. Leaving out any other field is a compile error pointing at the
+/// offending variant. Generic enums and `where` clauses are supported as well.
+///
+/// At most one variant can additionally be marked with `default`, which makes
+/// it the [default action](Action::default_action).
+///
+/// The enum itself can be marked with `#[action(from_str, display)]` to also
+/// implement [`FromStr`] and [`Display`] in terms of the action IDs, for use
+/// with APIs that expect those instead. They aren't implemented by default, as
+/// that would conflict with any implementations of your own.
+///
+/// IDs are matched exactly by [`from_id`](Action::from_id). Marking the enum
+/// with `#[action(case_insensitive)]` makes it ignore case and surrounding
+/// whitespace instead, which is a compile error if two IDs only differ in
+/// those. [`to_id`](Action::to_id) still returns the IDs as written.
+///
 /// # Example
 /// ```
-/// #[derive(krunner::Action)]
+/// #[derive(Debug, PartialEq, krunner::Action)]
+/// #[action(from_str, display)]
 /// pub enum Action {
 /// 	#[action(
 /// 		id = "open-in-browser",
@@ -45,22 +124,63 @@ use dbus::Signature;
 /// 		icon = "internet-web-browser"
 /// 	)]
 /// 	OpenInBrowser,
-/// 	#[action(
-/// 		id = "save-to-folder",
-/// 		title = "Save to Folder",
-/// 		icon = "document-save-symbolic"
-/// 	)]
+/// 	/// Save to Folder
+/// 	#[action(id = "save-to-folder", icon = "document-save-symbolic")]
 /// 	SaveToFolder,
 /// }
+///
+/// assert_eq!(Action::SaveToFolder.to_string(), "save-to-folder");
+/// assert_eq!("open-in-browser".parse(), Ok(Action::OpenInBrowser));
+/// ```
+///
+/// Every variant needs an `id` and an `icon`:
+/// ```compile_fail
+/// #[derive(krunner::Action)]
+/// pub enum Action {
+/// 	#[action(title = "Open in Browser", icon = "internet-web-browser")]
+/// 	OpenInBrowser,
+/// }
+/// ```
+///
+/// Case-insensitive IDs must still be unique:
+/// ```compile_fail
+/// #[derive(krunner::Action)]
+/// #[action(case_insensitive)]
+/// pub enum Action {
+/// 	#[action(id = "open", title = "Open", icon = "document-open")]
+/// 	Open,
+/// 	#[action(id = "Open", title = "Open Again", icon = "document-open")]
+/// 	OpenAgain,
+/// }
 /// ```
 pub use krunner_derive::Action;
+pub use match_builder::*;
+pub use matching::*;
+pub use metadata::*;
+#[cfg(feature = "notifications")]
+pub use notify::*;
+pub use provider::*;
+pub use settings::*;
+pub use shared::*;
 pub use sync::*;
+pub use usage::*;
+#[cfg(feature = "wasm")]
+pub use wasm::*;
+#[cfg(feature = "zbus")]
+pub use zbus_host::*;
+
+/// The D-Bus interface KRunner calls runners through.
+///
+/// This is the default for [`Runner::INTERFACE`].
+pub const KRUNNER_INTERFACE: &str = "org.kde.krunner1";
 
 /// Trait for actions that the user can perform.
 ///
 /// # Example
 /// Using the [derive macro](derive@Action):
 /// ```
+/// # #[cfg(feature = "derive")]
+/// # mod m {
 /// #[derive(krunner::Action)]
 /// pub enum Action {
 /// 	#[action(
@@ -76,6 +196,7 @@ pub use sync::*;
 /// 	)]
 /// 	SaveToFolder,
 /// }
+/// # }
 /// ```
 ///
 /// The equivalent without using the derive macro:
@@ -131,10 +252,71 @@ pub trait Action: Sized {
 	fn to_id(&self) -> String;
 	/// Returns associated information about the action.
 	fn info(&self) -> ActionInfo;
+
+	/// Returns associated information about the action, for the call
+	/// described by `ctx`.
+	///
+	/// This is what KRunner receives when it asks a runner for its actions,
+	/// so overriding it allows e.g. icons that depend on the object path of
+	/// the runner. The `Actions` entry of the [config](Config) is built
+	/// without a context, and always uses [`info`](Self::info).
+	///
+	/// When [all actions are available](Runner::available_actions), the reply
+	/// is only built once for each object path, so the information shouldn't
+	/// change afterwards. Runners whose actions depend on their state should
+	/// return them from `available_actions` instead, which is asked on every
+	/// call.
+	///
+	/// Defaults to [`info`](Self::info).
+	fn info_dynamic(&self, ctx: &RunnerContext) -> ActionInfo {
+		let _ = ctx;
+		self.info()
+	}
+
+	/// The action to run when the user activates a match without choosing a
+	/// specific action, e.g. by pressing Enter.
+	///
+	/// When this returns [`Some`], [`Runner::run`] receives it in place of
+	/// [`None`]. By default, there is no default action.
+	#[must_use]
+	fn default_action() -> Option<Self> {
+		None
+	}
 }
 
 /// Configuration for a runner.
+///
+/// KRunner only reads the configuration that affects how queries are
+/// dispatched. Example queries for KRunner's help overlay are declared in the
+/// runner's [metadata](MetadataOptions::syntaxes) instead.
+///
+/// With the `serde` feature, configs can be loaded from and saved to files.
+/// Missing fields take their [default](Config::default) values.
+///
+/// # Example
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use krunner::{Config, MatchFilter};
+///
+/// let config: Config<std::convert::Infallible> = serde_json::from_str(
+/// 	r#"{ "match_filter": { "keywords": ["nix", "nixpkgs"] }, "max_results": 10 }"#,
+/// )?;
+/// assert_eq!(
+/// 	config.match_filter,
+/// 	Some(MatchFilter::Keywords(vec![
+/// 		"nix".to_owned(),
+/// 		"nixpkgs".to_owned()
+/// 	]))
+/// );
+/// assert_eq!(config.max_results, Some(10));
+/// # }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, bound = ""))]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config<A> {
 	/// The filter that a query must pass before attempting a match.
 	pub match_filter: Option<MatchFilter>,
@@ -142,13 +324,117 @@ pub struct Config<A> {
 	/// The minimum length a query must be before attempting a match.
 	/// Queries shorter than this minimum letter count will not be matched
 	/// against.
+	///
+	/// KRunner doesn't call [`Runner::matches`] at all for shorter queries, so
+	/// a value that is too large effectively disables the runner. Prefer
+	/// setting this with [`Config::min_letter_count`], which rejects values
+	/// that make no sense.
 	pub min_letter_count: Option<u32>,
 
+	/// Whether the trigger word should be removed from the query before it is
+	/// passed to [`Runner::matches`].
+	///
+	/// KRunner passes the query in full, trigger word included. This only has
+	/// an effect when the [match filter](Self::match_filter) is
+	/// [`MatchFilter::Keywords`].
+	pub strip_trigger_words: bool,
+
+	/// The maximum number of matches to send to KRunner for each query.
+	///
+	/// When a runner returns more matches than this, only the highest ranked
+	/// ones (by [type](Match::ty), then [relevance](Match::relevance)) are
+	/// sent. This keeps replies for runners with huge result sets small, which
+	/// KRunner's UI can't make use of anyway.
+	pub max_results: Option<usize>,
+
+	/// Whether KRunner should display matches in the order they are returned
+	/// from [`Runner::matches`], rather than purely by relevance.
+	///
+	/// KRunner always sorts matches by [type](Match::ty) and
+	/// [relevance](Match::relevance), so this works by lowering relevances
+	/// where needed with [`Match::preserve_order`]. It is useful for runners
+	/// whose results come pre-sorted, e.g. by recency. When
+	/// [`max_results`](Self::max_results) is also set, the first matches are
+	/// kept rather than the highest ranked ones.
+	pub preserve_order: bool,
+
+	/// The largest reply to a query, in bytes, that may be sent to KRunner.
+	///
+	/// D-Bus limits the size of messages, and a reply larger than that would
+	/// break the connection with an opaque error. Instead, queries whose
+	/// matches exceed this size are answered with an
+	/// `org.freedesktop.DBus.Error.LimitsExceeded` error describing the
+	/// problem. Defaults to [`Config::DEFAULT_MAX_REPLY_SIZE`] when `None`.
+	pub max_reply_size: Option<usize>,
+
+	/// Whether to remove matches with duplicate [IDs](Match::id) before they
+	/// are sent, with [`Match::dedup_by_id`].
+	///
+	/// KRunner silently drops all but one match with the same ID, which is not
+	/// necessarily the best one.
+	pub dedup_ids: bool,
+
+	/// Whether matches that KRunner would rank the same should be displayed
+	/// in a deterministic order, with [`Match::break_ties`].
+	///
+	/// Without this, matches of the same [type](Match::ty) and
+	/// [relevance](Match::relevance) may swap places as the user types. This
+	/// has no effect when [`preserve_order`](Self::preserve_order) is set,
+	/// which already makes every relevance distinct.
+	pub stable_order: bool,
+
+	/// How long to wait for the query to stop changing before matching it.
+	///
+	/// KRunner asks for matches on every keystroke. With a debounce window,
+	/// each query first waits for the window to pass, and is answered with no
+	/// matches if a newer query has arrived in the meantime, without ever
+	/// reaching `AsyncRunner::matches`. This keeps runners with expensive
+	/// queries from doing work whose results KRunner would throw away anyway.
+	///
+	/// Only asynchronous runners (with the `tokio` feature) are debounced, as
+	/// synchronous runners handle one call at a time. As with
+	/// `AsyncRunner::match_timeout`, this requires the time driver of the
+	/// Tokio runtime to be enabled.
+	pub debounce: Option<Duration>,
+
+	/// How many distinct [relevances](Match::relevance) between 0 and 1 to
+	/// send to KRunner, with [`Match::round_relevance`].
+	///
+	/// Scores computed with floating-point math often differ by tiny amounts
+	/// for results that are equally good, which makes KRunner order them
+	/// arbitrarily, and reorder them as the user types. Rounding relevances to
+	/// `n` buckets makes near-equal matches rank the same, which pairs well
+	/// with [`stable_order`](Self::stable_order) for ordering them
+	/// deterministically. This has no effect when
+	/// [`preserve_order`](Self::preserve_order) is set, as that picks
+	/// relevances itself.
+	pub relevance_buckets: Option<u32>,
+
+	/// Whether the runner should be queried at all.
+	///
+	/// A disabled runner stays registered, but every query is answered with
+	/// no matches without reaching [`Runner::matches`]. This lets runners
+	/// whose backend is unavailable (e.g. a missing program) degrade
+	/// gracefully, and come back once it is available again, as the config is
+	/// read again at the start of every KRunner session unless it is
+	/// [static](Runner::config_is_static).
+	///
+	/// Returning no matches from [`Runner::matches`] has the same effect on
+	/// KRunner, but still pays for the query, and leaves room for telling the
+	/// user what is wrong: a runner that wants to explain why it is
+	/// unavailable should stay enabled and return a single match saying so.
+	///
+	/// Defaults to `true`.
+	pub enabled: bool,
+
+	#[cfg_attr(feature = "serde", serde(skip))]
 	_phan: PhantomData<A>,
 }
 
 /// A filter that a query must pass through before attempting a match.
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum MatchFilter {
 	/// A list of keywords that, if found at the start of a query, would trigger
 	/// a match. Also known in KDE docs as "trigger words".
@@ -162,7 +448,31 @@ pub enum MatchFilter {
 	Regex(String),
 }
 
+/// What happens when KRunner ends a matching session.
+///
+/// See [`Runner::teardown_policy`] for details.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum TeardownPolicy {
+	/// Keep everything around for the next session. The runner's
+	/// [`teardown`](Runner::teardown) is not called.
+	KeepCache,
+	/// Drop the data krunner keeps for the runner across calls, i.e. its
+	/// [match cache](crate::MatchCache) and its last [config](Runner::config),
+	/// but leave the runner itself alone. The runner's
+	/// [`teardown`](Runner::teardown) is not called.
+	EvictCache,
+	/// Clear the [match cache](crate::MatchCache) and call the runner's
+	/// [`teardown`](Runner::teardown).
+	#[default]
+	Custom,
+}
+
 /// A query match.
+///
+/// Matches are best created with [`Match::builder`], which makes sure that
+/// they have a title. Struct literals (filling in the rest with
+/// [`Match::default`]) and [`Match::new`] work too, but make it easy to return
+/// blank matches by accident.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Match<A> {
 	/// The unique identifier of this match.
@@ -188,22 +498,99 @@ pub struct Match<A> {
 	/// The category of this match.
 	///
 	/// If the category is set to `None`, the name of the runner would be used
-	/// as the category instead.
+	/// as the category instead. KRunner takes that name from the `Name` key
+	/// of the `.desktop` file of the runner, i.e. from
+	/// [`MetadataOptions::name`] when it's generated with
+	/// [`write_metadata`]; the runner itself has no say in it over D-Bus.
 	pub category: Option<String>,
 	/// Whether the text should be displayed as a multiline string.
+	///
+	/// Matches whose title or subtitle contains a newline are always sent as
+	/// multiline, as KRunner would cut them off at the first line otherwise.
 	pub multiline: bool,
+	/// Text that replaces the query in the search field when this match is
+	/// run.
+	///
+	/// This is the supported way to build calculator-style matches, whose
+	/// answer can be put back into the search field, now that
+	/// [`MatchType::InformationalMatch`] is deprecated.
+	pub replace_query: Option<String>,
 	/// List of [actions](crate::Action) that the user can perform for this
 	/// match.
 	pub actions: Vec<A>,
 }
 
+/// An error returned when a [minimum letter
+/// count](Config::min_letter_count) is out of range.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct InvalidLetterCount(pub u32);
+
+/// An error returned when a match ID can't be [parsed](parse_match_id) into
+/// its typed key.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidMatchId(pub String);
+
+/// An error returned when [RGBA data](ImageData::from_rgba8) doesn't fit the
+/// given dimensions of an image.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidImageSize {
+	/// The width of the image.
+	pub width: u32,
+	/// The height of the image.
+	pub height: u32,
+	/// The length of the data, in bytes.
+	pub len: usize,
+}
+
+/// An error returned when a string isn't the [ID](Action::to_id) of any
+/// action.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidActionId(pub String);
+
+/// An error returned when an integer isn't the value of any [`MatchType`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidMatchType(pub i32);
+
+/// An error returned when a string is not a valid absolute URL.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidUrl(pub String);
+
 /// The icon displayed for a match.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum MatchIcon {
 	/// An icon specified by its icon name (e.g. `new-command-alarm`).
 	ByName(String),
+	/// An icon specified by several icon names, in order of preference.
+	///
+	/// Icon themes don't all cover the same icons, so this allows falling
+	/// back to more common icons. With the `icon-lookup` feature, the first
+	/// name that exists in the current icon theme is sent to KRunner, as found
+	/// by `lookup_icon`. Otherwise, or if none of them exist, the first name is
+	/// sent.
+	ByNameWithFallbacks(Vec<String>),
 	/// An icon specified by associated [custom image data](ImageData).
+	///
+	/// KRunner's D-Bus interface has no separate preview or thumbnail
+	/// property, so this is also how to show a thumbnail of a match, such as
+	/// an image file found by a file search.
 	Custom(ImageData),
+	/// An icon specified by the absolute path of an image file (e.g. a PNG or
+	/// SVG file bundled with the runner).
+	///
+	/// KRunner loads the file itself, so there is no need to decode it into
+	/// [`ImageData`] first.
+	Path(PathBuf),
+	/// An icon specified by both an icon name and [custom image
+	/// data](ImageData).
+	///
+	/// Both are sent to KRunner, which uses the image data where it can, and
+	/// falls back to the (theme-aware) icon name otherwise.
+	NameWithFallbackData {
+		/// The name of the icon.
+		name: String,
+		/// The custom image data of the icon.
+		data: ImageData,
+	},
 }
 
 /// Information related to an action.
@@ -234,13 +621,33 @@ pub struct ImageData {
 	pub data: Vec<u8>,
 }
 
-/// The image formats supported by KRunner for icons.
+/// The pixel layouts supported for icons.
+///
+/// KRunner itself only reads [`Argb32`](Self::Argb32) and
+/// [`Rgb32`](Self::Rgb32) images; images in any other format are converted
+/// with [`ImageData::to_argb32`] before being sent.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum ImageFormat {
-	/// 32-bit ARGB.
+	/// 32-bit ARGB, stored as one byte each of red, green, blue and alpha, in
+	/// that order.
 	Argb32,
-	/// 32-bit RGB.
+	/// 24-bit RGB, stored as one byte each of red, green and blue, in that
+	/// order.
 	Rgb32,
+	/// 32-bit ARGB, stored as one byte each of blue, green, red and alpha, in
+	/// that order.
+	///
+	/// This is how `0xAARRGGBB` pixels are laid out in memory on
+	/// little-endian machines, e.g. by `QImage::Format_ARGB32` or Cairo.
+	Bgra32,
+	/// 32-bit ARGB, stored as one byte each of alpha, red, green and blue, in
+	/// that order.
+	///
+	/// This is how `0xAARRGGBB` pixels are laid out in memory on big-endian
+	/// machines.
+	ArgbBe32,
+	/// 8-bit grayscale.
+	Gray8,
 }
 
 /// The type of the match.
@@ -249,6 +656,8 @@ pub enum ImageFormat {
 /// a higher value corresponds to higher confidence that a
 /// match would be relevant for the user.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum MatchType {
 	/// Null match.
 	NoMatch = 0,
@@ -263,8 +672,9 @@ pub enum MatchType {
 	/// The data of the match will be converted to a string and set in
 	/// the search field.
 	///
-	/// **Deprecated** since KDE Frameworks version 5.99.
-	#[deprecated(since = "0.1.0")]
+	/// **Deprecated** since KDE Frameworks version 5.99. Set
+	/// [`Match::replace_query`] on a match of any other type instead.
+	#[deprecated(since = "0.1.0", note = "use `Match::replace_query` instead")]
 	InformationalMatch = 50,
 
 	/// A match that represents an action not directly related to activating
@@ -279,6 +689,13 @@ pub enum MatchType {
 	HelperMatch = 70,
 
 	/// An exact match to the query.
+	///
+	/// KRunner ranks exact matches above all other types, and an exact match
+	/// with a [relevance](Match::relevance) of `1.0` is the closest a runner
+	/// can get to claiming a query for itself. The `org.kde.krunner1`
+	/// interface has no way for a runner to hide the matches of other
+	/// runners. Runners that should only see some queries in the first place
+	/// can set [`Config::match_filter`] instead.
 	ExactMatch = 100,
 }
 
@@ -289,16 +706,168 @@ pub(crate) fn action_as_arg<A: Action>(action: &A) -> (String, String, String) {
 	(action.to_id(), title, icon)
 }
 
+/// Builds the reply to an `Actions` call, from the
+/// [available actions](Runner::available_actions) of a runner.
+pub(crate) fn actions_as_args<A: Action + 'static>(
+	available: Option<&[A]>,
+	ctx: &RunnerContext,
+) -> Vec<(String, String, String)> {
+	available
+		.unwrap_or(A::all())
+		.iter()
+		.map(|action| {
+			let ActionInfo { title, icon } = action.info_dynamic(ctx);
+			(action.to_id(), title, icon)
+		})
+		.collect()
+}
+
+/// Runners that don't have any actions can use [`Infallible`] as their action
+/// type.
+impl Action for Infallible {
+	fn all() -> &'static [Self] {
+		&[]
+	}
+
+	fn from_id(_: &str) -> Option<Self> {
+		None
+	}
+
+	fn to_id(&self) -> String {
+		match *self {}
+	}
+
+	fn info(&self) -> ActionInfo {
+		match *self {}
+	}
+}
+
+/// Appends the relevance of each match to its subtitle, for
+/// [`Runner::debug_relevance`].
+pub(crate) fn annotate_relevance<A>(matches: &mut [Match<A>]) {
+	for m in matches {
+		let relevance = format!("({:.2})", m.relevance);
+		m.subtitle = Some(match m.subtitle.take() {
+			Some(subtitle) if !subtitle.is_empty() => format!("{subtitle} {relevance}"),
+			_ => relevance,
+		});
+	}
+}
+
+pub(crate) fn debug_relevance_from_env() -> bool {
+	std::env::var_os("KRUNNER_DEBUG_RELEVANCE").is_some()
+}
+
+impl<A> Config<A> {
+	/// The default [maximum reply size](Self::max_reply_size), which is the
+	/// default message size limit of the D-Bus session bus.
+	pub const DEFAULT_MAX_REPLY_SIZE: usize = 32 * 1024 * 1024;
+	/// The largest [minimum letter count](Self::min_letter_count) accepted by
+	/// [`Config::min_letter_count`].
+	pub const MAX_LETTER_COUNT: u32 = 64;
+
+	/// Sets the [minimum letter count](Self::min_letter_count) of queries.
+	///
+	/// # Errors
+	/// Returns an error if `count` is 0, which would be no different from not
+	/// setting a minimum, or larger than [`Self::MAX_LETTER_COUNT`], which
+	/// would stop the runner from ever being queried in practice.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{Config, InvalidLetterCount};
+	///
+	/// let config = Config::<std::convert::Infallible>::default().min_letter_count(3)?;
+	/// assert_eq!(config.min_letter_count, Some(3));
+	///
+	/// assert!(
+	/// 	Config::<std::convert::Infallible>::default()
+	/// 		.min_letter_count(0)
+	/// 		.is_err()
+	/// );
+	/// # Ok::<(), InvalidLetterCount>(())
+	/// ```
+	pub fn min_letter_count(mut self, count: u32) -> Result<Self, InvalidLetterCount> {
+		if count == 0 || count > Self::MAX_LETTER_COUNT {
+			return Err(InvalidLetterCount(count));
+		}
+		self.min_letter_count = Some(count);
+		Ok(self)
+	}
+
+	/// Converts the config to one for a different type of action.
+	pub(crate) fn cast<B>(&self) -> Config<B> {
+		Config {
+			match_filter: self.match_filter.clone(),
+			min_letter_count: self.min_letter_count,
+			strip_trigger_words: self.strip_trigger_words,
+			max_results: self.max_results,
+			preserve_order: self.preserve_order,
+			max_reply_size: self.max_reply_size,
+			dedup_ids: self.dedup_ids,
+			stable_order: self.stable_order,
+			debounce: self.debounce,
+			relevance_buckets: self.relevance_buckets,
+			enabled: self.enabled,
+			_phan: PhantomData,
+		}
+	}
+
+	/// The trigger words to remove from queries, if any.
+	pub(crate) fn trigger_words_to_strip(&self) -> Option<Vec<String>> {
+		match &self.match_filter {
+			Some(MatchFilter::Keywords(kws)) if self.strip_trigger_words => Some(kws.clone()),
+			_ => None,
+		}
+	}
+}
+
 impl<A> Default for Config<A> {
 	fn default() -> Self {
 		Self {
 			match_filter: None,
 			min_letter_count: None,
+			strip_trigger_words: false,
+			max_results: None,
+			preserve_order: false,
+			max_reply_size: None,
+			dedup_ids: false,
+			stable_order: false,
+			debounce: None,
+			relevance_buckets: None,
+			enabled: true,
 			_phan: PhantomData,
 		}
 	}
 }
 
+impl MatchType {
+	/// Whether KRunner may run a match of this type when the user hits
+	/// "Enter" without selecting it first.
+	///
+	/// [Helper matches](Self::HelperMatch) must be explicitly selected to be
+	/// run, and [informational matches](Self::InformationalMatch) and
+	/// [null matches](Self::NoMatch) can't be run at all. Matches of every
+	/// other type are activated when they're the top result.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::MatchType;
+	///
+	/// assert!(MatchType::ExactMatch.is_autoactivatable());
+	/// assert!(MatchType::PossibleMatch.is_autoactivatable());
+	/// assert!(!MatchType::HelperMatch.is_autoactivatable());
+	/// ```
+	#[must_use]
+	#[allow(deprecated)]
+	pub fn is_autoactivatable(&self) -> bool {
+		!matches!(
+			self,
+			Self::NoMatch | Self::InformationalMatch | Self::HelperMatch
+		)
+	}
+}
+
 impl MatchIcon {
 	fn new() -> Self {
 		Self::default()
@@ -309,11 +878,21 @@ impl Default for MatchIcon {
 		Self::ByName(String::new())
 	}
 }
+impl From<&str> for MatchIcon {
+	fn from(s: &str) -> Self {
+		Self::ByName(s.to_owned())
+	}
+}
 impl From<String> for MatchIcon {
 	fn from(s: String) -> Self {
 		Self::ByName(s)
 	}
 }
+impl From<PathBuf> for MatchIcon {
+	fn from(p: PathBuf) -> Self {
+		Self::Path(p)
+	}
+}
 impl From<ImageData> for MatchIcon {
 	fn from(i: ImageData) -> Self {
 		Self::Custom(i)
@@ -341,28 +920,54 @@ impl<A: Action + 'static> Append for Config<A> {
 
 		match &self.match_filter {
 			Some(MatchFilter::Keywords(kws)) => {
-				fields.insert("TriggerWords", Variant(kws.box_clone()));
+				fields.insert(keys::config::TRIGGER_WORDS, Variant(kws.box_clone()));
 			}
 			Some(MatchFilter::Regex(r)) => {
-				fields.insert("MatchRegex", Variant(r.box_clone()));
+				fields.insert(keys::config::MATCH_REGEX, Variant(r.box_clone()));
 			}
 			_ => {}
 		}
 		if let Some(min_letter_count) = self.min_letter_count {
-			fields.insert("MinLetterCount", Variant(min_letter_count.box_clone()));
+			fields.insert(
+				keys::config::MIN_LETTER_COUNT,
+				Variant(min_letter_count.box_clone()),
+			);
 		}
 
 		let actions: Vec<_> = A::all().iter().map(action_as_arg).collect();
-		fields.insert("Actions", Variant(actions.box_clone()));
+		fields.insert(keys::config::ACTIONS, Variant(actions.box_clone()));
 
 		Dict::new(fields.iter()).append_by_ref(i);
 	}
 }
 
-impl<A: Action> Default for Match<A> {
-	fn default() -> Self {
+impl<A> Match<A> {
+	/// Creates a match with the given [ID](Self::id), and every other field
+	/// set to its [default](Self::default).
+	///
+	/// Together with the other builder methods, this allows creating matches
+	/// without struct literals, which keeps compiling when fields are added.
+	/// To also make sure the match isn't left without a title, use
+	/// [`Match::builder`] instead.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{Match, MatchIcon, MatchType};
+	///
+	/// let m = Match::<std::convert::Infallible>::new("firefox")
+	/// 	.title("Firefox")
+	/// 	.subtitle("Web browser".to_owned())
+	/// 	.icon("firefox")
+	/// 	.ty(MatchType::ExactMatch)
+	/// 	.relevance(0.9);
+	/// assert_eq!(m.id, "firefox");
+	/// assert_eq!(m.title, "Firefox");
+	/// assert_eq!(m.icon, MatchIcon::ByName("firefox".to_owned()));
+	/// ```
+	#[must_use]
+	pub fn new(id: impl Display) -> Self {
 		Self {
-			id: String::new(),
+			id: id.to_string(),
 			title: String::new(),
 			subtitle: None,
 			icon: MatchIcon::new(),
@@ -371,9 +976,753 @@ impl<A: Action> Default for Match<A> {
 			urls: vec![],
 			category: None,
 			multiline: false,
+			replace_query: None,
 			actions: vec![],
 		}
 	}
+
+	/// Creates a match that shows the answer to a query, like the result of a
+	/// calculation.
+	///
+	/// The answer is used as both the [ID](Self::id) and the
+	/// [title](Self::title) of the match, and it is an
+	/// [exact match](MatchType::ExactMatch) with a [relevance](Self::relevance)
+	/// of 1. Running the match [replaces the query](Self::replace_query) with
+	/// the answer, which is what the deprecated
+	/// [`MatchType::InformationalMatch`] used to do.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{Match, MatchType};
+	///
+	/// let m = Match::<std::convert::Infallible>::answer("42").subtitle("6 * 7".to_owned());
+	/// assert_eq!(m.title, "42");
+	/// assert_eq!(m.ty, MatchType::ExactMatch);
+	/// assert_eq!(m.replace_query.as_deref(), Some("42"));
+	/// ```
+	#[must_use]
+	pub fn answer(text: impl Into<String>) -> Self {
+		let text = text.into();
+		Self::new(&text)
+			.title(text.clone())
+			.ty(MatchType::ExactMatch)
+			.relevance(1.0)
+			.replace_query(text)
+	}
+
+	/// Sets the [ID](Self::id) of this match from a typed key.
+	///
+	/// The key can be turned back into its original type in
+	/// [`Runner::run`] with [`parse_match_id`].
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m = Match::<std::convert::Infallible>::default().id(42);
+	/// assert_eq!(m.id, "42");
+	/// assert_eq!(krunner::parse_match_id::<u32>(&m.id), Ok(42));
+	/// ```
+	#[must_use]
+	pub fn id(mut self, id: impl Display) -> Self {
+		self.id = id.to_string();
+		self
+	}
+
+	/// Sets the [title](Self::title) of this match.
+	#[must_use]
+	pub fn title(mut self, title: impl Into<String>) -> Self {
+		self.title = title.into();
+		self
+	}
+
+	/// Sets or clears the [subtitle](Self::subtitle) of this match.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m = Match::<std::convert::Infallible>::default().subtitle("Found in Documents".to_owned());
+	/// assert_eq!(m.subtitle.as_deref(), Some("Found in Documents"));
+	///
+	/// let m = m.subtitle(None);
+	/// assert_eq!(m.subtitle, None);
+	/// ```
+	#[must_use]
+	pub fn subtitle(mut self, subtitle: impl Into<Option<String>>) -> Self {
+		self.subtitle = subtitle.into();
+		self
+	}
+
+	/// Sets or clears the [category](Self::category) of this match.
+	#[must_use]
+	pub fn category(mut self, category: impl Into<Option<String>>) -> Self {
+		self.category = category.into();
+		self
+	}
+
+	/// Sets the [icon](Self::icon) of this match.
+	///
+	/// Icon names can be given as a `&str` or a [`String`], icon files as a
+	/// [`PathBuf`], and custom icons as [`ImageData`].
+	#[must_use]
+	pub fn icon(mut self, icon: impl Into<MatchIcon>) -> Self {
+		self.icon = icon.into();
+		self
+	}
+
+	/// Sets the [type](Self::ty) of this match.
+	#[must_use]
+	pub fn ty(mut self, ty: MatchType) -> Self {
+		self.ty = ty;
+		self
+	}
+
+	/// Sets the [relevance](Self::relevance) of this match.
+	#[must_use]
+	pub fn relevance(mut self, relevance: f64) -> Self {
+		self.relevance = relevance;
+		self
+	}
+
+	/// Sets whether the text of this match should be displayed as
+	/// [multiline](Self::multiline).
+	#[must_use]
+	pub fn multiline(mut self, multiline: bool) -> Self {
+		self.multiline = multiline;
+		self
+	}
+
+	/// Adds an [action](Self::actions) that the user can perform for this
+	/// match.
+	#[must_use]
+	pub fn action(mut self, action: A) -> Self {
+		self.actions.push(action);
+		self
+	}
+
+	/// Sets the [type](Self::ty) of this match based on its
+	/// [relevance](Self::relevance).
+	///
+	/// A relevance of 1 or above yields [`MatchType::ExactMatch`], a relevance
+	/// of at least 0.5 yields [`MatchType::PossibleMatch`], and anything lower
+	/// yields [`MatchType::CompletionMatch`]. The type is only changed when
+	/// this is called, so set [`ty`](Self::ty) directly for full control.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{Match, MatchType};
+	///
+	/// let m = Match::<std::convert::Infallible> {
+	/// 	relevance: 1.0,
+	/// 	..Match::default()
+	/// };
+	/// assert_eq!(m.infer_type().ty, MatchType::ExactMatch);
+	///
+	/// let m = Match::<std::convert::Infallible> {
+	/// 	relevance: 0.2,
+	/// 	..Match::default()
+	/// };
+	/// assert_eq!(m.infer_type().ty, MatchType::CompletionMatch);
+	/// ```
+	#[must_use]
+	pub fn infer_type(mut self) -> Self {
+		self.ty = if self.relevance >= 1.0 {
+			MatchType::ExactMatch
+		} else if self.relevance >= 0.5 {
+			MatchType::PossibleMatch
+		} else {
+			MatchType::CompletionMatch
+		};
+		self
+	}
+
+	/// Sets or clears the [text that replaces the query](Self::replace_query)
+	/// when this match is run.
+	#[must_use]
+	pub fn replace_query(mut self, query: impl Into<Option<String>>) -> Self {
+		self.replace_query = query.into();
+		self
+	}
+
+	/// Adds a URL associated with this match, without validating it.
+	#[must_use]
+	pub fn url(mut self, url: impl Into<String>) -> Self {
+		self.urls.push(url.into());
+		self
+	}
+
+	/// Adds several URLs associated with this match.
+	///
+	/// KRunner parses these as absolute URLs (e.g. `file:///home` or
+	/// `https://kde.org`) when the match is dragged or copied, so each one must
+	/// start with a scheme and may not contain whitespace. If any of them is
+	/// invalid, none are added.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m = Match::<std::convert::Infallible>::default()
+	/// 	.urls(["file:///home", "https://kde.org"])
+	/// 	.unwrap();
+	/// assert_eq!(m.urls.len(), 2);
+	///
+	/// assert!(
+	/// 	Match::<std::convert::Infallible>::default()
+	/// 		.urls(["/home"])
+	/// 		.is_err()
+	/// );
+	/// ```
+	pub fn urls(
+		mut self,
+		urls: impl IntoIterator<Item = impl Into<String>>,
+	) -> Result<Self, InvalidUrl> {
+		let urls = urls
+			.into_iter()
+			.map(Into::into)
+			.map(|url| {
+				if is_valid_url(&url) {
+					Ok(url)
+				} else {
+					Err(InvalidUrl(url))
+				}
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		self.urls.extend(urls);
+		Ok(self)
+	}
+
+	/// Adds the `file://` URL of a file or directory associated with this
+	/// match, e.g. for KRunner to open its containing folder.
+	///
+	/// Characters that aren't allowed in URLs, like spaces or `#`, are
+	/// percent-encoded, as are non-ASCII characters, byte by byte.
+	///
+	/// # Errors
+	/// Returns an error if the path isn't absolute, since KRunner would have no
+	/// way of telling what it is relative to.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m = Match::<std::convert::Infallible>::new("notes").file("/home/user/My Notes #1.txt")?;
+	/// assert_eq!(m.urls, ["file:///home/user/My%20Notes%20%231.txt"]);
+	///
+	/// let m = m.file("/home/user/Bücher")?;
+	/// assert_eq!(m.urls[1], "file:///home/user/B%C3%BCcher");
+	///
+	/// assert!(
+	/// 	Match::<std::convert::Infallible>::new("notes")
+	/// 		.file("notes.txt")
+	/// 		.is_err()
+	/// );
+	/// # Ok::<(), krunner::InvalidUrl>(())
+	/// ```
+	pub fn file(mut self, path: impl AsRef<Path>) -> Result<Self, InvalidUrl> {
+		use std::fmt::Write as _;
+		use std::os::unix::ffi::OsStrExt;
+
+		let path = path.as_ref();
+		if !path.is_absolute() {
+			return Err(InvalidUrl(path.display().to_string()));
+		}
+
+		let mut url = "file://".to_owned();
+		for &b in path.as_os_str().as_bytes() {
+			if b.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@".contains(&b) {
+				url.push(char::from(b));
+			} else {
+				let _ = write!(url, "%{b:02X}");
+			}
+		}
+		self.urls.push(url);
+		Ok(self)
+	}
+
+	/// Sorts matches the way KRunner displays them: grouped by category, with
+	/// the categories containing the best matches first, and the best matches
+	/// first within each category.
+	///
+	/// Matches are ranked by their [type](Self::ty) first, and their
+	/// [relevance](Self::relevance) second. Matches without a category are
+	/// grouped under `runner_name`, just like KRunner does. The sort is stable,
+	/// so equally ranked matches keep their order.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m = |id: &str, category: Option<&str>, relevance| Match::<std::convert::Infallible> {
+	/// 	id: id.to_owned(),
+	/// 	category: category.map(str::to_owned),
+	/// 	relevance,
+	/// 	..Match::default()
+	/// };
+	///
+	/// let sorted = Match::group_and_sort(
+	/// 	vec![
+	/// 		m("a", Some("Files"), 0.2),
+	/// 		m("b", None, 0.9),
+	/// 		m("c", Some("Files"), 0.5),
+	/// 		m("d", Some("Units"), 0.9),
+	/// 	],
+	/// 	"Units",
+	/// );
+	/// let ids: Vec<_> = sorted.iter().map(|m| m.id.as_str()).collect();
+	/// assert_eq!(ids, ["b", "d", "c", "a"]);
+	/// ```
+	#[must_use]
+	pub fn group_and_sort(mut matches: Vec<Self>, runner_name: &str) -> Vec<Self> {
+		let category = |m: &Self| m.category.clone().unwrap_or_else(|| runner_name.to_owned());
+
+		let mut best = HashMap::<String, Rank>::new();
+		for m in &matches {
+			let best = best.entry(category(m)).or_insert(Rank::of(m));
+			*best = (*best).max(Rank::of(m));
+		}
+
+		matches.sort_by_cached_key(|m| {
+			let category = category(m);
+			(Reverse(best[&category]), category, Reverse(Rank::of(m)))
+		});
+		matches
+	}
+
+	/// Lowers relevances where needed so that KRunner, which sorts matches by
+	/// relevance, displays them in the given order.
+	///
+	/// Each match ends up with a relevance strictly lower than the one before
+	/// it, by lowering it as little as possible. Relevances never drop below 0,
+	/// so the order of matches at the bottom of a list with many matches of
+	/// relevance 0 can't be kept. KRunner doesn't break ties between matches
+	/// of the same [type](Self::ty) and relevance in any guaranteed way, which
+	/// is why the order can't be kept by merely giving matches equal
+	/// relevances.
+	///
+	/// This is applied automatically to runners that set
+	/// [`Config::preserve_order`].
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let mut matches: Vec<_> = [0.5, 0.8, 0.5, 0.2]
+	/// 	.into_iter()
+	/// 	.map(|relevance| Match::<std::convert::Infallible> {
+	/// 		relevance,
+	/// 		..Match::default()
+	/// 	})
+	/// 	.collect();
+	/// Match::preserve_order(&mut matches);
+	///
+	/// assert!(matches.windows(2).all(|w| w[0].relevance > w[1].relevance));
+	/// assert_eq!(matches[0].relevance, 0.5);
+	/// assert_eq!(matches[3].relevance, 0.2);
+	/// ```
+	pub fn preserve_order(matches: &mut [Self]) {
+		// Small enough to not visibly change any relevances, large enough to
+		// survive the round-trip through KRunner
+		const STEP: f64 = 1e-6;
+
+		let mut prev = f64::INFINITY;
+		for m in matches {
+			if m.relevance >= prev {
+				m.relevance = (prev - STEP).max(0.0);
+			}
+			prev = m.relevance;
+		}
+	}
+
+	/// Sorts matches the way KRunner ranks them, by [type](Self::ty) and then
+	/// [relevance](Self::relevance), and lowers relevances where needed so
+	/// that no two matches are ranked the same.
+	///
+	/// KRunner doesn't break ties between matches of the same type and
+	/// relevance in any guaranteed way, so their order may change from one
+	/// query to the next. With this, tied matches are instead displayed in
+	/// the order they were returned in, by lowering the relevance of each as
+	/// little as possible. Matches of different types are never tied, as
+	/// KRunner always displays higher types first.
+	///
+	/// This is applied automatically to runners that set
+	/// [`Config::stable_order`].
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{Match, MatchType};
+	///
+	/// let m = |id: &str, ty, relevance| Match::<std::convert::Infallible> {
+	/// 	id: id.to_owned(),
+	/// 	ty,
+	/// 	relevance,
+	/// 	..Match::default()
+	/// };
+	///
+	/// let mut matches = vec![
+	/// 	m("a", MatchType::PossibleMatch, 0.5),
+	/// 	m("b", MatchType::ExactMatch, 0.2),
+	/// 	m("c", MatchType::PossibleMatch, 0.5),
+	/// ];
+	/// Match::break_ties(&mut matches);
+	///
+	/// let ids: Vec<_> = matches.iter().map(|m| m.id.as_str()).collect();
+	/// assert_eq!(ids, ["b", "a", "c"]);
+	/// assert!(matches[1].relevance > matches[2].relevance);
+	/// ```
+	pub fn break_ties(matches: &mut [Self]) {
+		matches.sort_by_key(|m| Reverse(Rank::of(m)));
+		for ty in matches.chunk_by_mut(|a, b| a.ty == b.ty) {
+			Self::preserve_order(ty);
+		}
+	}
+
+	/// Rounds the [relevances](Self::relevance) of matches to the nearest
+	/// multiple of `1 / buckets`, so that matches with near-equal relevances
+	/// rank the same.
+	///
+	/// Nothing is rounded when `buckets` is zero.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let mut matches: Vec<_> = [0.5, 0.500_000_1, 0.74]
+	/// 	.into_iter()
+	/// 	.map(|relevance| Match::<std::convert::Infallible>::default().relevance(relevance))
+	/// 	.collect();
+	/// Match::round_relevance(&mut matches, 10);
+	///
+	/// let relevances: Vec<_> = matches.iter().map(|m| m.relevance).collect();
+	/// assert_eq!(relevances, [0.5, 0.5, 0.7]);
+	/// ```
+	pub fn round_relevance(matches: &mut [Self], buckets: u32) {
+		if buckets == 0 {
+			return;
+		}
+		let buckets = f64::from(buckets);
+		for m in matches {
+			m.relevance = (m.relevance * buckets).round() / buckets;
+		}
+	}
+
+	/// Rescales the [relevances](Self::relevance) of matches in each
+	/// [category](Self::category), so that the best match of every category
+	/// has a relevance of 1.
+	///
+	/// This is useful for runners whose categories are scored in different
+	/// ways, e.g. by different search indexes, where one category would
+	/// otherwise crowd out the others. Relevances within a category keep
+	/// their proportions, and all end up between 0 and 1. Matches without a
+	/// category are treated as one category of their own.
+	///
+	/// Note that this only balances the matches of a single runner: KRunner
+	/// still weighs the matches of different runners against each other in
+	/// its own way.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let mut matches: Vec<_> = [("Apps", 8.0), ("Apps", 4.0), ("Files", 0.25)]
+	/// 	.into_iter()
+	/// 	.map(|(category, relevance)| Match::<std::convert::Infallible> {
+	/// 		category: Some(category.to_owned()),
+	/// 		relevance,
+	/// 		..Match::default()
+	/// 	})
+	/// 	.collect();
+	/// Match::normalize_relevance_per_category(&mut matches);
+	///
+	/// let relevances: Vec<_> = matches.iter().map(|m| m.relevance).collect();
+	/// assert_eq!(relevances, [1.0, 0.5, 1.0]);
+	/// ```
+	pub fn normalize_relevance_per_category(matches: &mut [Self]) {
+		let mut best: HashMap<Option<String>, f64> = HashMap::new();
+		for m in &*matches {
+			let best = best.entry(m.category.clone()).or_insert(0.0);
+			*best = best.max(m.relevance);
+		}
+
+		for m in matches {
+			let best = best[&m.category];
+			m.relevance = if best > 0.0 {
+				(m.relevance / best).clamp(0.0, 1.0)
+			} else {
+				0.0
+			};
+		}
+	}
+
+	/// Removes matches with duplicate [IDs](Self::id), keeping the highest
+	/// ranked match for each ID.
+	///
+	/// KRunner tells matches apart by their ID, and silently drops all but one
+	/// match with the same ID. Matches are ranked by [type](Self::ty), then
+	/// [relevance](Self::relevance). The kept match takes the place of the
+	/// first match with its ID, so the order of the matches is otherwise kept.
+	///
+	/// This is applied automatically to runners that set
+	/// [`Config::dedup_ids`].
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m = |id: &str, relevance| Match::<std::convert::Infallible> {
+	/// 	id: id.to_owned(),
+	/// 	relevance,
+	/// 	..Match::default()
+	/// };
+	///
+	/// let mut matches = vec![m("hello", 0.5), m("world", 0.3), m("hello", 0.9)];
+	/// Match::dedup_by_id(&mut matches);
+	///
+	/// let kept: Vec<_> = matches
+	/// 	.iter()
+	/// 	.map(|m| (m.id.as_str(), m.relevance))
+	/// 	.collect();
+	/// assert_eq!(kept, [("hello", 0.9), ("world", 0.3)]);
+	/// ```
+	pub fn dedup_by_id(matches: &mut Vec<Self>) {
+		let mut index = HashMap::<String, usize>::new();
+		let mut kept: Vec<Self> = Vec::with_capacity(matches.len());
+		for m in matches.drain(..) {
+			if let Some(&i) = index.get(&m.id) {
+				if Rank::of(&m) > Rank::of(&kept[i]) {
+					kept[i] = m;
+				}
+			} else {
+				index.insert(m.id.clone(), kept.len());
+				kept.push(m);
+			}
+		}
+		*matches = kept;
+	}
+
+	/// The number of bytes taken up by the text and icon of this match.
+	pub(crate) fn approximate_size(&self) -> usize {
+		let icon = match &self.icon {
+			MatchIcon::ByName(name) => name.len(),
+			MatchIcon::ByNameWithFallbacks(names) => {
+				names.iter().map(String::len).max().unwrap_or(0)
+			}
+			MatchIcon::Custom(data) => data.data.len(),
+			MatchIcon::Path(path) => path.as_os_str().len(),
+			MatchIcon::NameWithFallbackData { name, data } => name.len() + data.data.len(),
+		};
+		self.id.len()
+			+ self.title.len()
+			+ self.subtitle.as_ref().map_or(0, String::len)
+			+ self.category.as_ref().map_or(0, String::len)
+			+ self.urls.iter().map(String::len).sum::<usize>()
+			+ icon
+	}
+
+	fn is_multiline(&self) -> bool {
+		self.multiline
+			|| self.title.contains('\n')
+			|| self.subtitle.as_ref().is_some_and(|s| s.contains('\n'))
+	}
+}
+
+/// Keeps only the `max` highest ranked matches, for [`Config::max_results`].
+pub(crate) fn truncate_matches<A>(matches: &mut Vec<Match<A>>, max: usize) {
+	if matches.len() > max {
+		matches.sort_by_key(|m| Reverse(Rank::of(m)));
+		matches.truncate(max);
+	}
+}
+
+/// How highly KRunner ranks a match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rank(MatchType, f64);
+
+impl Rank {
+	fn of<A>(m: &Match<A>) -> Self {
+		Self(m.ty, m.relevance)
+	}
+}
+impl Eq for Rank {}
+impl PartialOrd for Rank {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl Ord for Rank {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.0
+			.cmp(&other.0)
+			.then_with(|| self.1.total_cmp(&other.1))
+	}
+}
+
+/// Parses a match ID set with [`Match::id`] back into its typed key.
+///
+/// Match IDs still reach [`Runner::run`] as plain strings, so runners call
+/// this themselves. The runner traits have no associated type for the ID, as
+/// it couldn't default to `String` on stable Rust, and would have to be
+/// spelled out by every runner.
+///
+/// KRunner only ever sends back IDs the runner handed out, so a failure
+/// usually means that the runner changed how it formats its IDs, or that the
+/// runner was called by something other than KRunner.
+///
+/// # Errors
+/// Returns an error if the ID isn't a valid `T`.
+///
+/// # Example
+/// ```
+/// #[derive(Debug, PartialEq)]
+/// enum Key {
+/// 	File(u32),
+/// }
+///
+/// impl std::str::FromStr for Key {
+/// 	type Err = std::num::ParseIntError;
+///
+/// 	fn from_str(s: &str) -> Result<Self, Self::Err> {
+/// 		s.parse().map(Self::File)
+/// 	}
+/// }
+///
+/// assert_eq!(krunner::parse_match_id("7"), Ok(Key::File(7)));
+/// assert!(krunner::parse_match_id::<Key>("seven").is_err());
+/// ```
+pub fn parse_match_id<T: FromStr>(id: &str) -> Result<T, InvalidMatchId> {
+	id.parse().map_err(|_| InvalidMatchId(id.to_owned()))
+}
+
+/// Checks that a URL has a scheme (as per RFC 3986) and no whitespace or
+/// control characters.
+fn is_valid_url(url: &str) -> bool {
+	let Some((scheme, _)) = url.split_once(':') else {
+		return false;
+	};
+	let mut scheme = scheme.chars();
+	scheme.next().is_some_and(|c| c.is_ascii_alphabetic())
+		&& scheme.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+		&& !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+impl Display for InvalidUrl {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "invalid URL: {:?}", self.0)
+	}
+}
+impl Error for InvalidUrl {}
+impl RunnerError for InvalidUrl {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::InvalidArgs
+	}
+}
+
+impl Display for InvalidImageSize {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let Self { width, height, len } = *self;
+		match InvalidImageSize::expected_len(width, height) {
+			Some(expected) => write!(
+				f,
+				"expected {expected} bytes of RGBA data for a {width}x{height} image, got {len}"
+			),
+			None => write!(f, "a {width}x{height} image is too large"),
+		}
+	}
+}
+impl Error for InvalidImageSize {}
+impl RunnerError for InvalidImageSize {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::InvalidArgs
+	}
+}
+
+impl InvalidImageSize {
+	/// The length of the RGBA data of an image, if it can be sent to KRunner.
+	fn expected_len(width: u32, height: u32) -> Option<usize> {
+		let row_stride = i32::try_from(width).ok()?.checked_mul(4)?;
+		let height = i32::try_from(height).ok()?;
+		usize::try_from(row_stride)
+			.ok()?
+			.checked_mul(usize::try_from(height).ok()?)
+	}
+}
+
+impl Display for InvalidMatchType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "invalid match type: {}", self.0)
+	}
+}
+impl Error for InvalidMatchType {}
+impl RunnerError for InvalidMatchType {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::InvalidArgs
+	}
+}
+
+impl Display for InvalidActionId {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "unknown action ID: {:?}", self.0)
+	}
+}
+impl Error for InvalidActionId {}
+impl RunnerError for InvalidActionId {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::InvalidArgs
+	}
+}
+
+impl Display for InvalidMatchId {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "invalid match ID: {:?}", self.0)
+	}
+}
+impl Error for InvalidMatchId {}
+impl RunnerError for InvalidMatchId {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::InvalidArgs
+	}
+}
+
+impl Display for InvalidLetterCount {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"invalid minimum letter count {}, expected 1 to {}",
+			self.0,
+			Config::<()>::MAX_LETTER_COUNT
+		)
+	}
+}
+impl Error for InvalidLetterCount {}
+impl RunnerError for InvalidLetterCount {
+	fn kind(&self) -> ErrorKind {
+		ErrorKind::InvalidArgs
+	}
+}
+
+impl<A: Action> Default for Match<A> {
+	fn default() -> Self {
+		Self::new("")
+	}
+}
+impl<A> Match<A> {
+	/// The icon name sent to KRunner, which is empty for custom icons.
+	pub(crate) fn icon_name(&self) -> Cow<'_, str> {
+		match &self.icon {
+			MatchIcon::ByName(n) | MatchIcon::NameWithFallbackData { name: n, .. } => {
+				Cow::Borrowed(n.as_str())
+			}
+			#[cfg(feature = "icon-lookup")]
+			MatchIcon::ByNameWithFallbacks(names) => Cow::Borrowed(icons::pick_icon(names)),
+			#[cfg(not(feature = "icon-lookup"))]
+			MatchIcon::ByNameWithFallbacks(names) => {
+				Cow::Borrowed(names.first().map_or("", String::as_str))
+			}
+			MatchIcon::Path(path) => path.to_string_lossy(),
+			MatchIcon::Custom(_) => Cow::Borrowed(""),
+		}
+	}
 }
 impl<A: Action> Arg for Match<A> {
 	const ARG_TYPE: ArgType = ArgType::Struct;
@@ -385,30 +1734,31 @@ impl<A: Action> Arg for Match<A> {
 impl<A: Action> Append for Match<A> {
 	fn append_by_ref(&self, i: &mut IterAppend<'_>) {
 		let mut fields = HashMap::<&'static str, AnyVariant>::new();
-
-		let icon = match &self.icon {
-			MatchIcon::ByName(n) => n,
-			MatchIcon::Custom(_) => "",
-		};
+		let icon = self.icon_name();
 
 		if !self.urls.is_empty() {
-			fields.insert("urls", Variant(self.urls.box_clone()));
+			fields.insert(keys::matches::URLS, Variant(self.urls.box_clone()));
 		}
 		if let Some(category) = &self.category {
-			fields.insert("category", Variant(category.box_clone()));
+			fields.insert(keys::matches::CATEGORY, Variant(category.box_clone()));
 		}
 		if let Some(subtext) = &self.subtitle {
-			fields.insert("subtext", Variant(subtext.box_clone()));
+			fields.insert(keys::matches::SUBTEXT, Variant(subtext.box_clone()));
+		}
+		if self.is_multiline() {
+			fields.insert(keys::matches::MULTILINE, Variant(true.box_clone()));
 		}
-		if self.multiline {
-			fields.insert("multiline", Variant(self.multiline.box_clone()));
+		if let Some(query) = &self.replace_query {
+			fields.insert(keys::matches::QUERY, Variant(query.box_clone()));
 		}
 		if !self.actions.is_empty() {
 			let actions: Vec<_> = self.actions.iter().map(A::to_id).collect();
-			fields.insert("actions", Variant(actions.box_clone()));
+			fields.insert(keys::matches::ACTIONS, Variant(actions.box_clone()));
 		}
-		if let MatchIcon::Custom(icon) = &self.icon {
-			fields.insert("icon-data", Variant(icon.box_clone()));
+		if let MatchIcon::Custom(icon) | MatchIcon::NameWithFallbackData { data: icon, .. } =
+			&self.icon
+		{
+			fields.insert(keys::matches::ICON_DATA, Variant(icon.box_clone()));
 		}
 
 		let fields = Dict::new(fields.iter());
@@ -416,7 +1766,7 @@ impl<A: Action> Append for Match<A> {
 		i.append((
 			&self.id,
 			&self.title,
-			&icon,
+			&*icon,
 			&self.ty,
 			&self.relevance,
 			&fields,
@@ -424,6 +1774,34 @@ impl<A: Action> Append for Match<A> {
 	}
 }
 
+/// Reads a match type from the value it is sent to KRunner as.
+///
+/// # Example
+/// ```
+/// use krunner::{InvalidMatchType, MatchType};
+///
+/// assert_eq!(MatchType::try_from(100), Ok(MatchType::ExactMatch));
+/// assert_eq!(MatchType::try_from(42), Err(InvalidMatchType(42)));
+/// ```
+impl TryFrom<i32> for MatchType {
+	type Error = InvalidMatchType;
+
+	#[allow(deprecated)]
+	fn try_from(value: i32) -> Result<Self, Self::Error> {
+		[
+			Self::NoMatch,
+			Self::CompletionMatch,
+			Self::PossibleMatch,
+			Self::InformationalMatch,
+			Self::HelperMatch,
+			Self::ExactMatch,
+		]
+		.into_iter()
+		.find(|&ty| ty as i32 == value)
+		.ok_or(InvalidMatchType(value))
+	}
+}
+
 impl Arg for MatchType {
 	const ARG_TYPE: ArgType = i32::ARG_TYPE;
 
@@ -477,29 +1855,179 @@ impl RefArg for ImageData {
 }
 impl Append for ImageData {
 	fn append_by_ref(&self, i: &mut IterAppend<'_>) {
+		let image = self.marshalled();
 		i.append((
-			&self.width,
-			&self.height,
-			&self.row_stride,
-			&self.has_alpha,
-			&self.format.bits_per_sample(),
-			&self.format.channels(),
-			&self.data,
+			&image.width,
+			&image.height,
+			&image.row_stride,
+			&image.has_alpha,
+			&image.format.bits_per_sample(),
+			&i32::from(image.format.channels()),
+			&image.data,
 		));
 	}
 }
 
+impl ImageData {
+	/// The image in a format KRunner can read.
+	pub(crate) fn marshalled(&self) -> Cow<'_, Self> {
+		match self.format {
+			ImageFormat::Argb32 | ImageFormat::Rgb32 => Cow::Borrowed(self),
+			_ => Cow::Owned(self.to_argb32()),
+		}
+	}
+
+	/// Creates an [`Argb32`](ImageFormat::Argb32) image from packed
+	/// `0xAARRGGBB` pixels, in rows from top to bottom.
+	///
+	/// As the pixels are read as integers rather than bytes, this works the
+	/// same regardless of the endianness of the machine.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::ImageData;
+	///
+	/// let image = ImageData::from_argb32_pixels(1, 1, &[0x80ff_0000]);
+	/// assert_eq!(image.data, [0xff, 0x00, 0x00, 0x80]);
+	/// ```
+	#[must_use]
+	pub fn from_argb32_pixels(width: i32, height: i32, pixels: &[u32]) -> Self {
+		Self {
+			width,
+			height,
+			row_stride: width.saturating_mul(4),
+			has_alpha: true,
+			format: ImageFormat::Argb32,
+			data: pixels
+				.iter()
+				.flat_map(|p| {
+					let [a, r, g, b] = p.to_be_bytes();
+					[r, g, b, a]
+				})
+				.collect(),
+		}
+	}
+
+	/// Creates an [`Argb32`](ImageFormat::Argb32) image from RGBA pixels with
+	/// one byte per channel, in rows from top to bottom, as produced by most
+	/// image libraries.
+	///
+	/// # Errors
+	/// Returns an error if `data` isn't exactly `width * height * 4` bytes
+	/// long, or if the image is too large to be sent to KRunner.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{ImageData, ImageFormat, InvalidImageSize};
+	///
+	/// let image = ImageData::from_rgba8(3, 1, &[0xff; 12])?;
+	/// assert_eq!(image.format, ImageFormat::Argb32);
+	/// assert_eq!(image.row_stride, 12);
+	///
+	/// assert!(ImageData::from_rgba8(3, 1, &[0xff; 9]).is_err());
+	/// # Ok::<(), InvalidImageSize>(())
+	/// ```
+	pub fn from_rgba8(width: u32, height: u32, data: &[u8]) -> Result<Self, InvalidImageSize> {
+		let err = || InvalidImageSize {
+			width,
+			height,
+			len: data.len(),
+		};
+		if InvalidImageSize::expected_len(width, height) != Some(data.len()) {
+			return Err(err());
+		}
+		let width = i32::try_from(width).map_err(|_| err())?;
+		let height = i32::try_from(height).map_err(|_| err())?;
+
+		// KRunner reads `Argb32` images as red, green, blue and alpha bytes, so
+		// the data can be used as-is
+		Ok(Self {
+			width,
+			height,
+			row_stride: width * 4,
+			has_alpha: true,
+			format: ImageFormat::Argb32,
+			data: data.to_vec(),
+		})
+	}
+
+	/// Converts this image to [`Argb32`](ImageFormat::Argb32), the layout
+	/// KRunner reads images in, with tightly packed rows.
+	///
+	/// Images without an alpha channel become fully opaque. Pixels missing
+	/// from [`data`](Self::data) are treated as transparent black.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::{ImageData, ImageFormat};
+	///
+	/// let image = ImageData {
+	/// 	width: 1,
+	/// 	height: 1,
+	/// 	row_stride: 4,
+	/// 	has_alpha: true,
+	/// 	format: ImageFormat::Bgra32,
+	/// 	data: vec![0x01, 0x02, 0x03, 0x04],
+	/// };
+	/// assert_eq!(image.to_argb32().data, [0x03, 0x02, 0x01, 0x04]);
+	/// ```
+	#[must_use]
+	pub fn to_argb32(&self) -> Self {
+		let width = usize::try_from(self.width).unwrap_or(0);
+		let height = usize::try_from(self.height).unwrap_or(0);
+		let stride = usize::try_from(self.row_stride).unwrap_or(0);
+		let bytes_per_pixel = self.format.bytes_per_pixel();
+
+		let mut data = Vec::with_capacity(width * height * 4);
+		for y in 0..height {
+			for x in 0..width {
+				let start = y * stride + x * bytes_per_pixel;
+				let [r, g, b, a] = self
+					.data
+					.get(start..start + bytes_per_pixel)
+					.map_or([0; 4], |px| self.format.to_rgba(px));
+				data.extend([r, g, b, if self.has_alpha { a } else { 0xff }]);
+			}
+		}
+
+		Self {
+			width: self.width,
+			height: self.height,
+			row_stride: self.width.saturating_mul(4),
+			has_alpha: true,
+			format: ImageFormat::Argb32,
+			data,
+		}
+	}
+}
+
 impl ImageFormat {
 	fn bits_per_sample(&self) -> i32 {
 		match self {
-			Self::Argb32 | Self::Rgb32 => 8,
+			Self::Argb32 | Self::Rgb32 | Self::Bgra32 | Self::ArgbBe32 | Self::Gray8 => 8,
 		}
 	}
 
-	fn channels(&self) -> i32 {
+	fn channels(&self) -> u8 {
 		match self {
-			Self::Argb32 => 4,
+			Self::Argb32 | Self::Bgra32 | Self::ArgbBe32 => 4,
 			Self::Rgb32 => 3,
+			Self::Gray8 => 1,
+		}
+	}
+
+	fn bytes_per_pixel(&self) -> usize {
+		usize::from(self.channels())
+	}
+
+	/// Reads a single pixel of this format as red, green, blue and alpha.
+	fn to_rgba(&self, px: &[u8]) -> [u8; 4] {
+		match *self {
+			Self::Argb32 => [px[0], px[1], px[2], px[3]],
+			Self::Rgb32 => [px[0], px[1], px[2], 0xff],
+			Self::Bgra32 => [px[2], px[1], px[0], px[3]],
+			Self::ArgbBe32 => [px[1], px[2], px[3], px[0]],
+			Self::Gray8 => [px[0], px[0], px[0], 0xff],
 		}
 	}
 }
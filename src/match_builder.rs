@@ -0,0 +1,158 @@
+use std::fmt::Display;
+use std::marker::PhantomData;
+
+use crate::{Match, MatchIcon, MatchType};
+
+/// A builder for [`Match`]es that can't be finished without a title.
+///
+/// Matches with an empty title show up as blank results in KRunner, which is
+/// easy to do by accident with [`Match::default`] or [`Match::new`]. This
+/// builder keeps track of whether a [title](Self::title) has been set in its
+/// type, so that [`build`](Self::build) only exists once it has.
+///
+/// Created with [`Match::builder`].
+///
+/// # Example
+/// ```
+/// use krunner::{Match, MatchType};
+///
+/// let m: Match<std::convert::Infallible> = Match::builder("firefox")
+/// 	.icon("firefox")
+/// 	.title("Firefox")
+/// 	.ty(MatchType::ExactMatch)
+/// 	.build();
+/// assert_eq!(m.title, "Firefox");
+/// ```
+///
+/// Forgetting the title is a compile error:
+/// ```compile_fail
+/// use krunner::Match;
+///
+/// let m: Match<std::convert::Infallible> = Match::builder("firefox").icon("firefox").build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MatchBuilder<A, T = Untitled> {
+	inner: Match<A>,
+	_state: PhantomData<T>,
+}
+
+/// The state of a [`MatchBuilder`] that has no title yet.
+#[derive(Debug, Clone, Copy)]
+pub enum Untitled {}
+
+/// The state of a [`MatchBuilder`] that has a title.
+#[derive(Debug, Clone, Copy)]
+pub enum Titled {}
+
+impl<A> Match<A> {
+	/// Starts building a match with the given [ID](Self::id), which can only
+	/// be [built](MatchBuilder::build) once it has a [title](Self::title).
+	///
+	/// See [`MatchBuilder`] for details.
+	#[must_use]
+	pub fn builder(id: impl Display) -> MatchBuilder<A> {
+		MatchBuilder {
+			inner: Self::new(id),
+			_state: PhantomData,
+		}
+	}
+}
+
+impl<A, T> MatchBuilder<A, T> {
+	/// Sets the [title](Match::title) of the match.
+	#[must_use]
+	pub fn title(self, title: impl Into<String>) -> MatchBuilder<A, Titled> {
+		MatchBuilder {
+			inner: self.inner.title(title),
+			_state: PhantomData,
+		}
+	}
+
+	/// Sets or clears the [subtitle](Match::subtitle) of the match.
+	#[must_use]
+	pub fn subtitle(self, subtitle: impl Into<Option<String>>) -> Self {
+		self.map(|m| m.subtitle(subtitle))
+	}
+
+	/// Sets or clears the [category](Match::category) of the match.
+	#[must_use]
+	pub fn category(self, category: impl Into<Option<String>>) -> Self {
+		self.map(|m| m.category(category))
+	}
+
+	/// Sets the [icon](Match::icon) of the match.
+	#[must_use]
+	pub fn icon(self, icon: impl Into<MatchIcon>) -> Self {
+		self.map(|m| m.icon(icon))
+	}
+
+	/// Sets the [type](Match::ty) of the match.
+	#[must_use]
+	pub fn ty(self, ty: MatchType) -> Self {
+		self.map(|m| m.ty(ty))
+	}
+
+	/// Sets the [relevance](Match::relevance) of the match.
+	#[must_use]
+	pub fn relevance(self, relevance: f64) -> Self {
+		self.map(|m| m.relevance(relevance))
+	}
+
+	/// Sets whether the text of the match should be displayed as
+	/// [multiline](Match::multiline).
+	#[must_use]
+	pub fn multiline(self, multiline: bool) -> Self {
+		self.map(|m| m.multiline(multiline))
+	}
+
+	/// Adds an [action](Match::actions) that the user can perform for the
+	/// match.
+	#[must_use]
+	pub fn action(self, action: A) -> Self {
+		self.map(|m| m.action(action))
+	}
+
+	/// Sets or clears the [text that replaces the query](Match::replace_query)
+	/// when the match is run.
+	#[must_use]
+	pub fn replace_query(self, query: impl Into<Option<String>>) -> Self {
+		self.map(|m| m.replace_query(query))
+	}
+
+	/// Adds a URL associated with the match, without validating it.
+	#[must_use]
+	pub fn url(self, url: impl Into<String>) -> Self {
+		self.map(|m| m.url(url))
+	}
+
+	/// Applies any other builder method of [`Match`] to the match.
+	///
+	/// # Example
+	/// ```
+	/// use krunner::Match;
+	///
+	/// let m: Match<std::convert::Infallible> = Match::builder("answer")
+	/// 	.title("42")
+	/// 	.map(Match::infer_type)
+	/// 	.build();
+	/// ```
+	#[must_use]
+	pub fn map(mut self, f: impl FnOnce(Match<A>) -> Match<A>) -> Self {
+		self.inner = f(self.inner);
+		self
+	}
+}
+
+impl<A> MatchBuilder<A, Titled> {
+	/// Finishes building the match.
+	#[must_use]
+	pub fn build(self) -> Match<A> {
+		self.inner
+	}
+}
+
+impl<A> From<MatchBuilder<A, Titled>> for Match<A> {
+	fn from(builder: MatchBuilder<A, Titled>) -> Self {
+		builder.build()
+	}
+}
@@ -0,0 +1,110 @@
+/// Scores how well `candidate` matches `query`, as a relevance suitable for
+/// [`Match::relevance`](crate::Match::relevance).
+///
+/// The comparison is case-insensitive, and ranks candidates by how the query
+/// is found in them:
+///
+/// - `1.0` if the candidate is the query;
+/// - between `0.8` and `1.0` if the candidate starts with the query;
+/// - between `0.6` and `0.8` if the candidate contains the query;
+/// - between `0.2` and `0.6` if the candidate contains all characters of the
+///   query in order, e.g. `ffx` in `Firefox`;
+/// - `0.0` otherwise, or if the query is empty.
+///
+/// Within each of these, candidates that are closer in length to the query,
+/// and whose matching characters are closer together, score higher.
+///
+/// This is cheap enough to run over a few thousand candidates for every
+/// query; for larger data sets, consider a proper search index instead.
+///
+/// # Example
+/// ```
+/// use krunner::fuzzy_score;
+///
+/// assert_eq!(fuzzy_score("firefox", "Firefox"), 1.0);
+/// assert!(fuzzy_score("fire", "Firefox") > fuzzy_score("fox", "Firefox"));
+/// assert!(fuzzy_score("fox", "Firefox") > fuzzy_score("ffx", "Firefox"));
+/// assert_eq!(fuzzy_score("chrome", "Firefox"), 0.0);
+/// ```
+#[must_use]
+pub fn fuzzy_score(query: &str, candidate: &str) -> f64 {
+	let query = query.to_lowercase();
+	let candidate = candidate.to_lowercase();
+	if query.is_empty() {
+		return 0.0;
+	}
+	if query == candidate {
+		return 1.0;
+	}
+
+	let query_len = query.chars().count();
+	let candidate_len = candidate.chars().count();
+	let coverage = ratio(query_len, candidate_len);
+
+	if candidate.starts_with(&query) {
+		0.8 + 0.2 * coverage
+	} else if candidate.contains(&query) {
+		0.6 + 0.2 * coverage
+	} else if let Some(span) = subsequence_span(&query, &candidate) {
+		0.2 + 0.2 * ratio(query_len, span) + 0.2 * coverage
+	} else {
+		0.0
+	}
+	.min(1.0)
+}
+
+/// The number of characters between the first and the last character of
+/// `query` when found in order in `candidate`, both inclusive.
+fn subsequence_span(query: &str, candidate: &str) -> Option<usize> {
+	let mut query = query.chars().peekable();
+	let mut start = None;
+	for (i, c) in candidate.chars().enumerate() {
+		if query.peek() == Some(&c) {
+			query.next();
+			let start = *start.get_or_insert(i);
+			if query.peek().is_none() {
+				return Some(i - start + 1);
+			}
+		}
+	}
+	None
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn ratio(a: usize, b: usize) -> f64 {
+	a as f64 / b as f64
+}
+
+/// Computes the relevance of `candidate` to `query` from the edit distance
+/// between the two, so that near-misses and typos still surface as matches.
+///
+/// The metric used is the [Damerau-Levenshtein
+/// distance](https://en.wikipedia.org/wiki/Damerau%E2%80%93Levenshtein_distance),
+/// i.e. the number of insertions, deletions, substitutions and transpositions
+/// of adjacent characters needed to turn one string into the other. The
+/// distance is then normalized by the length of the longer string, and
+/// inverted so that identical strings have a relevance of `1.0` and strings
+/// with nothing in common have a relevance of `0.0`.
+///
+/// The comparison is case-sensitive; lowercase both strings beforehand for a
+/// case-insensitive comparison.
+///
+/// Computing the distance takes time and memory proportional to the product of
+/// the lengths of both strings, so this is best reserved for short candidates
+/// like names or titles, rather than whole documents.
+///
+/// # Example
+/// ```
+/// let exact = krunner::relevance_from_distance("firefox", "firefox");
+/// let typo = krunner::relevance_from_distance("firefxo", "firefox");
+/// let unrelated = krunner::relevance_from_distance("firefox", "dolphin");
+///
+/// assert_eq!(exact, 1.0);
+/// assert!(typo > unrelated);
+/// ```
+#[cfg(feature = "strsim")]
+#[cfg_attr(docs_rs, doc(cfg(feature = "strsim")))]
+#[must_use]
+pub fn relevance_from_distance(query: &str, candidate: &str) -> f64 {
+	strsim::normalized_damerau_levenshtein(query, candidate).clamp(0.0, 1.0)
+}
@@ -0,0 +1,387 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::{fs, io};
+
+/// Options used to generate the files KRunner needs to discover a runner.
+///
+/// KRunner finds D-Bus runners through a `.desktop` file that names the
+/// service and object path to talk to, while the D-Bus daemon needs a
+/// `.service` file to start the runner on demand. Both can be generated
+/// with [`write_metadata`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MetadataOptions {
+	/// The human-friendly name of the runner, as shown in the Plasma Search
+	/// settings.
+	///
+	/// KRunner also uses this as the category of matches that don't have a
+	/// [category](crate::Match::category) of their own.
+	pub name: String,
+	/// A short description of what the runner does.
+	pub comment: Option<String>,
+	/// The name of the icon of the runner.
+	pub icon: Option<String>,
+	/// The version of the KRunner D-Bus API the runner implements.
+	pub x_plasma_api: PlasmaApi,
+	/// The name of a KCM plugin that renders the settings page of the runner.
+	///
+	/// When set, Plasma Search shows a configure button for the runner which
+	/// opens the KCM. See [`ConfigPage`](crate::ConfigPage) for reading back
+	/// the values it stores.
+	pub config_module: Option<String>,
+	/// Whether KRunner should only ask for the actions of the runner once,
+	/// instead of at the start of every matching session.
+	///
+	/// This saves a D-Bus round-trip per session. It must not be combined with
+	/// [`available_actions`](crate::Runner::available_actions), as KRunner
+	/// would then keep whichever actions happened to be available when it
+	/// first asked.
+	pub request_actions_once: bool,
+	/// Whether the runner can be used in KRunner's single runner mode, in
+	/// which only its matches are shown.
+	pub single_runner_mode: bool,
+	/// Example queries understood by the runner.
+	///
+	/// KRunner shows these as help for the runner, and in single runner mode
+	/// the first one serves as a hint for what to type.
+	pub syntaxes: Vec<Syntax>,
+}
+
+/// An example query understood by a runner, along with what it does.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Syntax {
+	/// The example query (e.g. `:q:` for the query text, or `units :q:`).
+	pub query: String,
+	/// A description of what the query does (e.g. `Type a package name...`).
+	pub description: String,
+}
+
+/// Versions of the KRunner D-Bus API.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum PlasmaApi {
+	/// The original D-Bus API, without support for the `Config` method.
+	DBus,
+	/// The second revision of the D-Bus API, which supports the `Config`
+	/// method. This is the only version understood by KRunner 6.
+	#[default]
+	DBus2,
+}
+
+/// Writes the `.desktop` and D-Bus `.service` files for a runner into
+/// `out_dir`.
+///
+/// The files are named `plasma-runner-<service>.desktop` and
+/// `<service>.service` respectively. The `.service` file launches the
+/// currently running executable, so this is best called from within the
+/// runner binary itself (e.g. behind a command-line flag).
+///
+/// The `.desktop` file should then be installed to
+/// `~/.local/share/krunner/dbusplugins/`, and the `.service` file to
+/// `~/.local/share/dbus-1/services/`, which [`install_metadata`] does
+/// directly.
+///
+/// # Errors
+/// Returns an error if `service` is not a valid D-Bus service name, or if
+/// either file can't be written.
+///
+/// # Example
+/// ```no_run
+/// use krunner::{MetadataOptions, PlasmaApi};
+///
+/// krunner::write_metadata(
+/// 	"target/metadata",
+/// 	"some.runner.path",
+/// 	"/SomeRunner",
+/// 	&MetadataOptions {
+/// 		name: "Some Runner".to_owned(),
+/// 		comment: Some("Does some things".to_owned()),
+/// 		icon: Some("system-run".to_owned()),
+/// 		x_plasma_api: PlasmaApi::DBus2,
+/// 		config_module: None,
+/// 		request_actions_once: true,
+/// 		single_runner_mode: false,
+/// 		syntaxes: vec![],
+/// 	},
+/// )?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn write_metadata(
+	out_dir: impl AsRef<Path>,
+	service: &str,
+	path: &str,
+	options: &MetadataOptions,
+) -> io::Result<()> {
+	crate::settings::validate_service(service)?;
+	let out_dir = out_dir.as_ref();
+	let exec = std::env::current_exe()?;
+
+	fs::create_dir_all(out_dir)?;
+	fs::write(
+		out_dir.join(format!("plasma-runner-{service}.desktop")),
+		options.desktop_entry(service, path),
+	)?;
+	fs::write(
+		out_dir.join(format!("{service}.service")),
+		dbus_service(service, &exec),
+	)
+}
+
+/// Installs the `.desktop` and D-Bus `.service` files for a runner for the
+/// current user, so that KRunner picks it up and the D-Bus daemon starts it
+/// on demand.
+///
+/// The files are the same as those written by [`write_metadata`], and are
+/// installed to `$XDG_DATA_HOME/krunner/dbusplugins/` and
+/// `$XDG_DATA_HOME/dbus-1/services/` respectively, replacing any earlier
+/// versions. KRunner may need to be restarted to notice a new runner.
+///
+/// Once installed, the runner no longer has to be running for KRunner to
+/// find it: the D-Bus daemon launches the current executable, without any
+/// arguments, the first time KRunner calls it. Nothing special is needed to
+/// support that, as [`start`](crate::RunnerExt::start) and friends request
+/// the service name the same way whether the runner was launched by the
+/// user or by D-Bus activation.
+///
+/// # Errors
+/// Returns an error if `service` is not a valid D-Bus service name, if the
+/// data directory can't be found, or if either file can't be written.
+///
+/// # Example
+/// ```no_run
+/// use krunner::{MetadataOptions, PlasmaApi};
+///
+/// if std::env::args().any(|arg| arg == "--install") {
+/// 	krunner::install_metadata("some.runner.path", "/SomeRunner", &MetadataOptions {
+/// 		name: "Some Runner".to_owned(),
+/// 		comment: None,
+/// 		icon: Some("system-run".to_owned()),
+/// 		x_plasma_api: PlasmaApi::DBus2,
+/// 		config_module: None,
+/// 		request_actions_once: true,
+/// 		single_runner_mode: false,
+/// 		syntaxes: vec![],
+/// 	})?;
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn install_metadata(service: &str, path: &str, options: &MetadataOptions) -> io::Result<()> {
+	crate::settings::validate_service(service)?;
+	let data_home = crate::settings::data_home()?;
+	let exec = std::env::current_exe()?;
+
+	let plugins = data_home.join("krunner/dbusplugins");
+	fs::create_dir_all(&plugins)?;
+	fs::write(
+		plugins.join(format!("plasma-runner-{service}.desktop")),
+		options.desktop_entry(service, path),
+	)?;
+	let services = data_home.join("dbus-1/services");
+	fs::create_dir_all(&services)?;
+	fs::write(
+		services.join(format!("{service}.service")),
+		dbus_service(service, &exec),
+	)
+}
+
+/// Renders the contents of the D-Bus `.service` file that allows the D-Bus
+/// daemon to start the runner on demand by running `exec`.
+#[must_use]
+pub fn dbus_service(service: &str, exec: &Path) -> String {
+	format!(
+		"[D-BUS Service]\nName={service}\nExec={}\n",
+		escape_value(&exec.to_string_lossy())
+	)
+}
+
+impl MetadataOptions {
+	/// Renders the contents of the `.desktop` file that tells KRunner where
+	/// to find the runner.
+	#[must_use]
+	pub fn desktop_entry(&self, service: &str, path: &str) -> String {
+		let mut entry = String::from("[Desktop Entry]\n");
+		let mut push = |key: &str, value: &str| {
+			entry.push_str(key);
+			entry.push('=');
+			entry.push_str(&escape_value(value));
+			entry.push('\n');
+		};
+
+		push("Name", &self.name);
+		if let Some(comment) = &self.comment {
+			push("Comment", comment);
+		}
+		if let Some(icon) = &self.icon {
+			push("Icon", icon);
+		}
+		push("Type", "Service");
+		push("X-KDE-ServiceTypes", "Plasma/Runner");
+		push("X-KDE-PluginInfo-Name", service);
+		push("X-KDE-PluginInfo-EnabledByDefault", "true");
+		push("X-Plasma-API", self.x_plasma_api.as_str());
+		if self.x_plasma_api == PlasmaApi::DBus2 {
+			push("X-Plasma-API-Minimum-Version", "2.0");
+		}
+		push("X-Plasma-DBusRunner-Service", service);
+		push("X-Plasma-DBusRunner-Path", path);
+		if self.request_actions_once {
+			push("X-Plasma-Request-Actions-Once", "true");
+		}
+		if self.single_runner_mode {
+			push("X-Plasma-AdvertiseSingleRunnerQueryMode", "true");
+		}
+		if let Some(config_module) = &self.config_module {
+			push("X-KDE-ConfigModule", config_module);
+		}
+		if !self.syntaxes.is_empty() {
+			// Commas separate list items, so they have to be escaped on top of
+			// the usual escaping
+			let list = |f: fn(&Syntax) -> &str| {
+				let items: Vec<_> = self
+					.syntaxes
+					.iter()
+					.map(|s| escape_value(f(s)).replace(',', "\\,"))
+					.collect();
+				items.join(",")
+			};
+			let _ = writeln!(entry, "X-Plasma-Runner-Syntaxes={}", list(|s| &s.query));
+			let _ = writeln!(
+				entry,
+				"X-Plasma-Runner-Syntax-Descriptions={}",
+				list(|s| &s.description)
+			);
+		}
+
+		entry
+	}
+
+	/// Renders the contents of the JSON plugin metadata that tells KRunner
+	/// where to find the runner.
+	///
+	/// This is the metadata format used by Plasma 6, and can be installed in
+	/// place of the [`.desktop` file](Self::desktop_entry) as
+	/// `plasma-runner-<service>.json`.
+	#[must_use]
+	pub fn json_metadata(&self, service: &str, path: &str) -> String {
+		let mut plugin = vec![];
+		if let Some(comment) = &self.comment {
+			plugin.push(("Description", json_string(comment)));
+		}
+		plugin.push(("EnabledByDefault", "true".to_owned()));
+		if let Some(icon) = &self.icon {
+			plugin.push(("Icon", json_string(icon)));
+		}
+		plugin.push(("Id", json_string(service)));
+		plugin.push(("Name", json_string(&self.name)));
+
+		let mut root = vec![("KPlugin", json_object(&plugin, 1))];
+		if let Some(config_module) = &self.config_module {
+			root.push(("X-KDE-ConfigModule", json_string(config_module)));
+		}
+		if self.x_plasma_api == PlasmaApi::DBus2 {
+			root.push(("X-Plasma-API-Minimum-Version", json_string("2.0")));
+		}
+		if self.single_runner_mode {
+			root.push(("X-Plasma-AdvertiseSingleRunnerQueryMode", "true".to_owned()));
+		}
+		root.push(("X-Plasma-DBusRunner-Path", json_string(path)));
+		root.push(("X-Plasma-DBusRunner-Service", json_string(service)));
+		if self.request_actions_once {
+			root.push(("X-Plasma-Request-Actions-Once", "true".to_owned()));
+		}
+		if !self.syntaxes.is_empty() {
+			let list = |f: fn(&Syntax) -> &str| {
+				let items: Vec<_> = self.syntaxes.iter().map(|s| json_string(f(s))).collect();
+				json_array(&items, 1)
+			};
+			root.push((
+				"X-Plasma-Runner-Syntax-Descriptions",
+				list(|s| &s.description),
+			));
+			root.push(("X-Plasma-Runner-Syntaxes", list(|s| &s.query)));
+		}
+
+		let mut out = json_object(&root, 0);
+		out.push('\n');
+		out
+	}
+}
+
+impl PlasmaApi {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::DBus => "DBus",
+			Self::DBus2 => "DBus2",
+		}
+	}
+}
+
+fn escape_value(s: &str) -> String {
+	let mut out = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			'\r' => out.push_str("\\r"),
+			c => out.push(c),
+		}
+	}
+	out
+}
+
+/// Renders a JSON object with the given keys and pre-rendered values,
+/// indented like KDE's own plugin metadata.
+fn json_object(entries: &[(&str, String)], depth: usize) -> String {
+	let indent = "    ".repeat(depth + 1);
+	let mut out = String::from("{\n");
+	for (i, (key, value)) in entries.iter().enumerate() {
+		out.push_str(&indent);
+		out.push_str(&json_string(key));
+		out.push_str(": ");
+		out.push_str(value);
+		if i + 1 < entries.len() {
+			out.push(',');
+		}
+		out.push('\n');
+	}
+	out.push_str(&"    ".repeat(depth));
+	out.push('}');
+	out
+}
+
+/// Renders a JSON array with the given pre-rendered values, indented like
+/// [`json_object`].
+fn json_array(items: &[String], depth: usize) -> String {
+	let indent = "    ".repeat(depth + 1);
+	let mut out = String::from("[\n");
+	for (i, item) in items.iter().enumerate() {
+		out.push_str(&indent);
+		out.push_str(item);
+		if i + 1 < items.len() {
+			out.push(',');
+		}
+		out.push('\n');
+	}
+	out.push_str(&"    ".repeat(depth));
+	out.push(']');
+	out
+}
+
+fn json_string(s: &str) -> String {
+	let mut out = String::with_capacity(s.len() + 2);
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			'\r' => out.push_str("\\r"),
+			c if c.is_control() => {
+				let _ = write!(out, "\\u{:04x}", u32::from(c));
+			}
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
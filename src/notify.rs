@@ -0,0 +1,217 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use dbus::arg::PropMap;
+use dbus::blocking::Connection;
+
+use crate::{Config, ConfigPage, Match, Runner, RunnerContext, TeardownPolicy};
+
+/// Shows a desktop notification about an error, through the
+/// [`org.freedesktop.Notifications`](https://specifications.freedesktop.org/notification-spec/latest/)
+/// service of the session bus.
+///
+/// `app_name` and `icon` (an icon name, or empty for none) identify who the
+/// notification comes from, and `summary` says what went wrong, with the
+/// error itself as the body.
+///
+/// # Example
+/// ```no_run
+/// let error = std::io::Error::other("no such program: firefox");
+/// krunner::notify_error("Launcher", "system-run", "Cannot launch Firefox", &error)?;
+/// # Ok::<(), dbus::Error>(())
+/// ```
+pub fn notify_error(
+	app_name: &str,
+	icon: &str,
+	summary: &str,
+	error: &dyn Display,
+) -> Result<(), dbus::Error> {
+	let c = Connection::new_session()?;
+	let proxy = c.with_proxy(
+		"org.freedesktop.Notifications",
+		"/org/freedesktop/Notifications",
+		Duration::from_secs(5),
+	);
+	let (_id,): (u32,) = proxy.method_call(
+		"org.freedesktop.Notifications",
+		"Notify",
+		(
+			app_name,
+			0u32,
+			icon,
+			summary,
+			error.to_string(),
+			Vec::<String>::new(),
+			PropMap::new(),
+			-1i32,
+		),
+	)?;
+	Ok(())
+}
+
+/// A [`Runner`] that shows a desktop notification whenever running one of its
+/// matches fails.
+///
+/// KRunner doesn't show the errors returned by [`Runner::run`] anywhere, so
+/// without this, an action that fails (e.g. because the program it launches
+/// is missing) looks as if nothing happened. Errors from
+/// [`Runner::matches`] are not reported, as they would show up on every
+/// keystroke.
+///
+/// Notifications are sent with [`notify_error`]. If that fails, the failure
+/// is only logged, and the original error is still returned to KRunner.
+///
+/// # Example
+/// ```no_run
+/// use krunner::{Match, NotifyErrors, RunnerContext, RunnerExt};
+///
+/// struct Launcher;
+///
+/// impl krunner::Runner for Launcher {
+/// 	type Action = std::convert::Infallible;
+/// 	type Err = std::io::Error;
+///
+/// 	fn matches(
+/// 		&mut self,
+/// 		query: String,
+/// 		_ctx: &RunnerContext,
+/// 	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+/// 		Ok(vec![Match::builder(&query).title(&query).build()])
+/// 	}
+///
+/// 	fn run(
+/// 		&mut self,
+/// 		match_id: String,
+/// 		_action: Option<Self::Action>,
+/// 		_ctx: &RunnerContext,
+/// 	) -> Result<(), Self::Err> {
+/// 		// Shown to the user if the program can't be found
+/// 		std::process::Command::new(match_id).spawn()?;
+/// 		Ok(())
+/// 	}
+/// }
+///
+/// NotifyErrors::new(Launcher, "Launcher")
+/// 	.with_icon("system-run")
+/// 	.start("some.runner.path", "/Launcher")?;
+/// # Ok::<(), dbus::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct NotifyErrors<R> {
+	runner: R,
+	app_name: String,
+	icon: String,
+}
+
+impl<R> NotifyErrors<R> {
+	/// Wraps the given runner, naming it `app_name` in notifications.
+	pub fn new(runner: R, app_name: impl Into<String>) -> Self {
+		Self {
+			runner,
+			app_name: app_name.into(),
+			icon: String::new(),
+		}
+	}
+
+	/// Sets the name of the icon shown in notifications.
+	#[must_use]
+	pub fn with_icon(mut self, icon: impl Into<String>) -> Self {
+		self.icon = icon.into();
+		self
+	}
+
+	/// The wrapped runner.
+	pub fn runner(&mut self) -> &mut R {
+		&mut self.runner
+	}
+
+	/// Unwraps the runner.
+	pub fn into_inner(self) -> R {
+		self.runner
+	}
+
+	/// Shows a notification about the error in `result`, if there is one.
+	#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+	fn notify<T, E: Display>(&self, result: Result<T, E>) -> Result<T, E> {
+		if let Err(e) = &result {
+			if let Err(notify_err) = notify_error(&self.app_name, &self.icon, "Action failed", e) {
+				event!(warn, error = %notify_err, "cannot send notification");
+			}
+		}
+		result
+	}
+}
+
+impl<R: Runner> Runner for NotifyErrors<R> {
+	type Action = R::Action;
+	type Err = R::Err;
+
+	const INTERFACE: &'static str = R::INTERFACE;
+
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		self.runner.matches(query, ctx)
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		let result = self.runner.run(match_id, action, ctx);
+		self.notify(result)
+	}
+
+	fn run_many(
+		&mut self,
+		runs: Vec<(String, Option<Self::Action>)>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		let result = self.runner.run_many(runs, ctx);
+		self.notify(result)
+	}
+
+	fn default_action_for(&self, match_id: &str) -> Option<Self::Action> {
+		self.runner.default_action_for(match_id)
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		self.runner.config()
+	}
+
+	fn config_is_static(&self) -> bool {
+		self.runner.config_is_static()
+	}
+
+	fn config_page(&self) -> Option<ConfigPage> {
+		self.runner.config_page()
+	}
+
+	fn available_actions(&self) -> Option<Vec<Self::Action>> {
+		self.runner.available_actions()
+	}
+
+	fn allowed_activities(&self) -> Option<Vec<String>> {
+		self.runner.allowed_activities()
+	}
+
+	fn debug_relevance(&self) -> bool {
+		self.runner.debug_relevance()
+	}
+
+	fn startup(&mut self) -> Result<(), Self::Err> {
+		self.runner.startup()
+	}
+
+	fn teardown_policy(&self) -> TeardownPolicy {
+		self.runner.teardown_policy()
+	}
+
+	fn teardown(&mut self) -> Result<(), Self::Err> {
+		self.runner.teardown()
+	}
+}
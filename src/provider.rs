@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::sync::atomic::AtomicBool;
+
+use dbus::blocking::Connection;
+use dbus::MethodErr;
+use dbus_crossroads::{Crossroads, IfaceToken};
+
+use crate::{Action, ActionInfo, Match, NameFlags, RunnerContext};
+
+/// A source of matches that is only known at runtime.
+///
+/// Unlike [`Runner`](crate::Runner)s, match providers are used as trait
+/// objects, and their actions are plain values instead of a type. This allows
+/// a [`ProviderHost`] to combine any number of them, e.g. loaded from plugins
+/// or from a configuration file, behind a single KRunner D-Bus object.
+pub trait MatchProvider: Send {
+	/// The name of this provider, which must be unique within a
+	/// [`ProviderHost`] and may not contain `/`.
+	///
+	/// The IDs of matches and actions are prefixed with this name before
+	/// being sent to KRunner, so that they can be routed back to this
+	/// provider.
+	fn name(&self) -> &str;
+
+	/// Every action this provider can perform.
+	fn actions(&self) -> Vec<DynAction> {
+		vec![]
+	}
+
+	/// Returns the matches for the given query.
+	fn matches(&self, query: &str, ctx: &RunnerContext) -> Vec<Match<DynAction>>;
+
+	/// Runs a match previously returned by [`matches`](Self::matches), with
+	/// an optional action chosen by the user.
+	fn run(
+		&self,
+		match_id: &str,
+		action: Option<&DynAction>,
+		ctx: &RunnerContext,
+	) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// An action of a [`MatchProvider`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DynAction {
+	/// The ID of the action, unique within its provider.
+	pub id: String,
+	/// Associated information about the action.
+	pub info: ActionInfo,
+}
+
+/// Serves several [`MatchProvider`]s behind a single KRunner D-Bus object.
+///
+/// # Example
+/// See `examples/providers.rs`.
+#[derive(Default)]
+pub struct ProviderHost {
+	providers: Vec<Box<dyn MatchProvider>>,
+}
+
+impl ProviderHost {
+	/// Creates a host without any providers.
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a provider.
+	///
+	/// # Panics
+	/// Panics if the [name](MatchProvider::name) of the provider contains `/`,
+	/// or is already taken by another provider.
+	#[must_use]
+	pub fn with_provider(mut self, provider: impl MatchProvider + 'static) -> Self {
+		self.add(Box::new(provider));
+		self
+	}
+
+	/// Adds a provider, e.g. one loaded at runtime.
+	///
+	/// # Panics
+	/// Panics if the [name](MatchProvider::name) of the provider contains `/`,
+	/// or is already taken by another provider.
+	pub fn add(&mut self, provider: Box<dyn MatchProvider>) {
+		let name = provider.name();
+		assert!(
+			!name.contains('/'),
+			"match provider name {name:?} must not contain '/'"
+		);
+		assert!(
+			!self.providers.iter().any(|p| p.name() == name),
+			"match provider name {name:?} is already taken"
+		);
+		self.providers.push(provider);
+	}
+
+	/// Starts serving all providers on the main thread indefinitely.
+	///
+	/// This is the equivalent of [`RunnerExt::start`](crate::RunnerExt::start)
+	/// for match providers.
+	pub fn start(self, service: &'static str, path: &'static str) -> Result<(), dbus::Error> {
+		crate::builder::validate_service(service)?;
+		crate::builder::validate_path(path)?;
+		let c = Connection::new_session()?;
+		NameFlags::default().request(&c, service)?;
+
+		let mut cr = Crossroads::new();
+		let token = Self::register(&mut cr);
+		cr.insert(path, &[token], self);
+		crate::host::serve_until(cr, vec![], &c, &AtomicBool::new(false))
+	}
+
+	#[doc = include_str!("./docs/runnerext/register.md")]
+	pub fn register(cr: &mut Crossroads) -> IfaceToken<Self> {
+		cr.register(crate::KRUNNER_INTERFACE, |b| {
+			b.method("Actions", (), ("matches",), |_, host: &mut Self, (): ()| {
+				let actions: Vec<_> = host
+					.providers
+					.iter()
+					.flat_map(|p| p.actions().into_iter().map(|a| namespaced(p.as_ref(), a)))
+					.map(|a| crate::action_as_arg(&a))
+					.collect();
+				Ok((actions,))
+			});
+			b.method(
+				"Run",
+				("matchId", "actionId"),
+				(),
+				|ctx, host, (match_id, action_id): (String, String)| {
+					let Some((name, match_id)) = match_id.split_once('/') else {
+						return Err(MethodErr::invalid_arg("Unknown match"));
+					};
+					let Some(provider) = host.providers.iter().find(|p| p.name() == name) else {
+						return Err(MethodErr::invalid_arg("Unknown match"));
+					};
+
+					let action = if action_id.is_empty() {
+						None
+					} else {
+						let id = action_id
+							.strip_prefix(name)
+							.and_then(|id| id.strip_prefix('/'))
+							.ok_or_else(|| MethodErr::invalid_arg("Unknown action"))?;
+						let action = provider.actions().into_iter().find(|a| a.id == id);
+						Some(action.ok_or_else(|| MethodErr::invalid_arg("Unknown action"))?)
+					};
+
+					provider
+						.run(match_id, action.as_ref(), &RunnerContext::new(ctx))
+						.map_err(|e| crate::method_err(&e))
+				},
+			);
+			b.method(
+				"Match",
+				("query",),
+				("matches",),
+				|ctx, host, (query,): (String,)| {
+					let ctx = RunnerContext::new(ctx);
+					let matches: Vec<_> = host
+						.providers
+						.iter()
+						.flat_map(|p| {
+							let provider = p.as_ref();
+							provider
+								.matches(&query, &ctx)
+								.into_iter()
+								.map(move |m| Match {
+									id: format!("{}/{}", provider.name(), m.id),
+									actions: m
+										.actions
+										.into_iter()
+										.map(|a| namespaced(provider, a))
+										.collect(),
+									..m
+								})
+						})
+						.collect();
+					Ok((matches,))
+				},
+			);
+		})
+	}
+}
+
+fn namespaced(provider: &dyn MatchProvider, action: DynAction) -> DynAction {
+	DynAction {
+		id: format!("{}/{}", provider.name(), action.id),
+		..action
+	}
+}
+
+/// As dynamic actions aren't known statically, [`all`](Action::all) is always
+/// empty and [`from_id`](Action::from_id) always fails; a [`ProviderHost`]
+/// asks its providers for their actions instead.
+impl Action for DynAction {
+	fn all() -> &'static [Self] {
+		&[]
+	}
+
+	fn from_id(_: &str) -> Option<Self> {
+		None
+	}
+
+	fn to_id(&self) -> String {
+		self.id.clone()
+	}
+
+	fn info(&self) -> ActionInfo {
+		self.info.clone()
+	}
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// A settings page for a runner, described as a list of fields.
+///
+/// Runners declare their settings page through
+/// [`Runner::config_page`](crate::Runner::config_page), and read back the
+/// values the user chose with [`load`](Self::load). Plasma can't render
+/// settings for D-Bus runners by itself, so the page has to be shown by a KCM
+/// named in the [`config_module`](crate::MetadataOptions::config_module) of
+/// the plugin metadata, e.g. one generated from these fields.
+///
+/// Values are persisted with [KConfig](https://api.kde.org/frameworks/kconfig/html/),
+/// in the same file and group that Plasma uses to store settings for KRunner
+/// plugins, so that the KCM and the runner always agree on where settings
+/// live.
+///
+/// # Example
+/// ```no_run
+/// use krunner::{ConfigField, ConfigPage, ConfigValue};
+///
+/// let page = ConfigPage {
+/// 	fields: vec![
+/// 		ConfigField::new(
+/// 			"max-results",
+/// 			"Maximum number of results",
+/// 			ConfigValue::Int(10),
+/// 		),
+/// 		ConfigField::new("show-icons", "Show icons", ConfigValue::Bool(true)),
+/// 	],
+/// };
+///
+/// let values = page.load_for_plugin("some.runner.path")?;
+/// assert!(matches!(values["max-results"], ConfigValue::Int(_)));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigPage {
+	/// The fields on this page, in display order.
+	pub fields: Vec<ConfigField>,
+}
+
+/// A single field on a [`ConfigPage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigField {
+	/// The key under which the value of this field is stored.
+	pub key: String,
+	/// The human-friendly label of this field.
+	pub label: String,
+	/// The value of this field when the user hasn't set one.
+	///
+	/// This also determines the type of the field.
+	pub default: ConfigValue,
+}
+
+/// The value of a [`ConfigField`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+	/// A boolean, usually rendered as a checkbox.
+	Bool(bool),
+	/// An integer, usually rendered as a spin box.
+	Int(i64),
+	/// A string, usually rendered as a text field.
+	String(String),
+	/// A list of strings.
+	StringList(Vec<String>),
+}
+
+impl ConfigPage {
+	/// Reads the values of all fields on this page from the group `group` of
+	/// the KConfig file at `path`.
+	///
+	/// Fields that haven't been set, or whose value can't be parsed as the type
+	/// of the field, take on their default value. A missing file is treated as
+	/// an empty one.
+	///
+	/// Nested groups are separated by `][`, just like in the file itself (e.g.
+	/// `Runners][some.runner.path`).
+	pub fn load(
+		&self,
+		path: impl AsRef<Path>,
+		group: &str,
+	) -> io::Result<HashMap<String, ConfigValue>> {
+		let contents = match fs::read_to_string(path) {
+			Ok(c) => c,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e),
+		};
+		let entries = parse_group(&contents, group);
+
+		Ok(self
+			.fields
+			.iter()
+			.map(|field| {
+				let value = entries
+					.get(field.key.as_str())
+					.and_then(|raw| field.default.parse_like(raw))
+					.unwrap_or_else(|| field.default.clone());
+				(field.key.clone(), value)
+			})
+			.collect())
+	}
+
+	/// Reads the values of all fields on this page from where Plasma stores
+	/// the settings of the KRunner plugin with the given ID, i.e. the
+	/// `[Runners][<plugin_id>]` group of `$XDG_CONFIG_HOME/krunnerrc`.
+	///
+	/// Plugins set up with [`write_metadata`](crate::write_metadata) use their
+	/// service name as their plugin ID.
+	pub fn load_for_plugin(&self, plugin_id: &str) -> io::Result<HashMap<String, ConfigValue>> {
+		self.load(
+			config_home()?.join("krunnerrc"),
+			&format!("Runners][{plugin_id}"),
+		)
+	}
+}
+
+impl ConfigField {
+	/// Creates a new field.
+	pub fn new(key: impl Into<String>, label: impl Into<String>, default: ConfigValue) -> Self {
+		Self {
+			key: key.into(),
+			label: label.into(),
+			default,
+		}
+	}
+}
+
+impl ConfigValue {
+	/// Parses a raw KConfig value as the same type as `self`.
+	fn parse_like(&self, raw: &str) -> Option<Self> {
+		Some(match self {
+			Self::Bool(_) => Self::Bool(match raw.to_ascii_lowercase().as_str() {
+				"true" | "on" | "yes" | "1" => true,
+				"false" | "off" | "no" | "0" => false,
+				_ => return None,
+			}),
+			Self::Int(_) => Self::Int(raw.trim().parse().ok()?),
+			Self::String(_) => Self::String(unescape(raw)),
+			Self::StringList(_) => Self::StringList(split_list(raw)),
+		})
+	}
+}
+
+fn config_home() -> io::Result<PathBuf> {
+	if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+		return Ok(dir.into());
+	}
+	env::var_os("HOME")
+		.map(|home| PathBuf::from(home).join(".config"))
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not find home directory"))
+}
+
+pub(crate) fn data_home() -> io::Result<PathBuf> {
+	if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+		return Ok(dir.into());
+	}
+	env::var_os("HOME")
+		.map(|home| PathBuf::from(home).join(".local/share"))
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not find home directory"))
+}
+
+/// Checks that `service` is a valid service name before it's used to build a
+/// path.
+pub(crate) fn validate_service(service: &str) -> io::Result<()> {
+	crate::builder::validate_service(service).map_err(|e| {
+		io::Error::new(
+			io::ErrorKind::InvalidInput,
+			e.message().unwrap_or("invalid service name"),
+		)
+	})
+}
+
+/// Finds the config directory of the runner with the given service name,
+/// creating it if needed.
+pub(crate) fn runner_config_dir(service: &str) -> io::Result<PathBuf> {
+	validate_service(service)?;
+	let dir = config_home()?.join(service);
+	fs::create_dir_all(&dir)?;
+	Ok(dir)
+}
+
+/// Collects the raw entries of a group in a KConfig file.
+fn parse_group<'a>(contents: &'a str, group: &str) -> HashMap<&'a str, &'a str> {
+	let mut entries = HashMap::new();
+	let mut in_group = false;
+
+	for line in contents.lines() {
+		let line = line.trim_start();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		if let Some(header) = line.strip_prefix('[') {
+			in_group = header.trim_end().strip_suffix(']') == Some(group);
+			continue;
+		}
+		if !in_group {
+			continue;
+		}
+		let Some((key, value)) = line.split_once('=') else {
+			continue;
+		};
+		let key = key.trim_end();
+		// Entries with options (e.g. `key[$e]`) apply to us too, but localized
+		// entries (e.g. `key[de]`) do not
+		let key = match key.split_once('[') {
+			Some((key, opts)) if opts.starts_with('$') => key,
+			Some(_) => continue,
+			None => key,
+		};
+		entries.insert(key, value.trim());
+	}
+	entries
+}
+
+fn unescape(raw: &str) -> String {
+	let mut out = String::with_capacity(raw.len());
+	let mut chars = raw.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			out.push(c);
+			continue;
+		}
+		match chars.next() {
+			Some('n') => out.push('\n'),
+			Some('t') => out.push('\t'),
+			Some('r') => out.push('\r'),
+			Some('s') => out.push(' '),
+			Some(c) => out.push(c),
+			None => out.push('\\'),
+		}
+	}
+	out
+}
+
+fn split_list(raw: &str) -> Vec<String> {
+	if raw.is_empty() {
+		return vec![];
+	}
+
+	let mut items = vec![];
+	let mut current = String::new();
+	let mut chars = raw.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' => match chars.next() {
+				Some(',') => current.push(','),
+				Some(c) => {
+					current.push('\\');
+					current.push(c);
+				}
+				None => current.push('\\'),
+			},
+			',' => items.push(unescape(&std::mem::take(&mut current))),
+			c => current.push(c),
+		}
+	}
+	items.push(unescape(&current));
+	items
+}
@@ -0,0 +1,172 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{Config, ConfigPage, Match, Runner, RunnerContext, TeardownPolicy};
+
+/// A [`Runner`] whose state can be shared, e.g. between several object paths
+/// or with other interfaces.
+///
+/// Clones share the same runner, which is locked for the duration of each
+/// call. This is the synchronous counterpart of the `Arc<Mutex<_>>` that
+/// asynchronous runners are registered with, and it is
+/// a [`Runner`] itself, so it can be served like any other.
+///
+/// A runner served on several paths of a [`RunnerHost`](crate::RunnerHost)
+/// has [`startup`](Runner::startup) and [`teardown`](Runner::teardown)
+/// called once for each of them.
+///
+/// # Example
+/// ```no_run
+/// use krunner::{Match, Runner, RunnerContext, RunnerHost, SharedRunner};
+///
+/// #[derive(Default)]
+/// struct Counter {
+/// 	queries: u32,
+/// }
+///
+/// impl Runner for Counter {
+/// 	type Action = std::convert::Infallible;
+/// 	type Err = String;
+///
+/// 	fn matches(
+/// 		&mut self,
+/// 		_: String,
+/// 		_: &RunnerContext,
+/// 	) -> Result<Vec<Match<Self::Action>>, String> {
+/// 		self.queries += 1;
+/// 		Ok(vec![])
+/// 	}
+///
+/// 	fn run(
+/// 		&mut self,
+/// 		_: String,
+/// 		_: Option<Self::Action>,
+/// 		_: &RunnerContext,
+/// 	) -> Result<(), String> {
+/// 		Ok(())
+/// 	}
+/// }
+///
+/// let counter = SharedRunner::new(Counter::default());
+/// let mut host = RunnerHost::new("some.runner.path");
+/// let stats = host.crossroads().register("some.runner.Stats", |b| {
+/// 	b.property("Queries")
+/// 		.get(|_, runner: &mut SharedRunner<Counter>| Ok(runner.lock().queries));
+/// });
+/// host.with_runner_interfaces("/Counter", counter.clone(), [stats])
+/// 	.with_runner("/CounterAlias", counter)
+/// 	.start()?;
+/// # Ok::<(), dbus::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct SharedRunner<R> {
+	runner: Arc<Mutex<R>>,
+}
+
+impl<R> SharedRunner<R> {
+	/// Wraps the given runner.
+	pub fn new(runner: R) -> Self {
+		Self {
+			runner: Arc::new(Mutex::new(runner)),
+		}
+	}
+
+	/// Locks the runner.
+	///
+	/// Calls to the runner are handled while holding the lock, so it should
+	/// only be held briefly.
+	pub fn lock(&self) -> MutexGuard<'_, R> {
+		self.runner.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Unwraps the runner, if this is its last clone.
+	#[must_use]
+	pub fn into_inner(self) -> Option<R> {
+		Arc::into_inner(self.runner).map(|m| m.into_inner().unwrap_or_else(PoisonError::into_inner))
+	}
+}
+
+impl<R> Clone for SharedRunner<R> {
+	fn clone(&self) -> Self {
+		Self {
+			runner: Arc::clone(&self.runner),
+		}
+	}
+}
+
+impl<R> From<Arc<Mutex<R>>> for SharedRunner<R> {
+	fn from(runner: Arc<Mutex<R>>) -> Self {
+		Self { runner }
+	}
+}
+
+impl<R: Runner> Runner for SharedRunner<R> {
+	type Action = R::Action;
+	type Err = R::Err;
+
+	const INTERFACE: &'static str = R::INTERFACE;
+
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		self.lock().matches(query, ctx)
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.lock().run(match_id, action, ctx)
+	}
+
+	fn run_many(
+		&mut self,
+		runs: Vec<(String, Option<Self::Action>)>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.lock().run_many(runs, ctx)
+	}
+
+	fn default_action_for(&self, match_id: &str) -> Option<Self::Action> {
+		self.lock().default_action_for(match_id)
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		self.lock().config()
+	}
+
+	fn config_is_static(&self) -> bool {
+		self.lock().config_is_static()
+	}
+
+	fn config_page(&self) -> Option<ConfigPage> {
+		self.lock().config_page()
+	}
+
+	fn available_actions(&self) -> Option<Vec<Self::Action>> {
+		self.lock().available_actions()
+	}
+
+	fn allowed_activities(&self) -> Option<Vec<String>> {
+		self.lock().allowed_activities()
+	}
+
+	fn debug_relevance(&self) -> bool {
+		self.lock().debug_relevance()
+	}
+
+	fn startup(&mut self) -> Result<(), Self::Err> {
+		self.lock().startup()
+	}
+
+	fn teardown_policy(&self) -> TeardownPolicy {
+		self.lock().teardown_policy()
+	}
+
+	fn teardown(&mut self) -> Result<(), Self::Err> {
+		self.lock().teardown()
+	}
+}
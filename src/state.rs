@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+
+use dbus::{Message, MethodErr};
+
+use crate::{Action, Config, Match, MatchCache, MatchFilter, RunnerContext};
+
+/// State kept by krunner on behalf of each runner, keyed by object path.
+///
+/// This lives outside of the runners themselves, and is shared between the
+/// method handlers registered by `register`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedState(Arc<Mutex<HashMap<String, PathState>>>);
+
+#[derive(Debug, Clone, Default)]
+struct PathState {
+	/// The last config read from the runner, if any.
+	config: Option<Config<()>>,
+	/// The trigger words to strip from queries.
+	strip_trigger_words: Option<Vec<String>>,
+	/// The maximum number of matches to reply with.
+	max_results: Option<usize>,
+	/// Whether to keep the order of matches.
+	preserve_order: bool,
+	/// Whether to remove matches with duplicate IDs.
+	dedup_ids: bool,
+	/// Whether to break ties between matches.
+	stable_order: bool,
+	/// The largest reply to a query, in bytes.
+	max_reply_size: Option<usize>,
+	/// The number of distinct relevances to round to.
+	relevance_buckets: Option<u32>,
+	/// How long to wait for the query to stop changing.
+	#[cfg(feature = "tokio")]
+	debounce: Option<Duration>,
+	/// The number of the latest query.
+	#[cfg(feature = "tokio")]
+	latest_query: u64,
+	/// The reply to `Actions` when all actions are available, built once.
+	all_actions: Option<Vec<(String, String, String)>>,
+	/// The values cached by the runner for its matches.
+	cache: MatchCache,
+}
+
+impl SharedState {
+	fn lock(&self) -> MutexGuard<'_, HashMap<String, PathState>> {
+		self.0.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	fn with<T>(&self, path: &str, f: impl FnOnce(&mut PathState) -> T) -> T {
+		f(self.lock().entry(path.to_owned()).or_default())
+	}
+
+	/// Whether the config of the runner at `path` has been read yet.
+	pub(crate) fn config_read(&self, path: &str) -> bool {
+		self.with(path, |s| s.config.is_some())
+	}
+
+	/// The last config read from the runner at `path`, if any.
+	pub(crate) fn config<A>(&self, path: &str) -> Option<Config<A>> {
+		self.with(path, |s| s.config.as_ref().map(Config::cast))
+	}
+
+	/// Remembers the parts of the config of the runner at `path` that affect
+	/// how its queries are handled.
+	pub(crate) fn set_config<A>(&self, path: &str, config: &Config<A>) {
+		self.with(path, |s| {
+			s.config = Some(config.cast());
+			s.strip_trigger_words = config.trigger_words_to_strip();
+			s.max_results = config.max_results;
+			s.preserve_order = config.preserve_order;
+			s.max_reply_size = config.max_reply_size;
+			s.dedup_ids = config.dedup_ids;
+			s.stable_order = config.stable_order;
+			s.relevance_buckets = config.relevance_buckets;
+			#[cfg(feature = "tokio")]
+			{
+				s.debounce = config.debounce;
+			}
+		});
+	}
+
+	/// Whether the runner at `path` has [disabled](Config::enabled) itself,
+	/// as of its last config.
+	pub(crate) fn disabled(&self, path: &str) -> bool {
+		self.with(path, |s| s.config.as_ref().is_some_and(|c| !c.enabled))
+	}
+
+	/// Numbers a new query to the runner at `path`, which supersedes all
+	/// earlier ones.
+	#[cfg(feature = "tokio")]
+	pub(crate) fn begin_query(&self, path: &str) -> u64 {
+		self.with(path, |s| {
+			s.latest_query += 1;
+			s.latest_query
+		})
+	}
+
+	/// The debounce window of the runner at `path`, if any.
+	#[cfg(feature = "tokio")]
+	pub(crate) fn debounce(&self, path: &str) -> Option<Duration> {
+		self.with(path, |s| s.debounce)
+	}
+
+	/// Whether the query numbered `query` should be skipped, because the
+	/// runner at `path` is debounced and a newer query has arrived since.
+	#[cfg(feature = "tokio")]
+	pub(crate) fn superseded(&self, path: &str, query: u64) -> bool {
+		self.with(path, |s| s.debounce.is_some() && s.latest_query != query)
+	}
+
+	/// The trigger words of the runner at `path`, as of its last config.
+	pub(crate) fn trigger_words(&self, path: &str) -> Vec<String> {
+		self.with(path, |s| {
+			match s.config.as_ref().and_then(|c| c.match_filter.as_ref()) {
+				Some(MatchFilter::Keywords(kws)) => kws.clone(),
+				_ => vec![],
+			}
+		})
+	}
+
+	/// The match cache of the runner at `path`.
+	pub(crate) fn cache(&self, path: &str) -> MatchCache {
+		self.with(path, |s| s.cache.clone())
+	}
+
+	/// Builds the reply to an `Actions` call to the runner at `path`, from its
+	/// [available actions](crate::Runner::available_actions).
+	///
+	/// The reply for all actions never changes, so it's only built once.
+	pub(crate) fn actions<A: Action + 'static>(
+		&self,
+		path: &str,
+		available: Option<&[A]>,
+		ctx: &RunnerContext,
+	) -> Vec<(String, String, String)> {
+		if available.is_some() {
+			return crate::actions_as_args(available, ctx);
+		}
+		if let Some(actions) = self.with(path, |s| s.all_actions.clone()) {
+			return actions;
+		}
+		let actions = crate::actions_as_args::<A>(None, ctx);
+		self.with(path, |s| s.all_actions = Some(actions.clone()));
+		actions
+	}
+
+	/// Forgets everything kept for the runner at `path`, i.e. its match cache
+	/// and its last config, which is read again on the next query.
+	pub(crate) fn evict(&self, path: &str) {
+		self.with(path, |s| {
+			s.cache.clear();
+			*s = PathState {
+				cache: s.cache.clone(),
+				#[cfg(feature = "tokio")]
+				latest_query: s.latest_query,
+				..PathState::default()
+			};
+		});
+	}
+
+	/// Prepares a query before it is passed to the runner at `path`.
+	pub(crate) fn preprocess_query(&self, path: &str, query: String) -> String {
+		match self.with(path, |s| s.strip_trigger_words.clone()) {
+			Some(words) => strip_trigger_word(&query, &words).to_owned(),
+			None => query,
+		}
+	}
+
+	/// Prepares the matches of the runner at `path` before they are sent.
+	pub(crate) fn postprocess_matches<A>(&self, path: &str, matches: &mut Vec<Match<A>>) {
+		let (max_results, preserve_order, dedup_ids, stable_order, relevance_buckets) =
+			self.with(path, |s| {
+				(
+					s.max_results,
+					s.preserve_order,
+					s.dedup_ids,
+					s.stable_order,
+					s.relevance_buckets,
+				)
+			});
+		if dedup_ids {
+			Match::dedup_by_id(matches);
+		}
+		if preserve_order {
+			if let Some(max) = max_results {
+				matches.truncate(max);
+			}
+			Match::preserve_order(matches);
+			return;
+		}
+		if let Some(buckets) = relevance_buckets {
+			Match::round_relevance(matches, buckets);
+		}
+		if stable_order {
+			Match::break_ties(matches);
+		}
+		if let Some(max) = max_results {
+			crate::truncate_matches(matches, max);
+		}
+	}
+
+	/// Checks that the matches of the runner at `path` fit into a reply.
+	pub(crate) fn check_reply_size<A: Action>(
+		&self,
+		path: &str,
+		matches: &Vec<Match<A>>,
+	) -> Result<(), MethodErr> {
+		// Marshalling the reply twice is expensive, so only do it when the reply
+		// has a chance of being too large
+		const OVERHEAD_PER_MATCH: usize = 256;
+
+		let max = self
+			.with(path, |s| s.max_reply_size)
+			.unwrap_or(Config::<()>::DEFAULT_MAX_REPLY_SIZE);
+		let estimate: usize = matches
+			.iter()
+			.map(|m| OVERHEAD_PER_MATCH + m.approximate_size())
+			.sum();
+		if estimate < max / 2 {
+			return Ok(());
+		}
+
+		let msg =
+			Message::new_method_call("org.kde.krunner1.Size", "/", "org.kde.krunner1", "Size")
+				.map_err(|e| MethodErr::failed(&e))?
+				.append1(matches);
+		let mut size = 0;
+		msg.marshal(|bytes| {
+			size += bytes.len();
+			Ok::<_, ()>(())
+		})
+		.map_err(|()| MethodErr::failed("cannot marshal matches"))?;
+
+		if size > max {
+			return Err(MethodErr::from((
+				"org.freedesktop.DBus.Error.LimitsExceeded",
+				format!(
+					"{} matches take up {size} bytes, more than the limit of {max} bytes; \
+					 consider setting Config::max_results or using smaller icons",
+					matches.len()
+				),
+			)));
+		}
+		Ok(())
+	}
+}
+
+/// Removes the first of `words` that the query starts with, along with any
+/// whitespace following it. Words are compared case-insensitively, like
+/// KRunner does, and only count if they're followed by whitespace or the end
+/// of the query, so that e.g. `nix` isn't stripped from `nixos`.
+pub(crate) fn strip_trigger_word<'q>(query: &'q str, words: &[String]) -> &'q str {
+	for word in words {
+		let Some(prefix) = query.get(..word.len()) else {
+			continue;
+		};
+		let rest = &query[word.len()..];
+		if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+			continue;
+		}
+		if prefix.to_lowercase() == word.to_lowercase() {
+			return rest.trim_start();
+		}
+	}
+	query
+}
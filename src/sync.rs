@@ -1,10 +1,23 @@
-use std::fmt::Display;
+use std::io;
+use std::path::PathBuf;
 
 use dbus::blocking::Connection;
 use dbus::MethodErr;
 use dbus_crossroads::{Crossroads, IfaceToken};
 
-use crate::{Action, Config, Match};
+use crate::state::SharedState;
+use crate::{
+	Action,
+	AttachedHost,
+	Config,
+	ConfigPage,
+	Match,
+	RunnerContext,
+	RunnerError,
+	RunnerHandle,
+	RunnerHost,
+	TeardownPolicy,
+};
 
 /// A synchronous runner.
 #[doc = concat!("\n\n", include_str!("./docs/runner/runner.md"), "\n\n")]
@@ -14,7 +27,9 @@ pub trait Runner {
 	#[doc = include_str!("./docs/runner/action.md")]
 	type Action: Action;
 	#[doc = include_str!("./docs/runner/err.md")]
-	type Err: Display;
+	type Err: RunnerError;
+	#[doc = include_str!("./docs/runner/interface.md")]
+	const INTERFACE: &'static str = crate::KRUNNER_INTERFACE;
 
 	#[doc = concat!(include_str!("./docs/runner/matches.md"), "\n\n")]
 	/// # Example
@@ -29,7 +44,8 @@ pub trait Runner {
 	///
 	///     fn matches(
 	///         &mut self,
-	///         query: String
+	///         query: String,
+	///         _ctx: &RunnerContext,
 	///     ) -> Result<Vec<Match<Self::Action>>, Self::Err> {
 	///         let matches = if self.known_words.contains(&query) {
 	///             vec![Match {
@@ -49,7 +65,11 @@ pub trait Runner {
 	///     // ...
 	/// }
 	/// ```
-	fn matches(&mut self, query: String) -> Result<Vec<Match<Self::Action>>, Self::Err>;
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err>;
 
 	#[doc = concat!(include_str!("./docs/runner/run.md"), "\n\n")]
 	/// # Example
@@ -66,6 +86,7 @@ pub trait Runner {
 	///         &mut self,
 	///         match_id: String,
 	///         action: Option<Self::Action>,
+	///         _ctx: &RunnerContext,
 	///     ) -> Result<(), Self::Err> {
 	///         match action {
 	///             Some(Action::LaunchDictionary) => {
@@ -84,13 +105,71 @@ pub trait Runner {
 	///     // ...
 	/// }
 	/// ```
-	fn run(&mut self, match_id: String, action: Option<Self::Action>) -> Result<(), Self::Err>;
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err>;
+
+	#[doc = include_str!("./docs/runner/run_many.md")]
+	fn run_many(
+		&mut self,
+		runs: Vec<(String, Option<Self::Action>)>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		for (match_id, action) in runs {
+			self.run(match_id, action, ctx)?;
+		}
+		Ok(())
+	}
+
+	#[doc = include_str!("./docs/runner/default_action_for.md")]
+	fn default_action_for(&self, match_id: &str) -> Option<Self::Action> {
+		let _ = match_id;
+		Self::Action::default_action()
+	}
 
 	#[doc = include_str!("./docs/runner/config.md")]
 	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
 		Ok(Config::default())
 	}
 
+	#[doc = include_str!("./docs/runner/config_is_static.md")]
+	fn config_is_static(&self) -> bool {
+		false
+	}
+
+	#[doc = include_str!("./docs/runner/config_page.md")]
+	fn config_page(&self) -> Option<ConfigPage> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/available_actions.md")]
+	fn available_actions(&self) -> Option<Vec<Self::Action>> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/allowed_activities.md")]
+	fn allowed_activities(&self) -> Option<Vec<String>> {
+		None
+	}
+
+	#[doc = include_str!("./docs/runner/debug_relevance.md")]
+	fn debug_relevance(&self) -> bool {
+		crate::debug_relevance_from_env()
+	}
+
+	#[doc = include_str!("./docs/runner/startup.md")]
+	fn startup(&mut self) -> Result<(), Self::Err> {
+		Ok(())
+	}
+
+	#[doc = include_str!("./docs/runner/teardown_policy.md")]
+	fn teardown_policy(&self) -> TeardownPolicy {
+		TeardownPolicy::default()
+	}
+
 	#[doc = include_str!("./docs/runner/teardown.md")]
 	fn teardown(&mut self) -> Result<(), Self::Err> {
 		Ok(())
@@ -103,8 +182,13 @@ pub trait RunnerExt: Runner + Sized + Send + 'static {
 	///
 	/// This is a convenience function that starts a new D-Bus connection,
 	/// requests the given service name, [registers the `KRunner`
-	/// interface](Self::register), and starts indefinitely listening on the
-	/// session bus.
+	/// interface](Self::register), calls [`startup`](Runner::startup), and
+	/// starts indefinitely listening on the session bus.
+	///
+	/// # Errors
+	/// Returns an `org.freedesktop.DBus.Error.InvalidArgs` error before
+	/// connecting if the service name or the object path is invalid (e.g. if
+	/// they were passed the wrong way around).
 	///
 	/// # Example
 	/// ```ignore
@@ -123,66 +207,193 @@ pub trait RunnerExt: Runner + Sized + Send + 'static {
 	/// ```
 	fn start(self, service: &'static str, path: &'static str) -> Result<(), dbus::Error>;
 
+	/// Starts running this runner on the main thread indefinitely, using an
+	/// existing D-Bus connection.
+	///
+	/// This is the same as [`start`](Self::start), except that the connection
+	/// can be shared with other parts of the application, or be to a
+	/// different bus.
+	fn start_on(
+		self,
+		connection: &Connection,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<(), dbus::Error>;
+
+	/// Starts handling calls to this runner on an existing D-Bus connection,
+	/// without waiting for them.
+	///
+	/// The caller is responsible for driving the connection. See
+	/// [`RunnerHost::attach`] for details.
+	fn attach(
+		self,
+		connection: &Connection,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<AttachedHost, dbus::Error>;
+
+	/// Starts running this runner on a new thread, and returns a handle to
+	/// stop it with.
+	///
+	/// This is useful for runners embedded in larger applications. See
+	/// [`RunnerHost::spawn`] for details.
+	fn spawn(self, service: &'static str, path: &'static str) -> Result<RunnerHandle, dbus::Error>;
+
+	#[doc = include_str!("./docs/runnerext/config_dir.md")]
+	fn config_dir(service: &str) -> io::Result<PathBuf>;
+
 	#[doc = include_str!("./docs/runnerext/register.md")]
 	fn register(cr: &mut Crossroads) -> IfaceToken<Self>;
 }
 
 impl<R: Runner + Sized + Send + 'static> RunnerExt for R {
 	fn start(self, service: &'static str, path: &'static str) -> Result<(), dbus::Error> {
-		let c = Connection::new_session()?;
-		c.request_name(service, false, true, false)?;
+		RunnerHost::new(service).with_runner(path, self).start()
+	}
 
-		let mut cr = Crossroads::new();
+	fn start_on(
+		self,
+		connection: &Connection,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<(), dbus::Error> {
+		RunnerHost::new(service)
+			.with_runner(path, self)
+			.start_on(connection)
+	}
+
+	fn attach(
+		self,
+		connection: &Connection,
+		service: &'static str,
+		path: &'static str,
+	) -> Result<AttachedHost, dbus::Error> {
+		RunnerHost::new(service)
+			.with_runner(path, self)
+			.attach(connection)
+	}
 
-		let token = Self::register(&mut cr);
-		cr.insert(path, &[token], self);
-		cr.serve(&c)
+	fn spawn(self, service: &'static str, path: &'static str) -> Result<RunnerHandle, dbus::Error> {
+		RunnerHost::new(service).with_runner(path, self).spawn()
+	}
+
+	fn config_dir(service: &str) -> io::Result<PathBuf> {
+		crate::settings::runner_config_dir(service)
 	}
 
 	fn register(cr: &mut Crossroads) -> IfaceToken<Self> {
-		cr.register("org.kde.krunner1", |b| {
-			b.method("Actions", (), ("matches",), |_, _: &mut Self, (): ()| {
-				let actions: Vec<_> = Self::Action::all()
-					.iter()
-					.map(crate::action_as_arg)
-					.collect();
-				Ok((actions,))
+		let state = SharedState::default();
+
+		cr.register(Self::INTERFACE, |b| {
+			b.method("Actions", (), ("matches",), {
+				let state = state.clone();
+				move |ctx, runner: &mut Self, (): ()| {
+					let runner_ctx = RunnerContext::new(ctx);
+					Ok((state.actions(
+						ctx.path(),
+						runner.available_actions().as_deref(),
+						&runner_ctx,
+					),))
+				}
 			});
-			b.method(
-				"Run",
-				("matchId", "actionId"),
-				(),
-				|_, runner, (match_id, action_id): (String, String)| {
+			b.method("Run", ("matchId", "actionId"), (), {
+				let state = state.clone();
+				move |ctx, runner: &mut Self, (match_id, action_id): (String, String)| {
+					event!(debug, path = %ctx.path(), match_id, action_id, "Run");
 					let action = if let Some(action) = Self::Action::from_id(&action_id) {
 						Some(action)
 					} else if action_id.is_empty() {
-						None
+						runner.default_action_for(&match_id)
 					} else {
 						return Err(MethodErr::invalid_arg("Unknown action"));
 					};
+					let runner_ctx = RunnerContext::new(ctx).with_cache(state.cache(ctx.path()));
 					runner
-						.run(match_id, action)
-						.map_err(|e| MethodErr::failed(&e))
-				},
-			);
-			b.method(
-				"Match",
-				("query",),
-				("matches",),
-				|_, runner, (query,): (String,)| match runner.matches(query) {
-					Ok(v) => Ok((v,)),
-					Err(e) => Err(MethodErr::failed(&e)),
-				},
-			);
-			b.method("Config", (), ("config",), |_, runner, (): ()| {
-				match runner.config() {
-					Ok(c) => Ok((c,)),
-					Err(e) => Err(MethodErr::failed(&e)),
+						.run(match_id, action, &runner_ctx)
+						.map_err(|e| crate::method_err(&e))
+				}
+			});
+			b.method("Match", ("query",), ("matches",), {
+				let state = state.clone();
+				move |ctx, runner: &mut Self, (query,): (String,)| {
+					event!(debug, path = %ctx.path(), query, "Match");
+					if let Some(allowed) = runner.allowed_activities() {
+						if !crate::activities::in_allowed_activity(&allowed) {
+							return Ok((vec![],));
+						}
+					}
+
+					let path = ctx.path();
+					if !state.config_read(path) {
+						match runner.config() {
+							Ok(config) => state.set_config(path, &config),
+							#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+							Err(e) => {
+								event!(warn, path = %path, error = %e, "cannot read config");
+							}
+						}
+					}
+					if state.disabled(path) {
+						return Ok((vec![],));
+					}
+					let runner_ctx = RunnerContext::new(ctx)
+						.with_cache(state.cache(path))
+						.with_raw_query(query.clone())
+						.with_trigger_words(state.trigger_words(path));
+					let query = state.preprocess_query(path, query);
+					runner_ctx.cache().clear();
+
+					match runner.matches(query, &runner_ctx) {
+						Ok(mut v) => {
+							state.postprocess_matches(path, &mut v);
+							event!(debug, path = %path, count = v.len(), "returning matches");
+							if cfg!(debug_assertions) && runner.debug_relevance() {
+								crate::annotate_relevance(&mut v);
+							}
+							state.check_reply_size(path, &v)?;
+							Ok((v,))
+						}
+						Err(e) => Err(crate::method_err(&e)),
+					}
 				}
 			});
-			b.method("Teardown", (), (), |_, runner, (): ()| {
-				runner.teardown().map_err(|e| MethodErr::failed(&e))
+			b.method("Config", (), ("config",), {
+				let state = state.clone();
+				move |ctx, runner: &mut Self, (): ()| {
+					event!(debug, path = %ctx.path(), "Config");
+					handle_config(runner, ctx.path(), &state).map(|c| (c,))
+				}
+			});
+			b.method("Teardown", (), (), move |ctx, runner: &mut Self, (): ()| {
+				#[cfg(feature = "icon-lookup")]
+				crate::icons::clear_lookups();
+				match runner.teardown_policy() {
+					TeardownPolicy::KeepCache => Ok(()),
+					TeardownPolicy::EvictCache => {
+						state.evict(ctx.path());
+						Ok(())
+					}
+					TeardownPolicy::Custom => {
+						state.cache(ctx.path()).clear();
+						runner.teardown().map_err(|e| crate::method_err(&e))
+					}
+				}
 			});
 		})
 	}
 }
+
+fn handle_config<R: Runner>(
+	runner: &mut R,
+	path: &str,
+	state: &SharedState,
+) -> Result<Config<R::Action>, MethodErr> {
+	if runner.config_is_static() {
+		if let Some(config) = state.config(path) {
+			return Ok(config);
+		}
+	}
+	let config = runner.config().map_err(|e| crate::method_err(&e))?;
+	state.set_config(path, &config);
+	Ok(config)
+}
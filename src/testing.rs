@@ -0,0 +1,273 @@
+//! Utilities for testing runners without a D-Bus session.
+//!
+//! # Example
+//! ```
+//! use krunner::testing::TestHarness;
+//! use krunner::{Match, Runner, RunnerContext};
+//!
+//! struct Echo;
+//!
+//! impl Runner for Echo {
+//! 	type Action = std::convert::Infallible;
+//! 	type Err = String;
+//!
+//! 	fn matches(
+//! 		&mut self,
+//! 		query: String,
+//! 		_ctx: &RunnerContext,
+//! 	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+//! 		Ok(vec![Match {
+//! 			id: query.clone(),
+//! 			title: query,
+//! 			subtitle: Some("Echo".to_owned()),
+//! 			..Match::default()
+//! 		}])
+//! 	}
+//!
+//! 	fn run(
+//! 		&mut self,
+//! 		_match_id: String,
+//! 		_action: Option<Self::Action>,
+//! 		_ctx: &RunnerContext,
+//! 	) -> Result<(), Self::Err> {
+//! 		Ok(())
+//! 	}
+//! }
+//!
+//! let mut harness = TestHarness::new(Echo);
+//! let matches = harness.marshal_matches("hello")?;
+//! assert_eq!(matches[0].title, "hello");
+//! assert_eq!(matches[0].subtitle(), Some("Echo"));
+//! # Ok::<(), String>(())
+//! ```
+
+use dbus::arg::{prop_cast, PropMap};
+use dbus::Message;
+
+use crate::{Config, Match, MatchType, Runner, RunnerContext};
+
+/// Drives a [`Runner`] directly, the way the D-Bus interface would.
+#[derive(Debug)]
+pub struct TestHarness<R> {
+	runner: R,
+	ctx: RunnerContext,
+	config: Option<Config<()>>,
+}
+
+/// A [`Match`] after it has been marshalled for D-Bus, as KRunner would
+/// receive it.
+#[derive(Debug)]
+pub struct MarshalledMatch {
+	/// The unique identifier of the match.
+	pub id: String,
+	/// The title of the match.
+	pub title: String,
+	/// The icon name of the match, or an empty string.
+	pub icon: String,
+	/// The numeric type of the match.
+	pub ty: i32,
+	/// The relevance of the match.
+	pub relevance: f64,
+	/// The remaining properties of the match.
+	pub properties: PropMap,
+}
+
+impl<R: Runner> TestHarness<R> {
+	/// Creates a harness for the given runner.
+	pub fn new(runner: R) -> Self {
+		Self {
+			runner,
+			ctx: RunnerContext::default(),
+			config: None,
+		}
+	}
+
+	/// Sets the context passed to the runner.
+	#[must_use]
+	pub fn with_context(mut self, ctx: RunnerContext) -> Self {
+		self.ctx = ctx;
+		self
+	}
+
+	/// Returns the runner being tested.
+	pub fn runner(&mut self) -> &mut R {
+		&mut self.runner
+	}
+
+	/// Calls [`Runner::matches`] with the given query.
+	///
+	/// As with the D-Bus interface, the [match cache](crate::MatchCache) is
+	/// cleared first, and the query is available as the
+	/// [raw query](RunnerContext::raw_query) of the context.
+	pub fn matches(&mut self, query: &str) -> Result<Vec<Match<R::Action>>, R::Err> {
+		let ctx = self.ctx.clone().with_raw_query(query.to_owned());
+		ctx.cache().clear();
+		self.runner.matches(query.to_owned(), &ctx)
+	}
+
+	/// Calls [`Runner::run`] with the given match ID and action ID.
+	///
+	/// As with the D-Bus interface, an empty action ID means that no action
+	/// was chosen, and the result of [`Runner::default_action_for`] is used
+	/// instead.
+	///
+	/// # Panics
+	/// Panics if the action ID doesn't belong to any action of the runner.
+	pub fn run(&mut self, match_id: &str, action_id: &str) -> Result<(), R::Err> {
+		let action = self.action(match_id, action_id);
+		self.runner.run(match_id.to_owned(), action, &self.ctx)
+	}
+
+	/// Calls [`Runner::run_many`] with the given match IDs and action IDs.
+	///
+	/// Action IDs are handled the same way as by [`run`](Self::run).
+	///
+	/// # Panics
+	/// Panics if any action ID doesn't belong to any action of the runner.
+	pub fn run_many(&mut self, runs: &[(&str, &str)]) -> Result<(), R::Err> {
+		let runs = runs
+			.iter()
+			.map(|&(match_id, action_id)| (match_id.to_owned(), self.action(match_id, action_id)))
+			.collect();
+		self.runner.run_many(runs, &self.ctx)
+	}
+
+	fn action(&self, match_id: &str, action_id: &str) -> Option<R::Action> {
+		if action_id.is_empty() {
+			self.runner.default_action_for(match_id)
+		} else {
+			let action = <R::Action as crate::Action>::from_id(action_id);
+			Some(action.unwrap_or_else(|| panic!("unknown action: {action_id:?}")))
+		}
+	}
+
+	/// Returns the IDs of the actions the runner would currently report to
+	/// KRunner, taking [`Runner::available_actions`] into account.
+	pub fn actions(&self) -> Vec<String>
+	where
+		R::Action: 'static,
+	{
+		match self.runner.available_actions() {
+			Some(available) => available.iter().map(crate::Action::to_id).collect(),
+			None => <R::Action as crate::Action>::all()
+				.iter()
+				.map(crate::Action::to_id)
+				.collect(),
+		}
+	}
+
+	/// Calls [`Runner::config`].
+	///
+	/// As with the D-Bus interface, the runner is only asked once if its
+	/// [config is static](Runner::config_is_static).
+	pub fn config(&mut self) -> Result<Config<R::Action>, R::Err> {
+		if self.runner.config_is_static() {
+			if let Some(config) = &self.config {
+				return Ok(config.cast());
+			}
+		}
+		let config = self.runner.config()?;
+		self.config = Some(config.cast());
+		Ok(config)
+	}
+
+	/// Calls [`Runner::matches`] with the given query, and marshals the
+	/// result as it would be sent over D-Bus.
+	///
+	/// # Panics
+	/// Panics if the matches aren't marshalled with the signature KRunner
+	/// expects, `a(sssida{sv})`.
+	pub fn marshal_matches(&mut self, query: &str) -> Result<Vec<MarshalledMatch>, R::Err>
+	where
+		R::Action: 'static,
+	{
+		let matches = self.matches(query)?;
+		let msg = marshal(&matches);
+		assert_eq!(&*msg.iter_init().signature(), "a(sssida{sv})");
+
+		let matches: Vec<(String, String, String, i32, f64, PropMap)> = msg.read1().unwrap();
+		Ok(matches
+			.into_iter()
+			.map(
+				|(id, title, icon, ty, relevance, properties)| MarshalledMatch {
+					id,
+					title,
+					icon,
+					ty,
+					relevance,
+					properties,
+				},
+			)
+			.collect())
+	}
+
+	/// Calls [`Runner::config`], and marshals the result as it would be sent
+	/// over D-Bus.
+	///
+	/// # Panics
+	/// Panics if the config isn't marshalled with the signature KRunner
+	/// expects, `a{sv}`.
+	pub fn marshal_config(&mut self) -> Result<PropMap, R::Err>
+	where
+		R::Action: 'static,
+	{
+		let msg = marshal(&self.config()?);
+		assert_eq!(&*msg.iter_init().signature(), "a{sv}");
+		Ok(msg.read1().unwrap())
+	}
+}
+
+impl MarshalledMatch {
+	/// The subtitle of the match, if any.
+	#[must_use]
+	pub fn subtitle(&self) -> Option<&str> {
+		prop_cast::<String>(&self.properties, crate::keys::matches::SUBTEXT).map(String::as_str)
+	}
+
+	/// The category of the match, if any.
+	#[must_use]
+	pub fn category(&self) -> Option<&str> {
+		prop_cast::<String>(&self.properties, crate::keys::matches::CATEGORY).map(String::as_str)
+	}
+
+	/// The [type](crate::MatchType) of the match, if [`ty`](Self::ty) is a
+	/// valid one.
+	#[must_use]
+	pub fn match_type(&self) -> Option<MatchType> {
+		MatchType::try_from(self.ty).ok()
+	}
+
+	/// The URLs of the match.
+	#[must_use]
+	pub fn urls(&self) -> &[String] {
+		prop_cast::<Vec<String>>(&self.properties, crate::keys::matches::URLS)
+			.map_or(&[], Vec::as_slice)
+	}
+
+	/// Whether the match is displayed as multiline text.
+	#[must_use]
+	pub fn multiline(&self) -> bool {
+		prop_cast::<bool>(&self.properties, crate::keys::matches::MULTILINE)
+			.copied()
+			.unwrap_or(false)
+	}
+
+	/// The text that replaces the query when the match is run, if any.
+	#[must_use]
+	pub fn replace_query(&self) -> Option<&str> {
+		prop_cast::<String>(&self.properties, crate::keys::matches::QUERY).map(String::as_str)
+	}
+
+	/// The IDs of the actions of the match.
+	#[must_use]
+	pub fn actions(&self) -> &[String] {
+		prop_cast::<Vec<String>>(&self.properties, crate::keys::matches::ACTIONS)
+			.map_or(&[], Vec::as_slice)
+	}
+}
+
+fn marshal(value: &impl dbus::arg::Append) -> Message {
+	Message::new_method_call("org.kde.krunner1.Test", "/", "org.kde.krunner1", "Test")
+		.unwrap()
+		.append1(value)
+}
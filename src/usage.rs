@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+use crate::{Action, Match};
+
+/// Records how often matches are run, so that frequently run matches can be
+/// ranked higher over time.
+///
+/// Each call to [`record`](Self::record) adds one to the score of a match,
+/// which then decays exponentially with the [half
+/// life](Self::with_half_life) of the tracker, so that matches that were run
+/// a lot a long time ago eventually make room for ones that are run now.
+/// Runners can then scale the relevance of their matches with
+/// [`multiplier`](Self::multiplier), or with [`apply`](Self::apply).
+///
+/// Trackers are cheap to clone, and clones share the same history, so a
+/// tracker can be freely shared between threads. Trackers created with
+/// [`load`](Self::load) write their history back to disk on every
+/// [`record`](Self::record) and [`forget`](Self::forget).
+///
+/// # Example
+/// ```no_run
+/// use krunner::{Match, RunnerContext, UsageTracker};
+///
+/// struct Launcher {
+/// 	usage: UsageTracker,
+/// }
+///
+/// impl krunner::Runner for Launcher {
+/// 	type Action = std::convert::Infallible;
+/// 	type Err = std::io::Error;
+///
+/// 	fn matches(
+/// 		&mut self,
+/// 		query: String,
+/// 		_ctx: &RunnerContext,
+/// 	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+/// 		let mut matches = vec![Match::new(&query).title(&query).relevance(0.5)];
+/// 		self.usage.apply(&mut matches);
+/// 		Ok(matches)
+/// 	}
+///
+/// 	fn run(
+/// 		&mut self,
+/// 		match_id: String,
+/// 		_action: Option<Self::Action>,
+/// 		_ctx: &RunnerContext,
+/// 	) -> Result<(), Self::Err> {
+/// 		// Launch the thing...
+/// 		self.usage.record(&match_id)
+/// 	}
+/// }
+///
+/// let usage = UsageTracker::load_for_plugin("some.runner.path")?;
+/// let launcher = Launcher { usage };
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct UsageTracker {
+	inner: Arc<Mutex<Usage>>,
+	half_life: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+	path: Option<PathBuf>,
+	entries: HashMap<String, Entry>,
+}
+
+/// The score of a match, as of `updated`.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+	score: f64,
+	updated: u64,
+}
+
+impl UsageTracker {
+	/// The default half life, after which the score of a match is halved.
+	// `Duration::from_hours` needs Rust 1.91
+	#[allow(clippy::duration_suboptimal_units)]
+	pub const DEFAULT_HALF_LIFE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+	/// Creates a tracker that only keeps its history in memory.
+	#[must_use]
+	pub fn new() -> Self {
+		Self {
+			inner: Arc::default(),
+			half_life: Self::DEFAULT_HALF_LIFE,
+		}
+	}
+
+	/// Creates a tracker that persists its history to the file at `path`,
+	/// reading back any existing history.
+	///
+	/// A missing file is treated as an empty one, and is created on the first
+	/// [`record`](Self::record). Lines that can't be parsed are ignored.
+	pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+		let path = path.into();
+		let entries = match fs::read_to_string(&path) {
+			Ok(contents) => parse(&contents),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+			Err(e) => return Err(e),
+		};
+		Ok(Self {
+			inner: Arc::new(Mutex::new(Usage {
+				path: Some(path),
+				entries,
+			})),
+			half_life: Self::DEFAULT_HALF_LIFE,
+		})
+	}
+
+	/// Creates a tracker for the KRunner plugin with the given ID, persisted
+	/// to `$XDG_DATA_HOME/krunner-rs/<plugin_id>.usage`.
+	///
+	/// # Errors
+	/// Returns an [`InvalidInput`](io::ErrorKind::InvalidInput) error if the
+	/// plugin ID isn't a valid service name, which could escape the directory.
+	pub fn load_for_plugin(plugin_id: &str) -> io::Result<Self> {
+		crate::settings::validate_service(plugin_id)?;
+		Self::load(
+			crate::settings::data_home()?
+				.join("krunner-rs")
+				.join(format!("{plugin_id}.usage")),
+		)
+	}
+
+	/// Sets how long it takes for the score of a match to be halved.
+	///
+	/// # Panics
+	/// Panics if `half_life` is zero.
+	#[must_use]
+	pub fn with_half_life(mut self, half_life: Duration) -> Self {
+		assert!(!half_life.is_zero(), "half life must not be zero");
+		self.half_life = half_life;
+		self
+	}
+
+	/// Records that the match with the given ID has been run.
+	///
+	/// # Errors
+	/// Returns an error if the history can't be written back to disk. The run
+	/// is still recorded in memory.
+	pub fn record(&self, match_id: &str) -> io::Result<()> {
+		let now = now();
+		let mut usage = self.lock();
+		let entry = usage.entries.entry(match_id.to_owned()).or_insert(Entry {
+			score: 0.0,
+			updated: now,
+		});
+		*entry = Entry {
+			score: self.decayed(*entry, now) + 1.0,
+			updated: now,
+		};
+		usage.save()
+	}
+
+	/// Forgets all runs of the match with the given ID.
+	///
+	/// # Errors
+	/// Returns an error if the history can't be written back to disk.
+	pub fn forget(&self, match_id: &str) -> io::Result<()> {
+		let mut usage = self.lock();
+		if usage.entries.remove(match_id).is_some() {
+			usage.save()?;
+		}
+		Ok(())
+	}
+
+	/// The current, decayed score of the match with the given ID.
+	///
+	/// This is roughly the number of times it has been run within the last
+	/// [half life](Self::with_half_life), and `0.0` if it's never been run.
+	#[must_use]
+	pub fn score(&self, match_id: &str) -> f64 {
+		self.lock()
+			.entries
+			.get(match_id)
+			.map_or(0.0, |&entry| self.decayed(entry, now()))
+	}
+
+	/// The factor by which to multiply the relevance of the match with the
+	/// given ID.
+	///
+	/// This is `1.0` for matches that have never been run, and approaches
+	/// `2.0` for matches that are run very often.
+	#[must_use]
+	pub fn multiplier(&self, match_id: &str) -> f64 {
+		let score = self.score(match_id);
+		1.0 + score / (score + 1.0)
+	}
+
+	/// Multiplies the relevance of each match by its
+	/// [`multiplier`](Self::multiplier), clamping it to at most `1.0`.
+	pub fn apply<A: Action>(&self, matches: &mut [Match<A>]) {
+		for m in matches {
+			m.relevance = (m.relevance * self.multiplier(&m.id)).min(1.0);
+		}
+	}
+
+	fn decayed(&self, entry: Entry, now: u64) -> f64 {
+		#[allow(clippy::cast_precision_loss)]
+		let elapsed = now.saturating_sub(entry.updated) as f64;
+		entry.score * 0.5f64.powf(elapsed / self.half_life.as_secs_f64())
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, Usage> {
+		// The history is always left in a consistent state
+		self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Default for UsageTracker {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Usage {
+	/// Writes the history back to disk, if it's backed by a file.
+	fn save(&self) -> io::Result<()> {
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+
+		let mut contents = String::new();
+		for (id, entry) in &self.entries {
+			contents.extend([
+				&*entry.score.to_string(),
+				"\t",
+				&*entry.updated.to_string(),
+				"\t",
+				&*escape(id),
+				"\n",
+			]);
+		}
+
+		// Write to a temporary file first so that a crash can't leave a
+		// truncated history behind
+		let tmp = path.with_extension("usage.tmp");
+		fs::write(&tmp, contents)?;
+		fs::rename(tmp, path)
+	}
+}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs())
+}
+
+/// Parses a history file, which has one `<score>\t<updated>\t<id>` line per
+/// match.
+fn parse(contents: &str) -> HashMap<String, Entry> {
+	contents
+		.lines()
+		.filter_map(|line| {
+			let mut parts = line.splitn(3, '\t');
+			let score = parts.next()?.parse().ok()?;
+			let updated = parts.next()?.parse().ok()?;
+			let id = unescape(parts.next()?);
+			Some((id, Entry { score, updated }))
+		})
+		.collect()
+}
+
+fn escape(id: &str) -> String {
+	id.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(raw: &str) -> String {
+	let mut out = String::with_capacity(raw.len());
+	let mut chars = raw.chars();
+	while let Some(c) = chars.next() {
+		match (c, chars.clone().next()) {
+			('\\', Some('n')) => {
+				out.push('\n');
+				chars.next();
+			}
+			('\\', Some('\\')) => {
+				out.push('\\');
+				chars.next();
+			}
+			_ => out.push(c),
+		}
+	}
+	out
+}
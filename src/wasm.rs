@@ -0,0 +1,382 @@
+use std::convert::Infallible;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use serde::Deserialize;
+use wasmtime::{
+	Config as EngineConfig,
+	Engine,
+	Instance,
+	Linker,
+	Memory,
+	Module,
+	Store,
+	TypedFunc,
+};
+
+use crate::{Match, MatchIcon, MatchType, Runner, RunnerContext, RunnerError};
+
+/// A runner that delegates matching to a sandboxed WebAssembly module.
+///
+/// This allows third parties to extend a runner without being able to crash
+/// it or touch anything outside of the sandbox: traps inside the guest are
+/// reported as [errors](WasmError), after which the guest is re-instantiated
+/// from scratch for the next call.
+///
+/// # Guest ABI
+///
+/// All strings are passed as UTF-8 encoded bytes in the guest's memory,
+/// addressed by a pointer and a length. The guest module must export:
+///
+/// - `memory`: the guest's linear memory.
+/// - `krunner_alloc(len: i32) -> i32`: allocates `len` bytes and returns a
+///   pointer to them. The host uses this to pass input to the guest.
+/// - `krunner_match(ptr: i32, len: i32) -> i64`: receives the query, and
+///   returns a pointer to a JSON array of matches (see below) in the upper 32
+///   bits of the result, and its length in the lower 32 bits.
+///
+/// The guest module may also export:
+///
+/// - `krunner_run(ptr: i32, len: i32) -> i32`: receives the ID of a match that
+///   the user has chosen to run. Returning a non-zero value signals failure.
+/// - `krunner_free(ptr: i32, len: i32)`: releases memory previously handed to
+///   the host by `krunner_match`, or to the guest by `krunner_alloc`.
+///
+/// Each match in the array returned by `krunner_match` is an object with the
+/// following fields, of which only `id` and `title` are required:
+///
+/// ```json
+/// {
+///     "id": "some-id",
+///     "title": "Some title",
+///     "subtitle": "Some subtitle",
+///     "icon": "some-icon-name",
+///     "type": 100,
+///     "relevance": 1.0,
+///     "urls": ["https://example.com"],
+///     "category": "Some category",
+///     "multiline": false,
+///     "replace_query": "Some text"
+/// }
+/// ```
+///
+/// `type` takes the numeric values of [`MatchType`].
+///
+/// Guests cannot declare [actions](crate::Action) of their own.
+///
+/// # Example
+/// ```
+/// use krunner::{Runner, RunnerContext, WasmRunner};
+///
+/// let mut runner = WasmRunner::from_bytes(
+/// 	r#"
+/// 	(module
+/// 		(memory (export "memory") 1)
+/// 		(data (i32.const 0) "[{\"id\":\"hi\",\"title\":\"Hello!\"}]")
+/// 		(func (export "krunner_alloc") (param i32) (result i32)
+/// 			i32.const 1024)
+/// 		(func (export "krunner_match") (param i32 i32) (result i64)
+/// 			i64.const 30))
+/// 	"#,
+/// )?
+/// .with_fuel(10_000);
+///
+/// let matches = runner.matches("hi".to_owned(), &RunnerContext::default())?;
+/// assert_eq!(matches[0].title, "Hello!");
+/// # Ok::<(), krunner::WasmError>(())
+/// ```
+pub struct WasmRunner {
+	engine: Engine,
+	module: Module,
+	linker: Linker<()>,
+	fuel: Option<u64>,
+	guest: Option<Guest>,
+}
+
+/// Errors emitted by a [`WasmRunner`].
+#[derive(Debug)]
+pub enum WasmError {
+	/// The module could not be compiled or instantiated.
+	Load(wasmtime::Error),
+	/// The module does not follow the expected [ABI](WasmRunner#guest-abi).
+	Abi(String),
+	/// The guest trapped, or ran out of fuel.
+	Trap(wasmtime::Error),
+	/// The matches returned by the guest could not be decoded.
+	Decode(serde_json::Error),
+	/// The guest reported a failure while running a match, with the given
+	/// status code.
+	Run(i32),
+}
+
+struct Guest {
+	store: Store<()>,
+	memory: Memory,
+	alloc: TypedFunc<i32, i32>,
+	free: Option<TypedFunc<(i32, i32), ()>>,
+	matches: TypedFunc<(i32, i32), i64>,
+	run: Option<TypedFunc<(i32, i32), i32>>,
+}
+
+#[derive(Deserialize)]
+struct GuestMatch {
+	id: String,
+	title: String,
+	#[serde(default)]
+	subtitle: Option<String>,
+	#[serde(default)]
+	icon: Option<String>,
+	#[serde(default, rename = "type")]
+	ty: Option<i32>,
+	#[serde(default)]
+	relevance: Option<f64>,
+	#[serde(default)]
+	urls: Vec<String>,
+	#[serde(default)]
+	category: Option<String>,
+	#[serde(default)]
+	multiline: bool,
+	#[serde(default)]
+	replace_query: Option<String>,
+}
+
+impl WasmRunner {
+	/// Loads a WebAssembly module from a file, in either the binary or text
+	/// format.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, WasmError> {
+		let engine = Self::engine()?;
+		let module = Module::from_file(&engine, path).map_err(WasmError::Load)?;
+		Self::new(engine, module)
+	}
+
+	/// Loads a WebAssembly module from memory, in either the binary or text
+	/// format.
+	pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, WasmError> {
+		let engine = Self::engine()?;
+		let module = Module::new(&engine, bytes).map_err(WasmError::Load)?;
+		Self::new(engine, module)
+	}
+
+	/// Limits the amount of fuel (roughly, the number of instructions) that
+	/// the guest may consume per call, so that a misbehaving guest can't hang
+	/// the runner forever.
+	#[must_use]
+	pub fn with_fuel(mut self, fuel: u64) -> Self {
+		self.fuel = Some(fuel);
+		self
+	}
+
+	fn engine() -> Result<Engine, WasmError> {
+		let mut config = EngineConfig::new();
+		config.consume_fuel(true);
+		Engine::new(&config).map_err(WasmError::Load)
+	}
+
+	fn new(engine: Engine, module: Module) -> Result<Self, WasmError> {
+		let linker = Linker::new(&engine);
+		let mut runner = Self {
+			engine,
+			module,
+			linker,
+			fuel: None,
+			guest: None,
+		};
+		// Instantiate eagerly, so that ABI errors surface at load time
+		runner.guest()?;
+		Ok(runner)
+	}
+
+	fn guest(&mut self) -> Result<&mut Guest, WasmError> {
+		if self.guest.is_none() {
+			let mut store = Store::new(&self.engine, ());
+			store.set_fuel(u64::MAX).map_err(WasmError::Load)?;
+
+			let instance = self
+				.linker
+				.instantiate(&mut store, &self.module)
+				.map_err(WasmError::Load)?;
+			self.guest = Some(Guest::new(store, &instance)?);
+		}
+		Ok(self.guest.as_mut().unwrap())
+	}
+
+	/// Runs `f` against the guest, throwing the guest away if it trapped.
+	fn call<T>(
+		&mut self,
+		f: impl FnOnce(&mut Guest) -> Result<T, WasmError>,
+	) -> Result<T, WasmError> {
+		let fuel = self.fuel.unwrap_or(u64::MAX);
+		let guest = self.guest()?;
+		guest.store.set_fuel(fuel).map_err(WasmError::Trap)?;
+
+		let res = f(guest);
+		if let Err(WasmError::Trap(_)) = res {
+			self.guest = None;
+		}
+		res
+	}
+}
+
+impl Runner for WasmRunner {
+	type Action = Infallible;
+	type Err = WasmError;
+
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		let json = self.call(|guest| {
+			let (ptr, len) = guest.write(query.as_bytes())?;
+			let packed = guest
+				.matches
+				.call(&mut guest.store, (ptr, len))
+				.map_err(WasmError::Trap)?;
+			guest.release(ptr, len)?;
+
+			// Reinterpret the packed halves as unsigned
+			#[allow(clippy::cast_sign_loss)]
+			let packed = packed as u64;
+			#[allow(clippy::cast_possible_truncation)]
+			let (ptr, len) = ((packed >> 32) as u32, packed as u32);
+			let json = guest.read(ptr, len)?;
+			#[allow(clippy::cast_possible_wrap)]
+			guest.release(ptr as i32, len as i32)?;
+			Ok(json)
+		})?;
+
+		let matches: Vec<GuestMatch> = serde_json::from_slice(&json).map_err(WasmError::Decode)?;
+		matches.into_iter().map(GuestMatch::into_match).collect()
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.call(|guest| {
+			let Some(run) = guest.run.clone() else {
+				return Ok(());
+			};
+			let (ptr, len) = guest.write(match_id.as_bytes())?;
+			let status = run
+				.call(&mut guest.store, (ptr, len))
+				.map_err(WasmError::Trap)?;
+			guest.release(ptr, len)?;
+
+			match status {
+				0 => Ok(()),
+				status => Err(WasmError::Run(status)),
+			}
+		})
+	}
+}
+
+impl Guest {
+	fn new(mut store: Store<()>, instance: &Instance) -> Result<Self, WasmError> {
+		fn abi(e: impl Display) -> WasmError {
+			WasmError::Abi(e.to_string())
+		}
+
+		let memory = instance
+			.get_memory(&mut store, "memory")
+			.ok_or_else(|| abi("missing `memory` export"))?;
+		let alloc = instance
+			.get_typed_func(&mut store, "krunner_alloc")
+			.map_err(abi)?;
+		let matches = instance
+			.get_typed_func(&mut store, "krunner_match")
+			.map_err(abi)?;
+		let free = instance.get_typed_func(&mut store, "krunner_free").ok();
+		let run = instance.get_typed_func(&mut store, "krunner_run").ok();
+
+		Ok(Self {
+			store,
+			memory,
+			alloc,
+			free,
+			matches,
+			run,
+		})
+	}
+
+	fn write(&mut self, bytes: &[u8]) -> Result<(i32, i32), WasmError> {
+		let len =
+			i32::try_from(bytes.len()).map_err(|_| WasmError::Abi("input too large".to_owned()))?;
+		let ptr = self
+			.alloc
+			.call(&mut self.store, len)
+			.map_err(WasmError::Trap)?;
+		let offset =
+			usize::try_from(ptr).map_err(|_| WasmError::Abi("negative pointer".to_owned()))?;
+
+		self.memory
+			.write(&mut self.store, offset, bytes)
+			.map_err(|e| WasmError::Abi(e.to_string()))?;
+		Ok((ptr, len))
+	}
+
+	fn read(&self, ptr: u32, len: u32) -> Result<Vec<u8>, WasmError> {
+		let mut buf = vec![0; len as usize];
+		self.memory
+			.read(&self.store, ptr as usize, &mut buf)
+			.map_err(|e| WasmError::Abi(e.to_string()))?;
+		Ok(buf)
+	}
+
+	fn release(&mut self, ptr: i32, len: i32) -> Result<(), WasmError> {
+		if let Some(free) = &self.free {
+			free.call(&mut self.store, (ptr, len))
+				.map_err(WasmError::Trap)?;
+		}
+		Ok(())
+	}
+}
+
+impl GuestMatch {
+	fn into_match(self) -> Result<Match<Infallible>, WasmError> {
+		let ty = match self.ty {
+			Some(ty) => MatchType::try_from(ty).map_err(|e| WasmError::Abi(e.to_string()))?,
+			None => MatchType::PossibleMatch,
+		};
+
+		Ok(Match {
+			id: self.id,
+			title: self.title,
+			subtitle: self.subtitle,
+			icon: self.icon.map(MatchIcon::ByName).unwrap_or_default(),
+			ty,
+			relevance: self.relevance.unwrap_or(1.0),
+			urls: self.urls,
+			category: self.category,
+			multiline: self.multiline,
+			replace_query: self.replace_query,
+			actions: vec![],
+		})
+	}
+}
+
+impl Display for WasmError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Load(e) => write!(f, "failed to load WebAssembly module: {e}"),
+			Self::Abi(e) => write!(f, "WebAssembly module does not follow the runner ABI: {e}"),
+			Self::Trap(e) => write!(f, "WebAssembly guest trapped: {e}"),
+			Self::Decode(e) => write!(f, "failed to decode matches from WebAssembly guest: {e}"),
+			Self::Run(status) => {
+				write!(f, "WebAssembly guest failed to run match (status {status})")
+			}
+		}
+	}
+}
+impl RunnerError for WasmError {}
+impl std::error::Error for WasmError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Load(e) | Self::Trap(e) => Some(e.as_ref()),
+			Self::Decode(e) => Some(e),
+			Self::Abi(_) | Self::Run(_) => None,
+		}
+	}
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use dbus::MethodErr;
+use tokio::sync::Mutex;
+use zbus::message::Header;
+use zbus::names::ErrorName;
+use zbus::zvariant::{Array, Structure, Type, Value};
+use zbus::{DBusError, Message};
+
+use crate::_async::{catch_panic, handle_config, handle_query, handle_run, handle_teardown};
+use crate::state::SharedState;
+use crate::{
+	Action,
+	AsyncRunner,
+	Config,
+	ImageData,
+	Match,
+	MatchFilter,
+	MatchIcon,
+	RunnerContext,
+	TeardownPolicy,
+};
+
+/// An [`AsyncRunner`] served through [`zbus`], a pure-Rust D-Bus
+/// implementation, instead of libdbus.
+///
+/// Note that this only changes how the runner is served: the rest of the
+/// crate, e.g. [`Match`] and [`Config`] marshalling and the errors returned by
+/// runners, is still built on the [`dbus`] crate, so binaries built with the
+/// `zbus` feature still link to libdbus.
+///
+/// This implements the KRunner interface as a [`zbus` interface], so it can be
+/// served on any [`zbus::Connection`] with
+/// [`ObjectServer::at`](zbus::ObjectServer::at), alongside other interfaces.
+/// For the common case of serving a single runner, see
+/// [`ZbusRunnerExt::start_zbus`].
+///
+/// The interface is always served as
+/// [`org.kde.krunner1`](crate::KRUNNER_INTERFACE), as `zbus` needs to know the
+/// interface name at compile time. [`AsyncRunner::INTERFACE`] is ignored.
+///
+/// [`AsyncRunner::startup`] is only called by
+/// [`start_zbus`](ZbusRunnerExt::start_zbus), so runners served on
+/// connections built by hand should be started up before being wrapped.
+///
+/// Synchronous [`Runner`](crate::Runner)s can be served by wrapping them in an
+/// [`AsyncAdapter`](crate::AsyncAdapter) first.
+///
+/// # Example
+/// ```ignore
+/// use krunner::ZbusRunner;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// 	let _c = zbus::connection::Builder::session()?
+/// 		.name("some.runner.path")?
+/// 		.serve_at("/SomeRunner", ZbusRunner::new(SomeRunner))?
+/// 		.serve_at("/OtherRunner", ZbusRunner::new(OtherRunner))?
+/// 		.build()
+/// 		.await?;
+///
+/// 	std::future::pending::<()>().await;
+/// 	Ok(())
+/// }
+/// ```
+///
+/// [`zbus` interface]: zbus::object_server::Interface
+pub struct ZbusRunner<R> {
+	runner: Arc<Mutex<R>>,
+	state: SharedState,
+}
+
+/// Helper methods for serving [`AsyncRunner`]s through [`zbus`].
+#[cfg_attr(not(docs_rs), async_trait::async_trait)]
+pub trait ZbusRunnerExt: AsyncRunner + Sized + Send + 'static {
+	/// Starts running this runner asynchronously through [`zbus`].
+	///
+	/// This is the same as
+	/// [`AsyncRunnerExt::start`](crate::AsyncRunnerExt::start), except that the
+	/// connection is handled by `zbus` rather than libdbus. See [`ZbusRunner`]
+	/// for details.
+	async fn start_zbus(self, service: &'static str, path: &'static str) -> zbus::Result<()>
+	where
+		Self::Action: Send;
+}
+
+#[cfg_attr(not(docs_rs), async_trait::async_trait)]
+impl<R: AsyncRunner + Sized + Send + 'static> ZbusRunnerExt for R {
+	async fn start_zbus(self, service: &'static str, path: &'static str) -> zbus::Result<()>
+	where
+		Self::Action: Send,
+	{
+		let runner = ZbusRunner::new(self);
+		let handle = Arc::clone(runner.runner());
+		let _c = zbus::connection::Builder::session()?
+			.name(service)?
+			.serve_at(path, runner)?
+			.build()
+			.await?;
+		handle
+			.lock()
+			.await
+			.startup()
+			.await
+			.map_err(|e| zbus::Error::Failure(format!("runner failed to start: {e}")))?;
+
+		#[cfg(not(feature = "signal"))]
+		{
+			let _ = handle;
+			std::future::pending::<()>().await;
+			unreachable!()
+		}
+
+		#[cfg(feature = "signal")]
+		{
+			let signal = crate::_async::shutdown_signal().await;
+
+			// We're shutting down either way, so there's nobody left to report
+			// the error to
+			let _ = handle_teardown(&mut *handle.lock().await).await;
+			signal.map_err(|e| zbus::Error::Failure(format!("cannot handle signals: {e}")))
+		}
+	}
+}
+
+impl<R: AsyncRunner + Send> ZbusRunner<R> {
+	/// Wraps the given runner.
+	pub fn new(runner: R) -> Self {
+		Self {
+			runner: Arc::new(Mutex::new(runner)),
+			state: SharedState::default(),
+		}
+	}
+
+	/// The wrapped runner.
+	///
+	/// Calls to the runner are handled while holding the lock, so it should
+	/// only be held briefly.
+	#[must_use]
+	pub fn runner(&self) -> &Arc<Mutex<R>> {
+		&self.runner
+	}
+
+	fn context(&self, header: &Header<'_>) -> RunnerContext {
+		let path = header.path().map_or_else(String::new, ToString::to_string);
+		let cache = self.state.cache(&path);
+		RunnerContext::from_parts(
+			header.sender().map(ToString::to_string),
+			Some(header.primary().serial_num().get()),
+			path,
+		)
+		.with_cache(cache)
+	}
+}
+
+#[zbus::interface(name = "org.kde.krunner1")]
+impl<R> ZbusRunner<R>
+where
+	R: AsyncRunner + Send + 'static,
+	R::Action: Send,
+{
+	#[zbus(out_args("matches"))]
+	async fn actions(
+		&self,
+		#[zbus(header)] header: Header<'_>,
+	) -> Result<Vec<(String, String, String)>, ZbusError> {
+		let ctx = self.context(&header);
+		let available = catch_panic(async { Ok(self.runner.lock().await.available_actions()) });
+		let available = available.await?;
+		Ok(self.state.actions(ctx.path(), available.as_deref(), &ctx))
+	}
+
+	async fn run(
+		&self,
+		match_id: String,
+		action_id: String,
+		#[zbus(header)] header: Header<'_>,
+	) -> Result<(), ZbusError> {
+		let ctx = self.context(&header);
+		event!(debug, path = %ctx.path(), match_id, action_id, "Run");
+		let result = catch_panic(async {
+			let mut lock = self.runner.lock().await;
+			handle_run(&mut *lock, match_id, &action_id, &ctx).await
+		});
+		Ok(result.await?)
+	}
+
+	#[zbus(name = "Match", out_args("matches"))]
+	async fn matches(
+		&self,
+		query: String,
+		#[zbus(header)] header: Header<'_>,
+	) -> Result<Vec<MarshalledMatch>, ZbusError> {
+		let ctx = self.context(&header);
+		event!(debug, path = %ctx.path(), query, "Match");
+		let id = self.state.begin_query(ctx.path());
+		let matches = catch_panic(handle_query(&self.runner, query, ctx, &self.state, id));
+		Ok(matches.await?.iter().map(marshal_match).collect())
+	}
+
+	#[zbus(out_args("config"))]
+	async fn config(
+		&self,
+		#[zbus(header)] header: Header<'_>,
+	) -> Result<HashMap<&'static str, Value<'static>>, ZbusError> {
+		let path = header.path().map_or_else(String::new, ToString::to_string);
+		event!(debug, path = %path, "Config");
+		let config = catch_panic(async {
+			let mut lock = self.runner.lock().await;
+			handle_config(&mut *lock, &path, &self.state).await
+		});
+		Ok(marshal_config(&config.await?))
+	}
+
+	async fn teardown(&self, #[zbus(header)] header: Header<'_>) -> Result<(), ZbusError> {
+		let path = header.path().map_or_else(String::new, ToString::to_string);
+		#[cfg(feature = "icon-lookup")]
+		crate::icons::clear_lookups();
+		let result = catch_panic(async {
+			let mut lock = self.runner.lock().await;
+			match lock.teardown_policy() {
+				TeardownPolicy::KeepCache => Ok(()),
+				TeardownPolicy::EvictCache => {
+					self.state.evict(&path);
+					Ok(())
+				}
+				TeardownPolicy::Custom => {
+					self.state.cache(&path).clear();
+					handle_teardown(&mut *lock).await
+				}
+			}
+		});
+		Ok(result.await?)
+	}
+}
+
+/// A [`MethodErr`] sent through `zbus`.
+struct ZbusError(MethodErr);
+
+impl From<MethodErr> for ZbusError {
+	fn from(e: MethodErr) -> Self {
+		Self(e)
+	}
+}
+
+impl DBusError for ZbusError {
+	fn create_reply(&self, header: &Header<'_>) -> zbus::Result<Message> {
+		Message::error(header, self.name())?.build(&(self.0.description(),))
+	}
+
+	fn name(&self) -> ErrorName<'_> {
+		// `dbus` has already validated the name
+		ErrorName::from_str_unchecked(self.0.errorname())
+	}
+
+	fn description(&self) -> Option<&str> {
+		Some(self.0.description())
+	}
+}
+
+type MarshalledMatch = (
+	String,
+	String,
+	String,
+	i32,
+	f64,
+	HashMap<&'static str, Value<'static>>,
+);
+
+/// Marshals a match the same way as its [`Append`](dbus::arg::Append) impl.
+fn marshal_match<A: Action>(m: &Match<A>) -> MarshalledMatch {
+	let mut fields = HashMap::new();
+
+	if !m.urls.is_empty() {
+		fields.insert(crate::keys::matches::URLS, Value::from(m.urls.clone()));
+	}
+	if let Some(category) = &m.category {
+		fields.insert(
+			crate::keys::matches::CATEGORY,
+			Value::from(category.clone()),
+		);
+	}
+	if let Some(subtext) = &m.subtitle {
+		fields.insert(crate::keys::matches::SUBTEXT, Value::from(subtext.clone()));
+	}
+	if m.is_multiline() {
+		fields.insert(crate::keys::matches::MULTILINE, Value::from(true));
+	}
+	if let Some(query) = &m.replace_query {
+		fields.insert(crate::keys::matches::QUERY, Value::from(query.clone()));
+	}
+	if !m.actions.is_empty() {
+		let actions: Vec<_> = m.actions.iter().map(A::to_id).collect();
+		fields.insert(crate::keys::matches::ACTIONS, Value::from(actions));
+	}
+	if let MatchIcon::Custom(icon) | MatchIcon::NameWithFallbackData { data: icon, .. } = &m.icon {
+		fields.insert(
+			crate::keys::matches::ICON_DATA,
+			Value::from(marshal_image(icon)),
+		);
+	}
+
+	(
+		m.id.clone(),
+		m.title.clone(),
+		m.icon_name().into_owned(),
+		m.ty as i32,
+		m.relevance,
+		fields,
+	)
+}
+
+/// Marshals an image the same way as its [`Append`](dbus::arg::Append) impl.
+fn marshal_image(image: &ImageData) -> Structure<'static> {
+	let image = image.marshalled();
+	Structure::from((
+		image.width,
+		image.height,
+		image.row_stride,
+		image.has_alpha,
+		image.format.bits_per_sample(),
+		i32::from(image.format.channels()),
+		image.data.clone(),
+	))
+}
+
+/// Marshals a config the same way as its [`Append`](dbus::arg::Append) impl.
+fn marshal_config<A: Action + 'static>(
+	config: &Config<A>,
+) -> HashMap<&'static str, Value<'static>> {
+	let mut fields = HashMap::new();
+
+	match &config.match_filter {
+		Some(MatchFilter::Keywords(kws)) => {
+			fields.insert(crate::keys::config::TRIGGER_WORDS, Value::from(kws.clone()));
+		}
+		Some(MatchFilter::Regex(r)) => {
+			fields.insert(crate::keys::config::MATCH_REGEX, Value::from(r.clone()));
+		}
+		_ => {}
+	}
+	if let Some(min_letter_count) = config.min_letter_count {
+		fields.insert(
+			crate::keys::config::MIN_LETTER_COUNT,
+			Value::from(min_letter_count),
+		);
+	}
+
+	let mut actions = Array::new(<(String, String, String) as Type>::SIGNATURE);
+	for action in A::all() {
+		let action = Structure::from(crate::action_as_arg(action));
+		// The signature of every action is the same as that of the array
+		let _ = actions.append(Value::from(action));
+	}
+	fields.insert(crate::keys::config::ACTIONS, Value::from(actions));
+
+	fields
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "tokio")]
+
+use std::time::Duration;
+
+use krunner::{AsyncAdapter, AsyncRunner, Match, Runner, RunnerContext, TeardownPolicy};
+
+#[derive(Default)]
+struct Counter {
+	started: bool,
+	queries: Vec<String>,
+}
+
+impl Runner for Counter {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		self.queries.push(query.clone());
+		Ok(vec![Match {
+			id: query.clone(),
+			title: query,
+			..Match::default()
+		}])
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Err(format!("cannot run {match_id}"))
+	}
+
+	fn startup(&mut self) -> Result<(), Self::Err> {
+		self.started = true;
+		Ok(())
+	}
+}
+
+#[test]
+fn runs_blocking_methods() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.build()
+		.unwrap();
+	let mut adapter = AsyncAdapter::new(Counter::default());
+	let ctx = RunnerContext::default();
+
+	rt.block_on(async {
+		adapter.startup().await.unwrap();
+		let matches = adapter.matches("hello".to_owned(), &ctx).await.unwrap();
+		assert_eq!(matches[0].title, "hello");
+		assert_eq!(
+			adapter.run("hello".to_owned(), None, &ctx).await,
+			Err("cannot run hello".to_owned())
+		);
+		assert!(adapter.config().await.unwrap().is_some());
+	});
+
+	let counter = adapter.into_inner().unwrap();
+	assert!(counter.started);
+	assert_eq!(counter.queries, ["hello"]);
+}
+
+/// A runner that takes a while to match.
+struct Slow;
+
+impl Runner for Slow {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		std::thread::sleep(Duration::from_millis(300));
+		Ok(vec![])
+	}
+
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+
+	fn allowed_activities(&self) -> Option<Vec<String>> {
+		Some(vec!["work".to_owned()])
+	}
+
+	fn teardown_policy(&self) -> TeardownPolicy {
+		TeardownPolicy::KeepCache
+	}
+}
+
+#[test]
+fn does_not_wait_for_dropped_calls() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_time()
+		.build()
+		.unwrap();
+	let mut adapter = AsyncAdapter::new(Slow);
+	let ctx = RunnerContext::default();
+
+	rt.block_on(async {
+		assert_eq!(adapter.allowed_activities(), Some(vec!["work".to_owned()]));
+		let matches = adapter.matches("hello".to_owned(), &ctx);
+		assert!(
+			tokio::time::timeout(Duration::from_millis(50), matches)
+				.await
+				.is_err()
+		);
+		// The runner is still matching on the blocking thread pool
+		assert_eq!(adapter.allowed_activities(), Some(vec!["work".to_owned()]));
+		assert_eq!(adapter.teardown_policy(), TeardownPolicy::KeepCache);
+	});
+}
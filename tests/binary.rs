@@ -0,0 +1,40 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use krunner::{require_binary, ErrorKind, MissingBinary, RunnerError};
+
+#[test]
+fn finds_binaries_in_path() {
+	let sh = require_binary("sh").unwrap();
+	assert!(sh.is_absolute());
+	assert!(sh.ends_with("sh"));
+}
+
+#[test]
+fn missing_binaries() {
+	let err = require_binary("krunner-rs-does-not-exist").unwrap_err();
+	assert_eq!(err, MissingBinary("krunner-rs-does-not-exist".to_owned()));
+	assert_eq!(err.kind(), ErrorKind::NotSupported);
+	assert!(err.to_string().contains("krunner-rs-does-not-exist"));
+	assert!(require_binary("").is_err());
+
+	let err = std::io::Error::from(err);
+	assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn paths_must_be_executable() {
+	let dir = std::env::temp_dir().join(format!("krunner-binary-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let file = dir.join("tool");
+	fs::write(&file, "#!/bin/sh\n").unwrap();
+	let path = file.to_str().unwrap();
+
+	fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+	assert!(require_binary(path).is_err());
+	fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+	assert_eq!(require_binary(path).unwrap(), file);
+	assert!(require_binary(dir.to_str().unwrap()).is_err());
+
+	fs::remove_dir_all(dir).unwrap();
+}
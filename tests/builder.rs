@@ -0,0 +1,123 @@
+use krunner::{Match, Runner, RunnerBuilder, RunnerContext, RunnerExt, RunnerHost};
+
+struct Empty;
+
+impl Runner for Empty {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Ok(vec![])
+	}
+
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+}
+
+fn run(service: &'static str, path: &'static str) -> dbus::Error {
+	RunnerBuilder::new(Empty)
+		.service(service)
+		.path(path)
+		.run()
+		.unwrap_err()
+}
+
+#[test]
+fn rejects_invalid_service_names() {
+	for service in [
+		"/SomeRunner",
+		"runner",
+		"some..runner",
+		"some.1runner",
+		"some.runner!",
+		":1.42",
+	] {
+		let err = run(service, "/SomeRunner");
+		assert_eq!(err.name(), Some("org.freedesktop.DBus.Error.InvalidArgs"));
+		assert!(err.message().unwrap().contains("invalid service name"));
+	}
+}
+
+#[test]
+fn rejects_invalid_paths() {
+	let err = run("some.runner.path", "some.runner.path");
+	assert_eq!(err.name(), Some("org.freedesktop.DBus.Error.InvalidArgs"));
+	assert!(err.message().unwrap().contains("invalid object path"));
+}
+
+#[test]
+fn requires_service_and_path() {
+	let err = RunnerBuilder::new(Empty)
+		.path("/SomeRunner")
+		.run()
+		.unwrap_err();
+	assert_eq!(err.message(), Some("no service name was given"));
+
+	let err = RunnerBuilder::new(Empty)
+		.service("some.runner.path")
+		.run()
+		.unwrap_err();
+	assert_eq!(err.message(), Some("no object path was given"));
+}
+
+#[test]
+fn start_validates_before_connecting() {
+	let err = Empty.start("/SomeRunner", "some.runner.path").unwrap_err();
+	assert_eq!(err.name(), Some("org.freedesktop.DBus.Error.InvalidArgs"));
+	assert!(err.message().unwrap().contains("invalid service name"));
+
+	let err = Empty.start("some.runner.path", "SomeRunner").unwrap_err();
+	assert_eq!(err.name(), Some("org.freedesktop.DBus.Error.InvalidArgs"));
+	assert!(err.message().unwrap().contains("invalid object path"));
+}
+
+#[test]
+fn host_reports_invalid_paths() {
+	let err = RunnerHost::new("some.runner.path")
+		.with_runner("/SomeRunner", Empty)
+		.with_runner("Other/", Empty)
+		.spawn()
+		.unwrap_err();
+	assert_eq!(err.name(), Some("org.freedesktop.DBus.Error.InvalidArgs"));
+	assert!(err.message().unwrap().contains("\"Other/\""));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn validates_async_runners() {
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Arc;
+
+	use krunner::{AsyncAdapter, Spawner};
+
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.build()
+		.unwrap();
+	let spawned = Arc::new(AtomicBool::new(false));
+	let spawner = Spawner::new({
+		let spawned = spawned.clone();
+		move |_| spawned.store(true, Ordering::Relaxed)
+	});
+
+	let err = rt
+		.block_on(
+			RunnerBuilder::new(AsyncAdapter::new(Empty))
+				.service("some.runner.path")
+				.path("some.runner.path")
+				.spawner(spawner)
+				.run_async(),
+		)
+		.unwrap_err();
+	assert_eq!(err.name(), Some("org.freedesktop.DBus.Error.InvalidArgs"));
+	assert!(!spawned.load(Ordering::Relaxed));
+}
@@ -0,0 +1,101 @@
+#![cfg(feature = "derive")]
+
+use krunner::Action;
+
+#[derive(Debug, PartialEq, krunner::Action)]
+enum Documented {
+	/// Open in Browser
+	///
+	/// The title is taken from the first line of the doc comment.
+	#[action(id = "open", icon = "internet-web-browser")]
+	Open,
+	/// This doc comment is overridden by the explicit title.
+	#[action(id = "save", title = "Save to Folder", icon = "document-save")]
+	Save,
+}
+
+#[derive(Debug, PartialEq, krunner::Action)]
+enum WithDefault {
+	#[action(id = "copy", title = "Copy", icon = "edit-copy")]
+	Copy,
+	#[action(id = "open", title = "Open", icon = "document-open", default)]
+	Open,
+}
+
+#[derive(Debug, PartialEq, krunner::Action)]
+#[action(from_str, display)]
+enum Strings {
+	#[action(id = "copy", title = "Copy", icon = "edit-copy")]
+	Copy,
+	#[action(id = "open", title = "Open", icon = "document-open")]
+	Open,
+}
+
+#[derive(Debug, PartialEq, krunner::Action)]
+#[action(case_insensitive)]
+enum CaseInsensitive {
+	#[action(id = "Copy", title = "Copy", icon = "edit-copy")]
+	Copy,
+	#[action(id = "open-in-browser", title = "Open", icon = "document-open")]
+	Open,
+}
+
+#[derive(Debug, PartialEq, krunner::Action)]
+enum Generic<const N: usize>
+where
+	[u8; N]: Sized,
+{
+	#[action(id = "only", title = "Only", icon = "only")]
+	Only,
+}
+
+#[test]
+fn title_from_doc_comment() {
+	assert_eq!(Documented::Open.info().title, "Open in Browser");
+	assert_eq!(Documented::Save.info().title, "Save to Folder");
+	assert_eq!(Documented::from_id("save"), Some(Documented::Save));
+}
+
+#[test]
+fn generics_and_where_clause() {
+	assert_eq!(Generic::<4>::all(), &[Generic::Only]);
+	assert_eq!(Generic::<4>::Only.to_id(), "only");
+}
+
+#[test]
+fn default_action() {
+	assert_eq!(WithDefault::default_action(), Some(WithDefault::Open));
+	assert_eq!(Documented::default_action(), None);
+}
+
+#[test]
+fn from_str_and_display() {
+	assert_eq!("open".parse(), Ok(Strings::Open));
+	assert_eq!(
+		"paste".parse::<Strings>(),
+		Err(krunner::InvalidActionId("paste".to_owned()))
+	);
+	assert_eq!(Strings::Copy.to_string(), "copy");
+	assert_eq!(Strings::Copy.to_string(), Strings::Copy.to_id());
+}
+
+#[test]
+fn case_insensitive() {
+	assert_eq!(
+		CaseInsensitive::from_id("copy"),
+		Some(CaseInsensitive::Copy)
+	);
+	assert_eq!(
+		CaseInsensitive::from_id(" COPY\n"),
+		Some(CaseInsensitive::Copy)
+	);
+	assert_eq!(
+		CaseInsensitive::from_id("Open-In-Browser"),
+		Some(CaseInsensitive::Open)
+	);
+	assert_eq!(CaseInsensitive::from_id("paste"), None);
+	assert_eq!(CaseInsensitive::Copy.to_id(), "Copy");
+
+	assert_eq!(Strings::from_id("Copy"), None);
+	assert_eq!(Strings::from_id(" copy"), None);
+}
@@ -0,0 +1,292 @@
+use krunner::testing::TestHarness;
+use krunner::{
+	Action,
+	ActionInfo,
+	Config,
+	Match,
+	MatchFilter,
+	MatchIcon,
+	MatchType,
+	Runner,
+	RunnerContext,
+	SharedRunner,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Act {
+	Copy,
+}
+
+impl Action for Act {
+	fn all() -> &'static [Self] {
+		&[Self::Copy]
+	}
+
+	fn from_id(s: &str) -> Option<Self> {
+		(s == "copy").then_some(Self::Copy)
+	}
+
+	fn to_id(&self) -> String {
+		"copy".to_owned()
+	}
+
+	fn info(&self) -> ActionInfo {
+		ActionInfo {
+			title: "Copy".to_owned(),
+			icon: "edit-copy".to_owned(),
+		}
+	}
+}
+
+#[derive(Default)]
+struct Calc {
+	ran: Vec<(String, Option<Act>)>,
+	operands: Option<(i64, i64)>,
+	hide_copy: bool,
+	static_config: bool,
+	config_calls: usize,
+	raw_query: Option<String>,
+}
+
+impl Runner for Calc {
+	type Action = Act;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		if let Some(text) = query.strip_prefix("echo ") {
+			return Ok(vec![Match {
+				id: "echo".to_owned(),
+				title: text.replace("\\n", "\n"),
+				icon: MatchIcon::Path("/usr/share/icons/echo.svg".into()),
+				..Match::default()
+			}]);
+		}
+		self.raw_query = ctx.raw_query().map(ToOwned::to_owned);
+		let Some((a, b)) = ctx.without_trigger_word(&query).split_once('+') else {
+			return Ok(vec![]);
+		};
+		let a: i64 = a.trim().parse().map_err(|_| "bad number")?;
+		let b: i64 = b.trim().parse().map_err(|_| "bad number")?;
+		ctx.cache().insert((a + b).to_string(), (a, b));
+		Ok(vec![Match {
+			id: (a + b).to_string(),
+			title: (a + b).to_string(),
+			ty: MatchType::ExactMatch,
+			relevance: 0.5,
+			category: Some("Calculator".to_owned()),
+			replace_query: Some((a + b).to_string()),
+			actions: vec![Act::Copy],
+			..Match::default()
+		}])
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.operands = ctx.cache().get(&match_id);
+		self.ran.push((match_id, action));
+		Ok(())
+	}
+
+	fn default_action_for(&self, match_id: &str) -> Option<Self::Action> {
+		(match_id == "echo").then_some(Act::Copy)
+	}
+
+	fn available_actions(&self) -> Option<Vec<Self::Action>> {
+		self.hide_copy.then(Vec::new)
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		self.config_calls += 1;
+		let mut config = Config::default();
+		config.match_filter = Some(MatchFilter::Regex("^\\d".to_owned()));
+		Ok(config)
+	}
+
+	fn config_is_static(&self) -> bool {
+		self.static_config
+	}
+}
+
+#[test]
+fn marshal_matches() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	let matches = harness.marshal_matches("1 + 2").unwrap();
+	assert_eq!(matches.len(), 1);
+	assert_eq!(matches[0].id, "3");
+	assert_eq!(matches[0].match_type(), Some(MatchType::ExactMatch));
+	assert!((matches[0].relevance - 0.5).abs() < f64::EPSILON);
+	assert_eq!(matches[0].category(), Some("Calculator"));
+	assert_eq!(matches[0].subtitle(), None);
+	assert_eq!(matches[0].replace_query(), Some("3"));
+	assert!(!matches[0].multiline());
+	assert_eq!(matches[0].actions(), ["copy"]);
+
+	assert!(harness.marshal_matches("hello").unwrap().is_empty());
+	assert!(harness.marshal_matches("a + b").is_err());
+}
+
+#[test]
+fn trigger_words() {
+	let ctx = RunnerContext::default().with_trigger_words(["calc"]);
+	let mut harness = TestHarness::new(Calc::default()).with_context(ctx);
+
+	let matches = harness.matches("Calc 1 + 2").unwrap();
+	assert_eq!(matches[0].id, "3");
+	assert_eq!(harness.runner().raw_query.as_deref(), Some("Calc 1 + 2"));
+
+	harness.run("3", "").unwrap();
+	assert_eq!(harness.runner().operands, Some((1, 2)));
+}
+
+#[test]
+fn trigger_words_are_whole_words() {
+	let ctx = RunnerContext::default().with_trigger_words(["nix"]);
+	assert_eq!(ctx.without_trigger_word("nix hello"), "hello");
+	assert_eq!(ctx.without_trigger_word("nix"), "");
+	assert_eq!(ctx.without_trigger_word("nixos"), "nixos");
+}
+
+#[test]
+fn multiline_from_newlines() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	let matches = harness.marshal_matches("echo one\\ntwo").unwrap();
+	assert_eq!(matches[0].title, "one\ntwo");
+	assert!(matches[0].multiline());
+}
+
+#[test]
+fn icon_from_path() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	let matches = harness.marshal_matches("echo hi").unwrap();
+	assert_eq!(matches[0].icon, "/usr/share/icons/echo.svg");
+}
+
+#[test]
+fn run() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	harness.run("3", "").unwrap();
+	harness.run("3", "copy").unwrap();
+	harness.run("echo", "").unwrap();
+	assert_eq!(harness.runner().ran, [
+		("3".to_owned(), None),
+		("3".to_owned(), Some(Act::Copy)),
+		("echo".to_owned(), Some(Act::Copy))
+	]);
+}
+
+#[test]
+fn run_many() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	harness
+		.run_many(&[("1", ""), ("2", "copy"), ("echo", "")])
+		.unwrap();
+	assert_eq!(harness.runner().ran, [
+		("1".to_owned(), None),
+		("2".to_owned(), Some(Act::Copy)),
+		("echo".to_owned(), Some(Act::Copy))
+	]);
+}
+
+#[test]
+fn match_cache() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	harness.matches("1 + 2").unwrap();
+	harness.run("3", "").unwrap();
+	assert_eq!(harness.runner().operands, Some((1, 2)));
+
+	// A new query clears the cache
+	harness.matches("2 + 2").unwrap();
+	harness.run("3", "").unwrap();
+	assert_eq!(harness.runner().operands, None);
+}
+
+#[test]
+fn available_actions() {
+	let mut harness = TestHarness::new(Calc::default());
+	assert_eq!(harness.actions(), ["copy"]);
+
+	harness.runner().hide_copy = true;
+	assert!(harness.actions().is_empty());
+	// Hidden actions can still be run
+	harness.run("3", "copy").unwrap();
+}
+
+#[test]
+fn marshal_config() {
+	let mut harness = TestHarness::new(Calc::default());
+
+	let config = harness.marshal_config().unwrap();
+	assert_eq!(
+		dbus::arg::prop_cast::<String>(&config, "MatchRegex").map(String::as_str),
+		Some("^\\d")
+	);
+	assert!(config.contains_key("Actions"));
+}
+
+#[test]
+fn static_config() {
+	let mut harness = TestHarness::new(Calc::default());
+	harness.config().unwrap();
+	harness.config().unwrap();
+	assert_eq!(harness.runner().config_calls, 2);
+
+	let mut harness = TestHarness::new(Calc {
+		static_config: true,
+		..Calc::default()
+	});
+	harness.config().unwrap();
+	let config = harness.config().unwrap();
+	assert_eq!(harness.runner().config_calls, 1);
+	assert_eq!(
+		config.match_filter,
+		Some(MatchFilter::Regex("^\\d".to_owned()))
+	);
+}
+
+#[test]
+#[allow(deprecated)]
+fn match_type_round_trip() {
+	for ty in [
+		MatchType::NoMatch,
+		MatchType::CompletionMatch,
+		MatchType::PossibleMatch,
+		MatchType::InformationalMatch,
+		MatchType::HelperMatch,
+		MatchType::ExactMatch,
+	] {
+		assert_eq!(MatchType::try_from(ty as i32), Ok(ty));
+	}
+	assert!(MatchType::try_from(-1).is_err());
+}
+
+#[test]
+fn shared_runner() {
+	let shared = SharedRunner::new(Calc::default());
+	let mut a = TestHarness::new(shared.clone());
+	let mut b = TestHarness::new(shared.clone());
+
+	a.matches("1 + 2").unwrap();
+	b.run("3", "copy").unwrap();
+	b.run("4", "").unwrap();
+	assert_eq!(shared.lock().ran, [
+		("3".to_owned(), Some(Act::Copy)),
+		("4".to_owned(), None)
+	]);
+
+	drop((a, b));
+	assert!(shared.into_inner().is_some());
+}
@@ -0,0 +1,34 @@
+use krunner::{Match, Runner, RunnerContext, RunnerExt};
+
+struct Empty;
+
+impl Runner for Empty {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Ok(vec![])
+	}
+
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+}
+
+#[test]
+fn spawn_reports_connection_errors() {
+	// This test runs in its own process, so no other test sees the bogus bus
+	std::env::set_var("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent/bus");
+
+	let err = Empty.spawn("org.kde.krunner1.Test", "/Test").unwrap_err();
+	assert!(err.message().is_some());
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "icon-lookup")]
+
+use std::fs;
+
+use krunner::testing::TestHarness;
+use krunner::{Match, MatchIcon, Runner, RunnerContext};
+
+struct Icons;
+
+impl Runner for Icons {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		let names = query.split(' ').map(str::to_owned).collect();
+		Ok(vec![
+			Match::new("icon").icon(MatchIcon::ByNameWithFallbacks(names))
+		])
+	}
+
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+}
+
+#[test]
+fn lookup_with_fallbacks() {
+	let root = std::env::temp_dir().join(format!("krunner-icons-{}", std::process::id()));
+	let theme = root.join("data/icons/custom");
+	let hicolor = root.join("data/icons/hicolor");
+	fs::create_dir_all(theme.join("scalable/apps")).unwrap();
+	fs::create_dir_all(hicolor.join("48x48/apps")).unwrap();
+	fs::create_dir_all(root.join("config")).unwrap();
+	fs::write(
+		theme.join("index.theme"),
+		"[Icon Theme]\nName=Custom\nDirectories=scalable/apps\n",
+	)
+	.unwrap();
+	fs::write(
+		hicolor.join("index.theme"),
+		"[Icon Theme]\nName=Hicolor\nDirectories=48x48/apps\n",
+	)
+	.unwrap();
+	fs::write(theme.join("scalable/apps/themed.svg"), "<svg/>").unwrap();
+	fs::write(hicolor.join("48x48/apps/fallback.png"), "").unwrap();
+	fs::write(root.join("config/kdeglobals"), "[Icons]\nTheme=custom\n").unwrap();
+
+	// This test runs in its own process, so no other test sees these
+	std::env::set_var("HOME", root.join("home"));
+	std::env::set_var("XDG_DATA_HOME", root.join("home/.local/share"));
+	std::env::set_var("XDG_DATA_DIRS", root.join("data"));
+	std::env::set_var("XDG_CONFIG_HOME", root.join("config"));
+
+	assert_eq!(
+		krunner::lookup_icon("themed"),
+		Some(theme.join("scalable/apps/themed.svg"))
+	);
+	assert_eq!(
+		krunner::lookup_icon("fallback"),
+		Some(hicolor.join("48x48/apps/fallback.png"))
+	);
+	assert_eq!(krunner::lookup_icon("missing"), None);
+	assert_eq!(krunner::lookup_icon("../custom/index"), None);
+
+	let mut harness = TestHarness::new(Icons);
+	let mut icon = |query| harness.marshal_matches(query).unwrap().remove(0).icon;
+	assert_eq!(icon("missing themed"), "themed");
+	assert_eq!(icon("missing fallback themed"), "fallback");
+	assert_eq!(icon("missing other"), "missing");
+
+	// Lookups are remembered until the runner is torn down
+	fs::write(theme.join("scalable/apps/missing.svg"), "<svg/>").unwrap();
+	assert_eq!(krunner::lookup_icon("missing"), None);
+
+	fs::remove_dir_all(root).unwrap();
+}
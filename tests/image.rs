@@ -0,0 +1,167 @@
+use krunner::{ImageData, ImageFormat, InvalidImageSize};
+
+/// Red, green, blue and half-transparent white, from top-left to
+/// bottom-right.
+const EXPECTED: [u8; 16] = [
+	0xff, 0x00, 0x00, 0xff, //
+	0x00, 0xff, 0x00, 0xff, //
+	0x00, 0x00, 0xff, 0xff, //
+	0xff, 0xff, 0xff, 0x80, //
+];
+
+fn image(format: ImageFormat, row_stride: i32, data: Vec<u8>) -> ImageData {
+	ImageData {
+		width: 2,
+		height: 2,
+		row_stride,
+		has_alpha: true,
+		format,
+		data,
+	}
+}
+
+#[test]
+fn argb32_is_unchanged() {
+	let converted = image(ImageFormat::Argb32, 8, EXPECTED.to_vec()).to_argb32();
+	assert_eq!(converted.data, EXPECTED);
+	assert_eq!(converted.row_stride, 8);
+}
+
+#[test]
+fn bgra32() {
+	#[rustfmt::skip]
+	let data = vec![
+		0x00, 0x00, 0xff, 0xff,  0x00, 0xff, 0x00, 0xff,
+		0xff, 0x00, 0x00, 0xff,  0xff, 0xff, 0xff, 0x80,
+	];
+	assert_eq!(
+		image(ImageFormat::Bgra32, 8, data).to_argb32().data,
+		EXPECTED
+	);
+}
+
+#[test]
+fn argb_be32() {
+	#[rustfmt::skip]
+	let data = vec![
+		0xff, 0xff, 0x00, 0x00,  0xff, 0x00, 0xff, 0x00,
+		0xff, 0x00, 0x00, 0xff,  0x80, 0xff, 0xff, 0xff,
+	];
+	assert_eq!(
+		image(ImageFormat::ArgbBe32, 8, data).to_argb32().data,
+		EXPECTED
+	);
+}
+
+#[test]
+fn packed_pixels() {
+	let pixels = [0xffff_0000, 0xff00_ff00, 0xff00_00ff, 0x80ff_ffff];
+	let image = ImageData::from_argb32_pixels(2, 2, &pixels);
+	assert_eq!(image.data, EXPECTED);
+	assert_eq!(image.to_argb32(), image);
+}
+
+#[test]
+fn rgb32_with_padded_rows() {
+	#[rustfmt::skip]
+	let data = vec![
+		0xff, 0x00, 0x00,  0x00, 0xff, 0x00,  0xaa, 0xaa,
+		0x00, 0x00, 0xff,  0xff, 0xff, 0xff,  0xaa, 0xaa,
+	];
+	let converted = image(ImageFormat::Rgb32, 8, data).to_argb32();
+
+	let mut expected = EXPECTED;
+	expected[15] = 0xff;
+	assert_eq!(converted.data, expected);
+	assert_eq!(converted.row_stride, 8);
+}
+
+#[test]
+fn gray8_without_alpha() {
+	let mut image = image(ImageFormat::Gray8, 2, vec![0x00, 0x40, 0x80, 0xff]);
+	image.has_alpha = false;
+
+	#[rustfmt::skip]
+	let expected = [
+		0x00, 0x00, 0x00, 0xff,  0x40, 0x40, 0x40, 0xff,
+		0x80, 0x80, 0x80, 0xff,  0xff, 0xff, 0xff, 0xff,
+	];
+	assert_eq!(image.to_argb32().data, expected);
+}
+
+#[test]
+fn from_rgba8() {
+	let image = ImageData::from_rgba8(2, 2, &EXPECTED).unwrap();
+	assert_eq!(image, image.to_argb32());
+	assert_eq!(image.data, EXPECTED);
+
+	#[allow(clippy::cast_possible_truncation)]
+	let data: Vec<u8> = (0..5 * 3 * 4).map(|i| i as u8).collect();
+	let image = ImageData::from_rgba8(5, 3, &data).unwrap();
+	assert_eq!((image.width, image.height, image.row_stride), (5, 3, 20));
+	assert_eq!(image.to_argb32().data, data);
+
+	let err = ImageData::from_rgba8(5, 3, &data[4..]).unwrap_err();
+	assert_eq!(err, InvalidImageSize {
+		width: 5,
+		height: 3,
+		len: 56
+	});
+	assert_eq!(
+		err.to_string(),
+		"expected 60 bytes of RGBA data for a 5x3 image, got 56"
+	);
+	assert!(ImageData::from_rgba8(u32::MAX, 1, &[]).is_err());
+	assert!(ImageData::from_rgba8(0, 7, &[]).is_ok());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn from_dynamic_image() {
+	use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+	let gradient = RgbaImage::from_fn(4, 3, |x, y| {
+		#[allow(clippy::cast_possible_truncation)]
+		Rgba([(x * 64) as u8, (y * 96) as u8, 0x40, (255 - x * 32) as u8])
+	});
+	let data = ImageData::try_from(DynamicImage::ImageRgba8(gradient.clone())).unwrap();
+	assert_eq!((data.width, data.height, data.row_stride), (4, 3, 16));
+	assert!(data.has_alpha);
+	assert_eq!(data.format, ImageFormat::Argb32);
+
+	let round_trip = RgbaImage::from_raw(4, 3, data.to_argb32().data).unwrap();
+	assert_eq!(round_trip, gradient);
+
+	let opaque = RgbImage::from_fn(4, 3, |x, y| {
+		#[allow(clippy::cast_possible_truncation)]
+		Rgb([(x * 64) as u8, (y * 96) as u8, 0x40])
+	});
+	let data = ImageData::try_from(DynamicImage::ImageRgb8(opaque.clone())).unwrap();
+	assert_eq!((data.row_stride, data.has_alpha), (12, false));
+	assert_eq!(data.format, ImageFormat::Rgb32);
+	assert_eq!(RgbImage::from_raw(4, 3, data.data).unwrap(), opaque);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn png_round_trip() {
+	let original = image(ImageFormat::Bgra32, 8, {
+		let mut data = EXPECTED.to_vec();
+		for px in data.chunks_mut(4) {
+			px.swap(0, 2);
+		}
+		data
+	});
+	let png = original.to_png_bytes().unwrap();
+
+	let decoded = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap();
+	let data = ImageData::try_from(decoded).unwrap();
+	assert_eq!((data.width, data.height), (2, 2));
+	assert_eq!(data.data, EXPECTED);
+
+	let invalid = ImageData {
+		width: -1,
+		..original
+	};
+	assert!(invalid.to_png_bytes().is_err());
+}
@@ -0,0 +1,30 @@
+use krunner::fuzzy_score;
+
+#[test]
+fn tiers() {
+	let scores = [
+		fuzzy_score("dolphin", "Dolphin"),
+		fuzzy_score("dol", "Dolphin"),
+		fuzzy_score("phin", "Dolphin"),
+		fuzzy_score("dpn", "Dolphin"),
+		fuzzy_score("konsole", "Dolphin"),
+	];
+	assert_eq!(scores[0], 1.0);
+	assert!(scores.windows(2).all(|w| w[0] > w[1]), "{scores:?}");
+	assert_eq!(scores[4], 0.0);
+}
+
+#[test]
+fn shorter_and_tighter_matches_score_higher() {
+	assert!(fuzzy_score("kate", "Kate") > fuzzy_score("kate", "KWrite"));
+	assert!(fuzzy_score("term", "Terminal") > fuzzy_score("term", "Terminator"));
+	assert!(fuzzy_score("ks", "Konsole") > fuzzy_score("ks", "Kate Sessions"));
+}
+
+#[test]
+fn edge_cases() {
+	assert_eq!(fuzzy_score("", "anything"), 0.0);
+	assert_eq!(fuzzy_score("longer query", "short"), 0.0);
+	assert_eq!(fuzzy_score("ÉCRAN", "écran"), 1.0);
+	assert!((0.0..=1.0).contains(&fuzzy_score("ö", "Dolphin öffnen")));
+}
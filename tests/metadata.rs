@@ -0,0 +1,123 @@
+//! Checks the generated runner metadata against the files in `tests/golden`.
+//!
+//! Run with `KRUNNER_BLESS=1` to update the golden files after an intentional
+//! change.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use krunner::{MetadataOptions, PlasmaApi, Syntax};
+
+const SERVICE: &str = "org.example.units";
+const PATH: &str = "/Units";
+
+fn options() -> MetadataOptions {
+	MetadataOptions {
+		name: "Units".to_owned(),
+		comment: Some("Converts between \"units\" of measurement".to_owned()),
+		icon: Some("accessories-calculator".to_owned()),
+		x_plasma_api: PlasmaApi::DBus2,
+		config_module: Some("kcm_krunner_units".to_owned()),
+		request_actions_once: true,
+		single_runner_mode: true,
+		syntaxes: vec![
+			Syntax {
+				query: ":q:".to_owned(),
+				description: "Type a unit, e.g. 5 km".to_owned(),
+			},
+			Syntax {
+				query: ":q: in :q:".to_owned(),
+				description: "Converts a value, e.g. 5 km in miles".to_owned(),
+			},
+		],
+	}
+}
+
+fn check_golden(name: &str, actual: &str) {
+	let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/golden")
+		.join(name);
+
+	if env::var_os("KRUNNER_BLESS").is_some() {
+		fs::write(&golden, actual).unwrap();
+		return;
+	}
+	let expected = fs::read_to_string(&golden)
+		.unwrap_or_else(|e| panic!("cannot read {}: {e}", golden.display()));
+	assert_eq!(
+		expected, actual,
+		"{name} differs from its golden file; rerun with KRUNNER_BLESS=1 if this is intended"
+	);
+}
+
+#[test]
+fn desktop_entry() {
+	check_golden(
+		"plasma-runner-org.example.units.desktop",
+		&options().desktop_entry(SERVICE, PATH),
+	);
+}
+
+#[test]
+fn desktop_entry_dbus1() {
+	let options = MetadataOptions {
+		comment: None,
+		icon: None,
+		x_plasma_api: PlasmaApi::DBus,
+		config_module: None,
+		request_actions_once: false,
+		single_runner_mode: false,
+		syntaxes: vec![],
+		..options()
+	};
+	check_golden(
+		"plasma-runner-org.example.units.dbus1.desktop",
+		&options.desktop_entry(SERVICE, PATH),
+	);
+}
+
+#[test]
+fn json_metadata() {
+	check_golden(
+		"plasma-runner-org.example.units.json",
+		&options().json_metadata(SERVICE, PATH),
+	);
+}
+
+#[test]
+fn dbus_service() {
+	check_golden(
+		"org.example.units.service",
+		&krunner::dbus_service(SERVICE, &PathBuf::from("/usr/bin/krunner-units")),
+	);
+}
+
+#[test]
+fn write_metadata_rejects_invalid_services() {
+	let out_dir = env::temp_dir().join(format!("krunner-write-{}", std::process::id()));
+	let err = krunner::write_metadata(&out_dir, "../../units", PATH, &options()).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+	assert!(!out_dir.exists());
+}
+
+#[test]
+fn install_metadata() {
+	let root = env::temp_dir().join(format!("krunner-install-{}", std::process::id()));
+	env::set_var("XDG_DATA_HOME", &root);
+
+	krunner::install_metadata(SERVICE, PATH, &options()).unwrap();
+	let desktop = root.join("krunner/dbusplugins/plasma-runner-org.example.units.desktop");
+	assert_eq!(
+		fs::read_to_string(desktop).unwrap(),
+		options().desktop_entry(SERVICE, PATH)
+	);
+	let service = fs::read_to_string(root.join("dbus-1/services/org.example.units.service"));
+	assert!(service
+		.unwrap()
+		.starts_with("[D-BUS Service]\nName=org.example.units\n"));
+
+	let err = krunner::install_metadata("../units", PATH, &options()).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+	fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "notifications")]
+
+use std::env;
+
+use krunner::testing::TestHarness;
+use krunner::{Match, NotifyErrors, Runner, RunnerContext};
+
+struct Failing {
+	ran: u32,
+}
+
+impl Runner for Failing {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Ok(vec![Match::builder(&query).title(&query).build()])
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.ran += 1;
+		Err(format!("cannot run {match_id}"))
+	}
+}
+
+/// Points the session bus at a socket that doesn't exist, so that no
+/// notification can actually be sent.
+fn without_session_bus() {
+	env::set_var(
+		"DBUS_SESSION_BUS_ADDRESS",
+		"unix:path=/nonexistent/krunner-rs",
+	);
+}
+
+#[test]
+fn notify_error_without_session_bus() {
+	without_session_bus();
+	assert!(krunner::notify_error("Test", "", "Action failed", &"oops").is_err());
+}
+
+#[test]
+fn errors_are_still_returned() {
+	without_session_bus();
+	let mut harness = TestHarness::new(NotifyErrors::new(Failing { ran: 0 }, "Test"));
+
+	assert_eq!(harness.matches("firefox").unwrap().len(), 1);
+	assert_eq!(
+		harness.run("firefox", "").unwrap_err(),
+		"cannot run firefox"
+	);
+	assert_eq!(
+		harness.run_many(&[("a", ""), ("b", "")]).unwrap_err(),
+		"cannot run a"
+	);
+	assert_eq!(harness.runner().runner().ran, 2);
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+
+use krunner::{DynAction, Match, MatchProvider, ProviderHost, RunnerContext};
+
+struct Named(&'static str);
+
+impl MatchProvider for Named {
+	fn name(&self) -> &str {
+		self.0
+	}
+
+	fn matches(&self, _query: &str, _ctx: &RunnerContext) -> Vec<Match<DynAction>> {
+		vec![]
+	}
+
+	fn run(
+		&self,
+		_match_id: &str,
+		_action: Option<&DynAction>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Box<dyn Error + Send + Sync>> {
+		Ok(())
+	}
+}
+
+#[test]
+fn distinct_names() {
+	let _ = ProviderHost::new()
+		.with_provider(Named("echo"))
+		.with_provider(Named("reverse"));
+}
+
+#[test]
+#[should_panic = "already taken"]
+fn rejects_duplicate_names() {
+	let mut host = ProviderHost::new().with_provider(Named("echo"));
+	host.add(Box::new(Named("echo")));
+}
+
+#[test]
+#[should_panic = "must not contain '/'"]
+fn rejects_names_with_slashes() {
+	let _ = ProviderHost::new().with_provider(Named("web/search"));
+}
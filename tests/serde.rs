@@ -0,0 +1,39 @@
+#![cfg(feature = "serde")]
+
+use std::convert::Infallible;
+
+use krunner::{Config, MatchFilter, MatchType};
+
+#[test]
+fn config_round_trip() {
+	let mut config = Config::<Infallible>::default().min_letter_count(2).unwrap();
+	config.match_filter = Some(MatchFilter::Regex("^=".to_owned()));
+	config.strip_trigger_words = true;
+	config.dedup_ids = true;
+
+	let json = serde_json::to_string(&config).unwrap();
+	assert_eq!(
+		serde_json::from_str::<Config<Infallible>>(&json).unwrap(),
+		config
+	);
+}
+
+#[test]
+fn missing_fields_are_defaulted() {
+	let config: Config<Infallible> = serde_json::from_str(r#"{ "preserve_order": true }"#).unwrap();
+	assert!(config.preserve_order);
+	assert_eq!(config.match_filter, None);
+	assert_eq!(config.max_results, None);
+}
+
+#[test]
+fn match_type_names() {
+	assert_eq!(
+		serde_json::to_string(&MatchType::ExactMatch).unwrap(),
+		r#""exact_match""#
+	);
+	assert_eq!(
+		serde_json::from_str::<MatchType>(r#""helper_match""#).unwrap(),
+		MatchType::HelperMatch
+	);
+}
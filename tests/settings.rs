@@ -0,0 +1,65 @@
+use krunner::{ConfigField, ConfigPage, ConfigValue, Match, Runner, RunnerContext, RunnerExt};
+
+struct Empty;
+
+impl Runner for Empty {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Ok(vec![])
+	}
+
+	fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+}
+
+#[test]
+fn config_dir() {
+	let root = std::env::temp_dir().join(format!("krunner-config-{}", std::process::id()));
+	// This test runs in its own process, so no other test sees the fake home
+	std::env::set_var("XDG_CONFIG_HOME", &root);
+
+	let dir = Empty::config_dir("some.runner.path").unwrap();
+	assert_eq!(dir, root.join("some.runner.path"));
+	assert!(dir.is_dir());
+
+	let err = Empty::config_dir("../escape").unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+	std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn load() {
+	let path = std::env::temp_dir().join(format!("krunner-krunnerrc-{}", std::process::id()));
+	std::fs::write(
+		&path,
+		"[Runners][some.runner.path]\nshow-icons=false \nname = Some\\sname\\s  \n",
+	)
+	.unwrap();
+
+	let page = ConfigPage {
+		fields: vec![
+			ConfigField::new("show-icons", "Show icons", ConfigValue::Bool(true)),
+			ConfigField::new("name", "Name", ConfigValue::String(String::new())),
+			ConfigField::new("max-results", "Max results", ConfigValue::Int(10)),
+		],
+	};
+	let values = page.load(&path, "Runners][some.runner.path").unwrap();
+	assert_eq!(values["show-icons"], ConfigValue::Bool(false));
+	assert_eq!(values["name"], ConfigValue::String("Some name ".to_owned()));
+	assert_eq!(values["max-results"], ConfigValue::Int(10));
+
+	std::fs::remove_file(path).unwrap();
+}
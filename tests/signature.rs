@@ -0,0 +1,164 @@
+//! KRunner only accepts replies with the exact signatures below, so these
+//! guard against e.g. reordering or retyping fields by accident.
+
+use dbus::arg::{prop_cast, Append, Arg, PropMap};
+use dbus::Message;
+use krunner::{
+	Action,
+	ActionInfo,
+	Config,
+	ImageData,
+	ImageFormat,
+	Match,
+	MatchFilter,
+	MatchIcon,
+	MatchType,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Act {
+	Copy,
+}
+
+impl Action for Act {
+	fn all() -> &'static [Self] {
+		&[Self::Copy]
+	}
+
+	fn from_id(s: &str) -> Option<Self> {
+		(s == "copy").then_some(Self::Copy)
+	}
+
+	fn to_id(&self) -> String {
+		"copy".to_owned()
+	}
+
+	fn info(&self) -> ActionInfo {
+		ActionInfo {
+			title: "Copy".to_owned(),
+			icon: "edit-copy".to_owned(),
+		}
+	}
+}
+
+fn marshal(value: &impl Append) -> Message {
+	Message::new_method_call("org.kde.krunner1.Test", "/", "org.kde.krunner1", "Test")
+		.unwrap()
+		.append1(value)
+}
+
+fn image() -> ImageData {
+	ImageData {
+		width: 1,
+		height: 1,
+		row_stride: 4,
+		has_alpha: true,
+		format: ImageFormat::Argb32,
+		data: vec![0xff, 0x00, 0x00, 0xff],
+	}
+}
+
+#[test]
+fn declared_signatures() {
+	assert_eq!(&*<Config<Act> as Arg>::signature(), "a{sv}");
+	assert_eq!(&*<Match<Act> as Arg>::signature(), "(sssida{sv})");
+	assert_eq!(&*<ImageData as Arg>::signature(), "(iiibiiay)");
+	assert_eq!(&*<MatchType as Arg>::signature(), "i");
+}
+
+#[test]
+fn marshalled_signatures() {
+	let mut config = Config::<Act>::default().min_letter_count(2).unwrap();
+	config.match_filter = Some(MatchFilter::Regex("^=".to_owned()));
+	assert_eq!(&*marshal(&config).iter_init().signature(), "a{sv}");
+	assert_eq!(
+		&*marshal(&Match::<Act>::default()).iter_init().signature(),
+		"(sssida{sv})"
+	);
+	assert_eq!(&*marshal(&image()).iter_init().signature(), "(iiibiiay)");
+	assert_eq!(
+		&*marshal(&MatchType::ExactMatch).iter_init().signature(),
+		"i"
+	);
+}
+
+#[test]
+fn config_round_trip() {
+	let mut config = Config::<Act>::default().min_letter_count(2).unwrap();
+	config.match_filter = Some(MatchFilter::Keywords(vec!["calc".to_owned()]));
+	let props: PropMap = marshal(&config).read1().unwrap();
+
+	assert_eq!(
+		prop_cast::<Vec<String>>(&props, "TriggerWords").unwrap(),
+		&["calc"]
+	);
+	assert_eq!(prop_cast::<u32>(&props, "MinLetterCount"), Some(&2));
+	assert!(props.contains_key("Actions"));
+	assert!(!props.contains_key("MatchRegex"));
+}
+
+#[test]
+fn config_keys() {
+	let keys = |config: &Config<Act>| {
+		let props: PropMap = marshal(config).read1().unwrap();
+		let mut keys: Vec<_> = props.into_keys().collect();
+		keys.sort_unstable();
+		keys
+	};
+
+	let mut config = Config::<Act>::default().min_letter_count(2).unwrap();
+	config.match_filter = Some(MatchFilter::Keywords(vec!["calc".to_owned()]));
+	assert_eq!(keys(&config), ["Actions", "MinLetterCount", "TriggerWords"]);
+
+	config.match_filter = Some(MatchFilter::Regex("^=".to_owned()));
+	assert_eq!(keys(&config), ["Actions", "MatchRegex", "MinLetterCount"]);
+	assert_eq!(keys(&Config::default()), ["Actions"]);
+}
+
+#[test]
+fn match_round_trip() {
+	let m = Match {
+		id: "id".to_owned(),
+		title: "Title".to_owned(),
+		subtitle: Some("Subtitle".to_owned()),
+		icon: MatchIcon::NameWithFallbackData {
+			name: "accessories-calculator".to_owned(),
+			data: image(),
+		},
+		ty: MatchType::ExactMatch,
+		relevance: 0.5,
+		urls: vec!["https://example.com".to_owned()],
+		category: Some("Category".to_owned()),
+		multiline: true,
+		replace_query: Some("query".to_owned()),
+		actions: vec![Act::Copy],
+	};
+	let (id, title, icon, ty, relevance, props): (String, String, String, i32, f64, PropMap) =
+		marshal(&m).read1().unwrap();
+
+	assert_eq!(id, "id");
+	assert_eq!(title, "Title");
+	assert_eq!(icon, "accessories-calculator");
+	assert_eq!(ty, MatchType::ExactMatch as i32);
+	assert!((relevance - 0.5).abs() < f64::EPSILON);
+
+	let mut keys: Vec<_> = props.keys().map(String::as_str).collect();
+	keys.sort_unstable();
+	assert_eq!(keys, [
+		"actions",
+		"category",
+		"icon-data",
+		"multiline",
+		"query",
+		"subtext",
+		"urls"
+	]);
+	assert_eq!(&*props["icon-data"].0.signature(), "(iiibiiay)");
+}
+
+#[test]
+fn default_match_has_no_properties() {
+	let (.., props): (String, String, String, i32, f64, PropMap) =
+		marshal(&Match::<Act>::default()).read1().unwrap();
+	assert!(props.is_empty());
+}
@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+
+use dbus::channel::Sender;
+use dbus::Message;
+use dbus_crossroads::Crossroads;
+use krunner::{Match, Runner, RunnerContext, RunnerExt, SharedRunner, TeardownPolicy};
+
+/// A runner that records what survives a teardown.
+#[derive(Default)]
+struct Tracker {
+	policy: TeardownPolicy,
+	config_calls: usize,
+	teardowns: usize,
+	cached_on_run: Option<bool>,
+}
+
+impl Tracker {
+	fn new(policy: TeardownPolicy) -> SharedRunner<Self> {
+		SharedRunner::new(Self {
+			policy,
+			..Self::default()
+		})
+	}
+}
+
+impl Runner for Tracker {
+	type Action = std::convert::Infallible;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		ctx.cache().insert(query.clone(), ());
+		Ok(vec![Match::builder(&query).title(query).build()])
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		_action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.cached_on_run = Some(ctx.cache().get::<()>(&match_id).is_some());
+		Ok(())
+	}
+
+	fn config(&mut self) -> Result<krunner::Config<Self::Action>, Self::Err> {
+		self.config_calls += 1;
+		Ok(krunner::Config::default())
+	}
+
+	fn teardown_policy(&self) -> TeardownPolicy {
+		self.policy
+	}
+
+	fn teardown(&mut self) -> Result<(), Self::Err> {
+		self.teardowns += 1;
+		Ok(())
+	}
+}
+
+/// Collects the replies sent by [`Crossroads`].
+#[derive(Default)]
+struct Replies(RefCell<Vec<Message>>);
+
+impl Sender for Replies {
+	fn send(&self, msg: Message) -> Result<u32, ()> {
+		self.0.borrow_mut().push(msg);
+		Ok(0)
+	}
+}
+
+/// Matches, tears down, and then runs the match and matches again.
+fn session(runner: SharedRunner<Tracker>) {
+	let mut cr = Crossroads::new();
+	let token = SharedRunner::<Tracker>::register(&mut cr);
+	cr.insert("/Tracker", &[token], runner);
+
+	let replies = Replies::default();
+	let mut call = |method: &str, args: &[&str]| {
+		let mut msg =
+			Message::new_method_call("some.runner.path", "/Tracker", "org.kde.krunner1", method)
+				.unwrap();
+		for arg in args {
+			msg = msg.append1(*arg);
+		}
+		msg.set_serial(1);
+		cr.handle_message(msg, &replies).unwrap();
+		let mut reply = replies.0.borrow_mut().pop().unwrap();
+		assert!(reply.as_result().is_ok(), "{method} failed");
+	};
+	call("Match", &["hello"]);
+	call("Teardown", &[]);
+	call("Run", &["hello", ""]);
+	call("Match", &["world"]);
+}
+
+#[test]
+fn keep_cache() {
+	let runner = Tracker::new(TeardownPolicy::KeepCache);
+	session(runner.clone());
+
+	let runner = runner.lock();
+	assert_eq!(runner.cached_on_run, Some(true));
+	assert_eq!(runner.config_calls, 1);
+	assert_eq!(runner.teardowns, 0);
+}
+
+#[test]
+fn evict_cache() {
+	let runner = Tracker::new(TeardownPolicy::EvictCache);
+	session(runner.clone());
+
+	let runner = runner.lock();
+	assert_eq!(runner.cached_on_run, Some(false));
+	assert_eq!(runner.config_calls, 2);
+	assert_eq!(runner.teardowns, 0);
+}
+
+#[test]
+fn custom() {
+	let runner = Tracker::new(TeardownPolicy::Custom);
+	session(runner.clone());
+
+	let runner = runner.lock();
+	assert_eq!(runner.cached_on_run, Some(false));
+	assert_eq!(runner.config_calls, 1);
+	assert_eq!(runner.teardowns, 1);
+}
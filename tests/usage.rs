@@ -0,0 +1,70 @@
+use std::fs;
+use std::time::Duration;
+
+use krunner::{Match, UsageTracker};
+
+#[test]
+fn multiplier_grows_with_runs() {
+	let usage = UsageTracker::new();
+	assert!((usage.multiplier("firefox") - 1.0).abs() < f64::EPSILON);
+
+	usage.record("firefox").unwrap();
+	let once = usage.multiplier("firefox");
+	usage.record("firefox").unwrap();
+	let twice = usage.multiplier("firefox");
+	assert!(1.0 < once && once < twice && twice < 2.0);
+
+	usage.forget("firefox").unwrap();
+	assert!(usage.score("firefox").abs() < f64::EPSILON);
+}
+
+#[test]
+fn apply_clamps_relevance() {
+	let usage = UsageTracker::new();
+	for _ in 0..10 {
+		usage.record("often").unwrap();
+	}
+
+	let mut matches: Vec<Match<std::convert::Infallible>> = vec![
+		Match::new("often").relevance(0.9),
+		Match::new("never").relevance(0.5),
+	];
+	usage.apply(&mut matches);
+	assert!((matches[0].relevance - 1.0).abs() < f64::EPSILON);
+	assert!((matches[1].relevance - 0.5).abs() < f64::EPSILON);
+}
+
+#[test]
+fn clones_share_history() {
+	let usage = UsageTracker::new();
+	let clone = usage.clone();
+	std::thread::spawn(move || clone.record("shared").unwrap())
+		.join()
+		.unwrap();
+	assert!(usage.score("shared") > 0.0);
+}
+
+#[test]
+fn persists_to_disk() {
+	let dir = std::env::temp_dir().join(format!("krunner-usage-{}", std::process::id()));
+	let path = dir.join("nested").join("runner.usage");
+
+	let usage = UsageTracker::load(&path)
+		.unwrap()
+		.with_half_life(Duration::from_secs(60 * 60));
+	usage.record("plain").unwrap();
+	usage.record("tab\tand\nnewline\\").unwrap();
+
+	let reloaded = UsageTracker::load(&path).unwrap();
+	assert!(reloaded.score("plain") > 0.9);
+	assert!(reloaded.score("tab\tand\nnewline\\") > 0.9);
+	assert!(reloaded.score("missing").abs() < f64::EPSILON);
+
+	fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_invalid_plugin_ids() {
+	let err = UsageTracker::load_for_plugin("../../escape").unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,555 @@
+#![cfg(feature = "zbus")]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use krunner::{
+	Action,
+	ActionInfo,
+	AsyncAdapter,
+	AsyncRunner,
+	Config,
+	Match,
+	MatchFilter,
+	Runner,
+	RunnerContext,
+	SharedRunner,
+	TeardownPolicy,
+	ZbusRunner,
+};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use zbus::connection::Builder;
+use zbus::zvariant::OwnedValue;
+use zbus::{Connection, Guid};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Act {
+	Copy,
+}
+
+impl Action for Act {
+	fn all() -> &'static [Self] {
+		&[Self::Copy]
+	}
+
+	fn from_id(s: &str) -> Option<Self> {
+		(s == "copy").then_some(Self::Copy)
+	}
+
+	fn to_id(&self) -> String {
+		"copy".to_owned()
+	}
+
+	fn info(&self) -> ActionInfo {
+		ActionInfo {
+			title: "Copy".to_owned(),
+			icon: "edit-copy".to_owned(),
+		}
+	}
+
+	fn info_dynamic(&self, ctx: &RunnerContext) -> ActionInfo {
+		ActionInfo {
+			icon: format!("edit-copy{}", ctx.path().replace('/', "-").to_lowercase()),
+			..self.info()
+		}
+	}
+}
+
+struct Echo;
+
+impl Runner for Echo {
+	type Action = Act;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Ok(vec![Match::new(&query)
+			.title(&query)
+			.subtitle("Echo".to_owned())
+			.icon("edit-copy")
+			.action(Act::Copy)])
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		_action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		assert_eq!(ctx.path(), "/Echo");
+		Err(format!("cannot run {match_id}"))
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		let mut config = Config::default();
+		config.match_filter = Some(MatchFilter::Keywords(vec!["echo".to_owned()]));
+		Ok(config)
+	}
+}
+
+/// An [`Echo`] runner that waits for the query to stop changing.
+struct Debounced;
+
+impl Runner for Debounced {
+	type Action = Act;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Echo.matches(query, ctx)
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Echo.run(match_id, action, ctx)
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		let mut config = Config::default();
+		config.debounce = Some(Duration::from_millis(100));
+		Ok(config)
+	}
+}
+
+/// A runner whose backend is missing.
+struct Disabled;
+
+impl Runner for Disabled {
+	type Action = Act;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		unreachable!("the runner is disabled")
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Echo.run(match_id, action, ctx)
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		let mut config = Config::default();
+		config.enabled = false;
+		Ok(config)
+	}
+}
+
+/// A runner with a bug.
+struct Panicking;
+
+impl Runner for Panicking {
+	type Action = Act;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		panic!("oops")
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Echo.run(match_id, action, ctx)
+	}
+}
+
+/// A runner without a config of its own, which it is never asked for twice.
+struct Unconfigured(Arc<AtomicUsize>);
+
+#[cfg_attr(not(feature = "native-async"), async_trait::async_trait)]
+impl AsyncRunner for Unconfigured {
+	type Action = Act;
+	type Err = String;
+
+	async fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		Ok(vec![])
+	}
+
+	async fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+
+	async fn config(&mut self) -> Result<Option<Config<Self::Action>>, Self::Err> {
+		self.0.fetch_add(1, Ordering::Relaxed);
+		Ok(None)
+	}
+
+	fn config_is_static(&self) -> bool {
+		true
+	}
+}
+
+/// A runner that streams some matches, and then never finishes.
+struct Streaming;
+
+#[cfg_attr(not(feature = "native-async"), async_trait::async_trait)]
+impl AsyncRunner for Streaming {
+	type Action = Act;
+	type Err = String;
+
+	async fn matches(
+		&mut self,
+		_query: String,
+		_ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		unreachable!("matches are streamed")
+	}
+
+	async fn matches_streaming(
+		&mut self,
+		query: String,
+		_ctx: &RunnerContext,
+		matches: mpsc::UnboundedSender<Match<Self::Action>>,
+	) -> Result<(), Self::Err> {
+		let _ = matches.send(Match::builder("early").title(&query).build());
+		tokio::spawn(async move {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+			let _ = matches.send(Match::builder("spawned").title(query).build());
+			std::future::pending::<()>().await;
+		});
+		std::future::pending().await
+	}
+
+	async fn run(
+		&mut self,
+		_match_id: String,
+		_action: Option<Self::Action>,
+		_ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		Ok(())
+	}
+
+	fn match_timeout(&self) -> Option<Duration> {
+		Some(Duration::from_millis(200))
+	}
+}
+
+/// A runner that records what survives a teardown.
+#[derive(Default)]
+struct Tracker {
+	policy: TeardownPolicy,
+	config_calls: usize,
+	teardowns: usize,
+	cached_on_run: Option<bool>,
+}
+
+impl Tracker {
+	fn new(policy: TeardownPolicy) -> SharedRunner<Self> {
+		SharedRunner::new(Self {
+			policy,
+			..Self::default()
+		})
+	}
+}
+
+impl Runner for Tracker {
+	type Action = Act;
+	type Err = String;
+
+	fn matches(
+		&mut self,
+		query: String,
+		ctx: &RunnerContext,
+	) -> Result<Vec<Match<Self::Action>>, Self::Err> {
+		ctx.cache().insert(query.clone(), ());
+		Ok(vec![Match::builder(&query).title(query).build()])
+	}
+
+	fn run(
+		&mut self,
+		match_id: String,
+		_action: Option<Self::Action>,
+		ctx: &RunnerContext,
+	) -> Result<(), Self::Err> {
+		self.cached_on_run = Some(ctx.cache().get::<()>(&match_id).is_some());
+		Ok(())
+	}
+
+	fn config(&mut self) -> Result<Config<Self::Action>, Self::Err> {
+		self.config_calls += 1;
+		Ok(Config::default())
+	}
+
+	fn teardown_policy(&self) -> TeardownPolicy {
+		self.policy
+	}
+
+	fn teardown(&mut self) -> Result<(), Self::Err> {
+		self.teardowns += 1;
+		Ok(())
+	}
+}
+
+type Properties = HashMap<String, OwnedValue>;
+
+/// Connects to `runner` served at `/Echo` over a socket pair.
+async fn connect<R: Runner + Send + 'static>(runner: R) -> (Connection, Connection)
+where
+	R::Action: Send,
+	R::Err: Send,
+{
+	connect_async(AsyncAdapter::new(runner)).await
+}
+
+/// Like [`connect`], but for asynchronous runners.
+async fn connect_async<R: AsyncRunner + Send + 'static>(runner: R) -> (Connection, Connection)
+where
+	R::Action: Send,
+{
+	let (a, b) = UnixStream::pair().unwrap();
+	let server = Builder::unix_stream(a)
+		.p2p()
+		.server(Guid::generate())
+		.unwrap()
+		.serve_at("/Echo", ZbusRunner::new(runner))
+		.unwrap()
+		.build();
+	let client = Builder::unix_stream(b).p2p().build();
+	let (server, client) = tokio::join!(server, client);
+	(server.unwrap(), client.unwrap())
+}
+
+macro_rules! call {
+	($c:expr, $method:literal, $body:expr) => {
+		$c.call_method(
+			None::<&str>,
+			"/Echo",
+			Some("org.kde.krunner1"),
+			$method,
+			$body,
+		)
+		.await
+	};
+}
+
+#[test]
+fn serves_runner() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let (_server, c) = connect(Echo).await;
+
+		let reply = call!(c, "Match", &("hello",)).unwrap();
+		let matches: Vec<(String, String, String, i32, f64, Properties)> =
+			reply.body().deserialize().unwrap();
+		let (id, title, icon, _, _, props) = &matches[0];
+		assert_eq!((id.as_str(), title.as_str()), ("hello", "hello"));
+		assert_eq!(icon, "edit-copy");
+		assert_eq!(String::try_from(props["subtext"].clone()).unwrap(), "Echo");
+		assert!(props.contains_key("actions"));
+
+		let reply = call!(c, "Actions", &()).unwrap();
+		let actions: Vec<(String, String, String)> = reply.body().deserialize().unwrap();
+		assert_eq!(actions, [(
+			"copy".to_owned(),
+			"Copy".to_owned(),
+			"edit-copy-echo".to_owned()
+		)]);
+
+		let reply = call!(c, "Config", &()).unwrap();
+		let config: Properties = reply.body().deserialize().unwrap();
+		assert!(config.contains_key("TriggerWords"));
+		assert!(config.contains_key("Actions"));
+
+		let err = call!(c, "Run", &("hello", "")).unwrap_err();
+		let zbus::Error::MethodError(name, description, _) = err else {
+			panic!("unexpected error: {err}");
+		};
+		assert_eq!(name.as_str(), "org.freedesktop.DBus.Error.Failed");
+		assert_eq!(description.as_deref(), Some("cannot run hello"));
+
+		let err = call!(c, "Run", &("hello", "paste")).unwrap_err();
+		let zbus::Error::MethodError(name, ..) = err else {
+			panic!("unexpected error: {err}");
+		};
+		assert_eq!(name.as_str(), "org.freedesktop.DBus.Error.InvalidArgs");
+	});
+}
+
+#[test]
+fn debounces_queries() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let (_server, c) = connect(Debounced).await;
+		// The debounce window is only known once the config has been read
+		call!(c, "Match", &("h",)).unwrap();
+
+		let (first, second) = tokio::join!(async { call!(c, "Match", &("he",)) }, async {
+			call!(c, "Match", &("hello",))
+		});
+		let first: Vec<(String, String, String, i32, f64, Properties)> =
+			first.unwrap().body().deserialize().unwrap();
+		let second: Vec<(String, String, String, i32, f64, Properties)> =
+			second.unwrap().body().deserialize().unwrap();
+		assert!(first.is_empty());
+		assert_eq!(second[0].0, "hello");
+	});
+}
+
+#[test]
+fn keeps_streamed_matches_on_timeout() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let (_server, c) = connect_async(Streaming).await;
+
+		let reply = call!(c, "Match", &("hello",)).unwrap();
+		let matches: Vec<(String, String, String, i32, f64, Properties)> =
+			reply.body().deserialize().unwrap();
+		let ids: Vec<_> = matches.iter().map(|m| m.0.as_str()).collect();
+		assert_eq!(ids, ["early", "spawned"]);
+	});
+}
+
+#[test]
+fn skips_disabled_runners() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let (_server, c) = connect(Disabled).await;
+
+		let reply = call!(c, "Match", &("hello",)).unwrap();
+		let matches: Vec<(String, String, String, i32, f64, Properties)> =
+			reply.body().deserialize().unwrap();
+		assert!(matches.is_empty());
+	});
+}
+
+#[test]
+fn caches_default_config() {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let calls = Arc::new(AtomicUsize::new(0));
+		let (_server, c) = connect_async(Unconfigured(Arc::clone(&calls))).await;
+
+		call!(c, "Config", &()).unwrap();
+		call!(c, "Config", &()).unwrap();
+		call!(c, "Match", &("hello",)).unwrap();
+		assert_eq!(calls.load(Ordering::Relaxed), 1);
+	});
+}
+
+#[test]
+fn replies_to_panics() {
+	krunner::install_panic_hook();
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let (_server, c) = connect(Panicking).await;
+
+		let err = call!(c, "Match", &("hello",)).unwrap_err();
+		let zbus::Error::MethodError(name, description, _) = err else {
+			panic!("unexpected error: {err}");
+		};
+		assert_eq!(name.as_str(), "org.freedesktop.DBus.Error.Failed");
+		let description = description.unwrap();
+		assert!(description.starts_with("runner panicked at tests/zbus.rs:"));
+		assert!(description.ends_with(": oops"));
+	});
+}
+
+/// Matches, tears down, and then runs the match and matches again.
+fn teardown_session(runner: SharedRunner<Tracker>) {
+	let rt = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap();
+
+	rt.block_on(async {
+		let (_server, c) = connect(runner).await;
+
+		call!(c, "Match", &("hello",)).unwrap();
+		call!(c, "Teardown", &()).unwrap();
+		call!(c, "Run", &("hello", "")).unwrap();
+		call!(c, "Match", &("world",)).unwrap();
+	});
+}
+
+#[test]
+fn teardown_keeps_cache() {
+	let runner = Tracker::new(TeardownPolicy::KeepCache);
+	teardown_session(runner.clone());
+
+	let runner = runner.lock();
+	assert_eq!(runner.cached_on_run, Some(true));
+	assert_eq!(runner.config_calls, 1);
+	assert_eq!(runner.teardowns, 0);
+}
+
+#[test]
+fn teardown_evicts_cache() {
+	let runner = Tracker::new(TeardownPolicy::EvictCache);
+	teardown_session(runner.clone());
+
+	let runner = runner.lock();
+	assert_eq!(runner.cached_on_run, Some(false));
+	assert_eq!(runner.config_calls, 2);
+	assert_eq!(runner.teardowns, 0);
+}
+
+#[test]
+fn teardown_calls_runner() {
+	let runner = Tracker::new(TeardownPolicy::Custom);
+	teardown_session(runner.clone());
+
+	let runner = runner.lock();
+	assert_eq!(runner.cached_on_run, Some(false));
+	assert_eq!(runner.config_calls, 1);
+	assert_eq!(runner.teardowns, 1);
+}